@@ -0,0 +1,158 @@
+use sim_common::trajectory::Trajectory;
+use std::error::Error;
+use std::f64::consts::PI;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CycleDetection {
+    ZeroCrossings, //upward crossings of zero, linearly interpolated
+    Peaks, //local maxima, refined with a parabola through three samples
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct OscillationSummary {
+    pub periods : Vec<f64>, //one per detected cycle
+    pub mean_period : f64,
+    pub envelope : Vec<(f64, f64)>, //(time, amplitude) of every positive peak
+    pub logarithmic_decrement : f64, //ln of the ratio of successive peak amplitudes
+    pub decay_rate : f64, //1/s, amplitude ~ exp(-decay_rate * t)
+    pub damping_ratio : f64,
+    pub quality_factor : f64, //infinite without damping
+}
+
+pub fn analyze_oscillation(trajectory : &Trajectory, name : &str, detection : CycleDetection) -> Result<OscillationSummary, Box<dyn Error>> {
+    /*
+    measures period and damping of an oscillating series, e.g. the pendulum theta
+    the logarithmic decrement comes from a least squares line through ln(amplitude) per cycle,
+    the damping ratio from zeta = delta / sqrt(4 pi^2 + delta^2) and the quality factor from Q = 1 / (2 zeta)
+     */
+    let values = trajectory.series(name).ok_or(format!("trajectory has no series '{}'", name))?;
+    let times = &trajectory.times;
+
+    let envelope = find_peaks(times, &values);
+    let cycle_times : Vec<f64> = match detection {
+        CycleDetection::ZeroCrossings => upward_zero_crossings(times, &values),
+        CycleDetection::Peaks => envelope.iter().map(|peak| peak.0).collect(),
+    };
+    if cycle_times.len() < 2 || envelope.len() < 2 {
+        return Err(format!("series '{}' does not complete a full oscillation", name).into());
+    }
+    let periods : Vec<f64> = cycle_times.windows(2).map(|w| w[1] - w[0]).collect();
+    let mean_period = periods.iter().sum::<f64>() / periods.len() as f64;
+
+    //slope of ln(amplitude) against the peak index
+    let log_amplitudes : Vec<(f64, f64)> = envelope.iter().enumerate()
+        .map(|(k, peak)| (k as f64, peak.1.ln()))
+        .collect();
+    let logarithmic_decrement = -fit_slope(&log_amplitudes);
+    let decay_rate = logarithmic_decrement / mean_period;
+    let damping_ratio = logarithmic_decrement / (4.0 * PI * PI + logarithmic_decrement.powi(2)).sqrt();
+    let quality_factor = 1.0 / (2.0 * damping_ratio);
+
+    Ok(OscillationSummary {periods, mean_period, envelope, logarithmic_decrement, decay_rate, damping_ratio, quality_factor})
+}
+
+fn find_peaks(times : &[f64], values : &[f64]) -> Vec<(f64, f64)> {
+    //positive local maxima as (time, value)
+    (1..values.len().saturating_sub(1))
+        .filter(|&i| values[i] > 0.0 && values[i] > values[i - 1] && values[i] >= values[i + 1])
+        .map(|i| {
+            let (y0, y1, y2) = (values[i - 1], values[i], values[i + 1]);
+            let denominator = y0 - 2.0 * y1 + y2;
+            if denominator == 0.0 {
+                return (times[i], y1);
+            }
+            let offset = 0.5 * (y0 - y2) / denominator;
+            let dt = 0.5 * (times[i + 1] - times[i - 1]);
+            (times[i] + offset * dt, y1 - 0.25 * (y0 - y2) * offset)
+        })
+        .collect()
+}
+
+fn upward_zero_crossings(times : &[f64], values : &[f64]) -> Vec<f64> {
+    (1..values.len())
+        .filter(|&i| values[i - 1] < 0.0 && values[i] >= 0.0)
+        .map(|i| times[i - 1] + (times[i] - times[i - 1]) * values[i - 1] / (values[i - 1] - values[i]))
+        .collect()
+}
+
+fn fit_slope(points : &[(f64, f64)]) -> f64 {
+    //least squares slope of y against x
+    let n = points.len() as f64;
+    let mean_x = points.iter().map(|p| p.0).sum::<f64>() / n;
+    let mean_y = points.iter().map(|p| p.1).sum::<f64>() / n;
+    let covariance : f64 = points.iter().map(|p| (p.0 - mean_x) * (p.1 - mean_y)).sum();
+    let variance : f64 = points.iter().map(|p| (p.0 - mean_x).powi(2)).sum();
+    covariance / variance
+}
+
+#[cfg(test)]
+fn simulate_rk4(l : f64, g : f64, b : f64, theta0 : f64, steps : usize) -> Trajectory {
+    use crate::math::make_propogate_rk4;
+    use sim_common::trajectory::Metadata;
+    let dt = 0.005;
+    let propogate = make_propogate_rk4(l, g, b, dt);
+    let mut trajectory = Trajectory::new(Metadata::new("pendulum", "rk4", dt), &["theta", "d_theta"]);
+    let mut x = vec![theta0, 0.0];
+    for i in 0..steps {
+        trajectory.push(i as f64 * dt, &x);
+        x = propogate(i as f64 * dt, &x);
+    }
+    trajectory
+}
+
+#[test]
+fn test_damped_small_oscillation() {
+    //small angles behave like a damped harmonic oscillator with omega0 = sqrt(g/l), 2 zeta omega0 = b
+    let (l, g, b) = (1.0, 9.81, 0.3);
+    let trajectory = simulate_rk4(l, g, b, 0.05, 4000);
+    let omega0 = (g / l).sqrt();
+    let zeta = b / (2.0 * omega0);
+    let expected_period = 2.0 * PI / (omega0 * (1.0 - zeta * zeta).sqrt());
+
+    for detection in [CycleDetection::ZeroCrossings, CycleDetection::Peaks] {
+        let summary = analyze_oscillation(&trajectory, "theta", detection).unwrap();
+        assert!((summary.mean_period - expected_period).abs() < 1e-3 * expected_period);
+        assert!((summary.damping_ratio - zeta).abs() < 0.01 * zeta);
+        assert!((summary.decay_rate - b / 2.0).abs() < 0.01 * b);
+        assert!((summary.quality_factor - 1.0 / (2.0 * zeta)).abs() < 0.02 / (2.0 * zeta));
+    }
+}
+
+#[test]
+fn test_large_amplitude_period_is_longer() {
+    //the undamped period grows with amplitude, 90 degrees is about 18% longer than 2 pi sqrt(l/g)
+    let (l, g) = (1.0, 9.81);
+    let trajectory = simulate_rk4(l, g, 0.0, PI / 2.0, 4000);
+    let summary = analyze_oscillation(&trajectory, "theta", CycleDetection::ZeroCrossings).unwrap();
+    let small_angle_period = 2.0 * PI * (l / g).sqrt();
+    assert!((summary.mean_period / small_angle_period - 1.18034).abs() < 1e-3);
+    assert!(summary.damping_ratio.abs() < 1e-4);
+}
+
+#[test]
+fn test_no_oscillation() {
+    let trajectory = simulate_rk4(1.0, 9.81, 0.0, 0.0, 100);
+    assert!(analyze_oscillation(&trajectory, "theta", CycleDetection::Peaks).is_err());
+}
+
+#[test]
+fn test_pendulum_frequency() {
+    //small oscillations of an undamped pendulum are at sqrt(g/l)/(2 pi)
+    use crate::math::make_propogate_rk4;
+    use sim_common::spectrum::{trajectory_spectrum, Window};
+    use sim_common::trajectory::Metadata;
+    let (l, g, dt) = (2.0, 9.81, 0.01);
+    let propogate = make_propogate_rk4(l, g, 0.0, dt);
+    let mut trajectory = Trajectory::new(Metadata::new("pendulum", "rk4", dt), &["theta", "d_theta"]);
+    let mut x = vec![0.05, 0.0];
+    for i in 0..6000 {
+        trajectory.push(i as f64 * dt, &x);
+        x = propogate(i as f64 * dt, &x);
+    }
+    let spectrum = trajectory_spectrum(&trajectory, "theta", None, Window::Hann).unwrap();
+    let expected = (g / l).sqrt() / (2.0 * PI);
+    assert!((spectrum.dominant_frequency().unwrap() - expected).abs() < 0.005);
+
+    let resampled = trajectory_spectrum(&trajectory, "theta", Some(0.05), Window::Hann).unwrap();
+    assert!((resampled.dominant_frequency().unwrap() - expected).abs() < 0.005);
+}
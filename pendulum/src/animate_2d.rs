@@ -0,0 +1,99 @@
+use sim_common::math::lerp1d;
+use sim_common::trajectory::Trajectory;
+use plotters::prelude::*;
+use std::error::Error;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct GifOptions {
+    pub path : String,
+    pub size : (u32, u32),
+    pub fps : u32, //1 to 100, gif delays are in hundredths of a second
+    pub duration : Option<f64>, //seconds of simulated time to animate, None for the whole trajectory
+    pub trail : Option<f64>, //seconds of bob history drawn as a fading trail, None for no trail
+}
+
+impl Default for GifOptions {
+    fn default() -> Self {
+        GifOptions {path : "pendulum.gif".to_string(), size : (480, 480), fps : 25, duration : None, trail : Some(1.0)}
+    }
+}
+
+pub fn animate_gif(trajectory : &Trajectory, options : &GifOptions) -> Result<(), Box<dyn Error>> {
+    /*
+    draws the pivot, rod and bob frame by frame into an animated gif, no gpu needed
+    the string length is taken from the trajectory metadata, the animation runs in real time
+     */
+    let l = *trajectory.metadata.parameters.get("l").ok_or("trajectory metadata has no parameter 'l'")?;
+    let theta_vec = trajectory.series("theta").ok_or("trajectory has no 'theta' state")?;
+    if trajectory.is_empty() {
+        return Err("trajectory has no samples to animate".into());
+    }
+    //gif frame delays are whole hundredths of a second
+    if options.fps == 0 || options.fps > 100 {
+        return Err(format!("fps must be between 1 and 100, got {}", options.fps).into());
+    }
+    let times = &trajectory.times;
+    let t_start = times[0];
+    let t_end = match options.duration {
+        Some(duration) => (t_start + duration).min(times[times.len() - 1]),
+        None => times[times.len() - 1],
+    };
+    let n_frames = ((t_end - t_start) * options.fps as f64).floor() as usize + 1;
+    let bob = |theta : f64| (l * theta.sin(), -l * theta.cos());
+
+    //keep the aspect ratio so the pendulum is not distorted
+    let (width, height) = options.size;
+    let half_x = 1.2 * l * (width as f64 / height as f64).max(1.0);
+    let half_y = 1.2 * l * (height as f64 / width as f64).max(1.0);
+
+    let root = BitMapBackend::gif(&options.path, options.size, 1000 / options.fps)?.into_drawing_area();
+    for frame in 0..n_frames {
+        let t = t_start + frame as f64 / options.fps as f64;
+        root.fill(&WHITE)?;
+        let mut chart = ChartBuilder::on(&root)
+            .caption(format!("t = {:.2} s", t), ("sans-serif", 20).into_font())
+            .margin(10)
+            .build_cartesian_2d(-half_x..half_x, -half_y..half_y)?;
+
+        if let Some(trail) = options.trail {
+            //older samples fade out towards the start of the trail
+            let start = times.partition_point(|&s| s < t - trail);
+            let end = times.partition_point(|&s| s <= t);
+            for i in start.max(1)..end {
+                let age = (t - times[i]) / trail;
+                let alpha = (1.0 - age).clamp(0.0, 1.0);
+                chart.draw_series(std::iter::once(PathElement::new(
+                    vec![bob(theta_vec[i - 1]), bob(theta_vec[i])], RED.mix(alpha).stroke_width(2))))?;
+            }
+        }
+
+        let position = bob(lerp1d(t, times, &theta_vec));
+        chart.draw_series(std::iter::once(PathElement::new(vec![(0.0, 0.0), position], BLACK.stroke_width(3))))?;
+        chart.draw_series(std::iter::once(Circle::new((0.0, 0.0), 5, BLACK.filled())))?;
+        chart.draw_series(std::iter::once(Circle::new(position, 12, RED.filled())))?;
+        root.present()?;
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_animate_gif() {
+    use sim_common::trajectory::Metadata;
+    let mut trajectory = Trajectory::new(Metadata::new("pendulum", "rk4", 0.05).with_parameter("l", 1.0), &["theta", "d_theta"]);
+    for i in 0..20 {
+        let t = 0.05 * i as f64;
+        trajectory.push(t, &[t.cos(), -t.sin()]);
+    }
+    let path = std::env::temp_dir().join(format!("pendulum_animation_{}.gif", std::process::id()));
+    let options = GifOptions {path : path.to_str().unwrap().to_string(), size : (64, 48), fps : 10, ..Default::default()};
+    animate_gif(&trajectory, &options).unwrap();
+    assert!(std::fs::metadata(&options.path).unwrap().len() > 0);
+    std::fs::remove_file(&options.path).unwrap();
+
+    for fps in [0, 101, 2000] {
+        assert!(animate_gif(&trajectory, &GifOptions {fps, ..options.clone()}).is_err());
+    }
+    trajectory.metadata.parameters.clear();
+    assert!(animate_gif(&trajectory, &options).is_err());
+}
@@ -1,14 +1,16 @@
 use three_d::*;
-use crate::math::lerp1d;
+use sim_common::math::lerp1d;
+use sim_common::trajectory::Trajectory;
+use std::error::Error;
 use std::sync::Arc;
 
-pub fn draw_3d(time_vec : &Vec<f64> ,theta_vec: &Vec<f64>, string_length : f32) {
+pub fn draw_3d(time_vec : &[f64] ,theta_vec: &[f64], string_length : f32) {
     //vectors passed by reference so we dont take ownership of them
     //vectors are cloned so we can move them into the closure in set_animation
     let r = string_length; //shorter syntax
      // Now we just clone Arc references, which is cheap
-     let time_vec_arc = Arc::new(time_vec.clone());
-     let theta_vec_arc = Arc::new(theta_vec.clone());
+     let time_vec_arc = Arc::new(time_vec.to_vec());
+     let theta_vec_arc = Arc::new(theta_vec.to_vec());
 
     let window = Window::new(WindowSettings {
         title: "Pendulum".to_string(),
@@ -110,3 +112,17 @@ pub fn draw_3d(time_vec : &Vec<f64> ,theta_vec: &Vec<f64>, string_length : f32)
         FrameOutput::default()
     });
 }
+
+pub fn replay_3d(trajectory : &Trajectory) -> Result<(), Box<dyn Error>> {
+    //plays back a recorded trajectory, the string length is taken from its metadata
+    if trajectory.metadata.model != "pendulum" {
+        return Err(format!("expected a pendulum trajectory, got '{}'", trajectory.metadata.model).into());
+    }
+    let l = *trajectory.metadata.parameters.get("l").ok_or("trajectory metadata has no parameter 'l'")?;
+    let theta_vec = trajectory.series("theta").ok_or("trajectory has no 'theta' state")?;
+    if trajectory.is_empty() {
+        return Err("trajectory has no samples to replay".into());
+    }
+    draw_3d(&trajectory.times, &theta_vec, l as f32);
+    Ok(())
+}
@@ -0,0 +1,421 @@
+use crate::math::{make_propogate_rk4, solve_linear};
+use sim_common::plot_2d::{auto_range, PlotBackend, PlotOptions};
+use plotters::coord::Shift;
+use plotters::prelude::*;
+use std::error::Error;
+use std::fmt;
+use std::fs;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FitParameter {
+    L,
+    G,
+    B,
+    Theta0, //angle at the first measurement
+    DTheta0, //angular velocity at the first measurement
+}
+
+impl FitParameter {
+    pub fn name(&self) -> &'static str {
+        match self {
+            FitParameter::L => "l",
+            FitParameter::G => "g",
+            FitParameter::B => "b",
+            FitParameter::Theta0 => "theta0",
+            FitParameter::DTheta0 => "d_theta0",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PendulumParameters {
+    pub l : f64,
+    pub g : f64,
+    pub b : f64,
+    pub theta0 : f64,
+    pub d_theta0 : f64,
+}
+
+impl Default for PendulumParameters {
+    fn default() -> Self {
+        PendulumParameters {l : 1.0, g : 9.81, b : 0.0, theta0 : 0.0, d_theta0 : 0.0}
+    }
+}
+
+impl PendulumParameters {
+    pub fn get(&self, parameter : FitParameter) -> f64 {
+        match parameter {
+            FitParameter::L => self.l,
+            FitParameter::G => self.g,
+            FitParameter::B => self.b,
+            FitParameter::Theta0 => self.theta0,
+            FitParameter::DTheta0 => self.d_theta0,
+        }
+    }
+
+    pub fn set(&mut self, parameter : FitParameter, value : f64) {
+        match parameter {
+            FitParameter::L => self.l = value,
+            FitParameter::G => self.g = value,
+            FitParameter::B => self.b = value,
+            FitParameter::Theta0 => self.theta0 = value,
+            FitParameter::DTheta0 => self.d_theta0 = value,
+        }
+    }
+
+    fn is_physical(&self) -> bool {
+        self.l > 0.0 && self.g > 0.0 && self.b >= 0.0
+            && [self.l, self.g, self.b, self.theta0, self.d_theta0].iter().all(|v| v.is_finite())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct FitOptions {
+    pub initial : PendulumParameters, //starting guess, parameters not fitted stay fixed at these values
+    pub fit : Vec<FitParameter>, //angle data alone only determines g/l, so fit l or g but not both
+    pub max_iterations : usize,
+    pub tolerance : f64, //stop when the relative drop of the squared residual sum is below this
+    pub max_step : f64, //longest rk4 step between measurements (s)
+}
+
+impl Default for FitOptions {
+    fn default() -> Self {
+        FitOptions {
+            initial : PendulumParameters::default(),
+            fit : vec![FitParameter::L, FitParameter::B, FitParameter::Theta0, FitParameter::DTheta0],
+            max_iterations : 100,
+            tolerance : 1e-10,
+            max_step : 0.01,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FitStop {
+    Converged, //the squared residual sum stopped dropping
+    Stalled, //no step lowered the cost even with the largest damping
+    MaxIterations,
+}
+
+impl fmt::Display for FitStop {
+    fn fmt(&self, f : &mut fmt::Formatter) -> fmt::Result {
+        let description = match self {
+            FitStop::Converged => "converged",
+            FitStop::Stalled => "stalled, no step lowered the residuals",
+            FitStop::MaxIterations => "did not converge within the iteration limit",
+        };
+        write!(f, "{}", description)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct FitResult {
+    pub parameters : PendulumParameters,
+    pub uncertainties : Vec<(FitParameter, f64)>, //one standard error per fitted parameter
+    pub fitted : Vec<f64>, //simulated angle at the measurement times
+    pub residuals : Vec<f64>, //measured minus fitted angle
+    pub rms : f64,
+    pub iterations : usize,
+    pub stop : FitStop,
+}
+
+impl FitResult {
+    pub fn converged(&self) -> bool {
+        self.stop == FitStop::Converged
+    }
+}
+
+pub fn read_angle_csv(filename : &str) -> Result<(Vec<f64>, Vec<f64>), Box<dyn Error>> {
+    /*
+    reads time stamped angle measurements, the first column is the time and the second the angle
+    lines starting with # and a non numeric header line are skipped, extra columns are ignored
+     */
+    let text = fs::read_to_string(filename)?;
+    let mut times = Vec::new();
+    let mut angles = Vec::new();
+    for (number, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let fields : Vec<&str> = line.split([',', ';', '\t']).map(|field| field.trim()).collect();
+        if fields.len() < 2 {
+            return Err(format!("line {}: expected a time and an angle", number + 1).into());
+        }
+        match (fields[0].parse::<f64>(), fields[1].parse::<f64>()) {
+            (Ok(t), Ok(theta)) => {
+                times.push(t);
+                angles.push(theta);
+            },
+            _ if times.is_empty() => continue, //header
+            _ => return Err(format!("line {}: could not parse '{}'", number + 1, line).into()),
+        }
+    }
+    if times.windows(2).any(|w| w[1] <= w[0]) {
+        return Err("measurement times must be strictly increasing".into());
+    }
+    Ok((times, angles))
+}
+
+pub fn simulate_angles(parameters : &PendulumParameters, times : &[f64], max_step : f64) -> Result<Vec<f64>, Box<dyn Error>> {
+    //rk4 from (theta0, d_theta0) at times[0], with every gap split into equal steps of at most max_step
+    if !(max_step.is_finite() && max_step > 0.0) {
+        return Err(format!("max_step must be positive, got {}", max_step).into());
+    }
+    Ok(integrate_angles(parameters, times, max_step))
+}
+
+fn integrate_angles(parameters : &PendulumParameters, times : &[f64], max_step : f64) -> Vec<f64> {
+    let mut x = vec![parameters.theta0, parameters.d_theta0];
+    let mut angles = Vec::with_capacity(times.len());
+    for (i, t) in times.iter().enumerate() {
+        if i > 0 {
+            let gap = t - times[i - 1];
+            let steps = (gap / max_step).ceil().max(1.0) as usize;
+            let dt = gap / steps as f64;
+            let propogate = make_propogate_rk4(parameters.l, parameters.g, parameters.b, dt);
+            for k in 0..steps {
+                x = propogate(times[i - 1] + k as f64 * dt, &x);
+            }
+        }
+        angles.push(x[0]);
+    }
+    angles
+}
+
+pub fn fit_pendulum(times : &[f64], angles : &[f64], options : &FitOptions) -> Result<FitResult, Box<dyn Error>> {
+    /*
+    levenberg-marquardt on the squared angle residuals, the jacobian is taken by forward differences of the simulation
+    standard errors come from the covariance s^2 (J^T J)^-1 with s^2 = sum(r^2) / (measurements - fitted parameters)
+     */
+    let n = times.len();
+    let p = options.fit.len();
+    if n != angles.len() {
+        return Err(format!("got {} times for {} angles", n, angles.len()).into());
+    }
+    if p == 0 {
+        return Err("no parameters to fit".into());
+    }
+    if n <= p {
+        return Err(format!("{} measurements are not enough to fit {} parameters", n, p).into());
+    }
+    if options.fit.contains(&FitParameter::L) && options.fit.contains(&FitParameter::G) {
+        return Err("l and g cannot both be fitted from angles, only g/l is observable".into());
+    }
+    if !options.initial.is_physical() {
+        return Err("the initial guess needs l > 0, g > 0 and b >= 0".into());
+    }
+    if !(options.max_step.is_finite() && options.max_step > 0.0) {
+        return Err(format!("max_step must be positive, got {}", options.max_step).into());
+    }
+
+    let residuals_of = |parameters : &PendulumParameters| -> Vec<f64> {
+        integrate_angles(parameters, times, options.max_step).iter().zip(angles).map(|(fit, data)| data - fit).collect()
+    };
+    let cost_of = |residuals : &[f64]| residuals.iter().map(|r| r * r).sum::<f64>();
+    let jacobian_of = |parameters : &PendulumParameters, residuals : &[f64]| -> Vec<Vec<f64>> {
+        //columns of d(fit)/d(parameter), i.e. minus the residual derivative
+        options.fit.iter().map(|&parameter| {
+            let value = parameters.get(parameter);
+            let h = 1e-6 * value.abs().max(1e-2);
+            let mut shifted = *parameters;
+            shifted.set(parameter, value + h);
+            residuals_of(&shifted).iter().zip(residuals).map(|(r_h, r)| (r - r_h) / h).collect()
+        }).collect()
+    };
+    //normal equations J^T J and J^T r from the column major jacobian
+    let normal_equations = |jacobian : &[Vec<f64>], residuals : &[f64]| {
+        let jtj : Vec<Vec<f64>> = jacobian.iter()
+            .map(|a| jacobian.iter().map(|b| a.iter().zip(b).map(|(x, y)| x * y).sum()).collect())
+            .collect();
+        let jtr : Vec<f64> = jacobian.iter().map(|a| a.iter().zip(residuals).map(|(x, y)| x * y).sum()).collect();
+        (jtj, jtr)
+    };
+
+    let mut parameters = options.initial;
+    let mut residuals = residuals_of(&parameters);
+    let mut cost = cost_of(&residuals);
+    let mut lambda = 1e-3;
+    let mut stop = FitStop::MaxIterations;
+    let mut iterations = 0;
+
+    while iterations < options.max_iterations && stop == FitStop::MaxIterations {
+        iterations += 1;
+        let jacobian = jacobian_of(&parameters, &residuals);
+        let (jtj, jtr) = normal_equations(&jacobian, &residuals);
+        //raise the damping until a step lowers the cost
+        loop {
+            let damped : Vec<Vec<f64>> = jtj.iter().enumerate()
+                .map(|(i, row)| row.iter().enumerate().map(|(j, v)| if i == j {v + lambda * v.max(1e-12)} else {*v}).collect())
+                .collect();
+            let step = solve_linear(&damped, &jtr);
+            let mut candidate = parameters;
+            if let Some(step) = &step {
+                for (parameter, delta) in options.fit.iter().zip(step) {
+                    candidate.set(*parameter, parameters.get(*parameter) + delta);
+                }
+            }
+            if step.is_some() && candidate.is_physical() {
+                let candidate_residuals = residuals_of(&candidate);
+                let candidate_cost = cost_of(&candidate_residuals);
+                if candidate_cost <= cost {
+                    if cost - candidate_cost <= options.tolerance * cost.max(f64::MIN_POSITIVE) {
+                        stop = FitStop::Converged;
+                    }
+                    parameters = candidate;
+                    residuals = candidate_residuals;
+                    cost = candidate_cost;
+                    lambda = (lambda / 10.0).max(1e-12);
+                    break;
+                }
+            }
+            lambda *= 10.0;
+            if lambda > 1e12 {
+                //no step improves the fit, which does not show that the fit is at a minimum
+                stop = FitStop::Stalled;
+                break;
+            }
+        }
+    }
+
+    let jacobian = jacobian_of(&parameters, &residuals);
+    let (jtj, _) = normal_equations(&jacobian, &residuals);
+    let variance = cost / (n - p) as f64;
+    let mut uncertainties = Vec::with_capacity(p);
+    for (i, parameter) in options.fit.iter().enumerate() {
+        let mut unit = vec![0.0; p];
+        unit[i] = 1.0;
+        let column = solve_linear(&jtj, &unit).ok_or("the fitted parameters are not identifiable from the data")?;
+        uncertainties.push((*parameter, (variance * column[i]).max(0.0).sqrt()));
+    }
+
+    let fitted : Vec<f64> = angles.iter().zip(&residuals).map(|(data, r)| data - r).collect();
+    let rms = (cost / n as f64).sqrt();
+    Ok(FitResult {parameters, uncertainties, fitted, residuals, rms, iterations, stop})
+}
+
+pub fn plot_fit(times : &[f64], angles : &[f64], result : &FitResult, options : &PlotOptions) -> Result<(), Box<dyn Error>> {
+    //measurements as dots with the fitted simulation on top, residuals in a panel below
+    if times.len() != angles.len() || times.len() != result.fitted.len() {
+        return Err("times, angles and the fit must have the same length".into());
+    }
+    if times.is_empty() {
+        return Err("no measurements to plot".into());
+    }
+    match options.backend()? {
+        PlotBackend::Svg => {
+            let root = SVGBackend::new(&options.path, options.size).into_drawing_area();
+            draw_fit(&root, times, angles, result, options)
+        },
+        PlotBackend::Png => {
+            let root = BitMapBackend::new(&options.path, options.size).into_drawing_area();
+            draw_fit(&root, times, angles, result, options)
+        },
+    }
+}
+
+fn draw_fit<DB : DrawingBackend>(root : &DrawingArea<DB, Shift>, times : &[f64], angles : &[f64], result : &FitResult, options : &PlotOptions) -> Result<(), Box<dyn Error>>
+where DB::ErrorType : 'static {
+    root.fill(&WHITE)?;
+    let root = root.titled(&options.title, ("sans-serif", 30).into_font())?;
+    let (upper, lower) = root.split_vertically(root.dim_in_pixel().1 * 2 / 3);
+
+    let x_range = options.x_range.unwrap_or((times[0], times[times.len() - 1]));
+    let y_range = options.y_range.unwrap_or_else(|| auto_range(angles.iter().chain(&result.fitted).copied()));
+    let mut chart = ChartBuilder::on(&upper)
+        .margin(5)
+        .x_label_area_size(15)
+        .y_label_area_size(50)
+        .build_cartesian_2d(x_range.0..x_range.1, y_range.0..y_range.1)?;
+    chart.configure_mesh().y_desc(&options.y_label).draw()?;
+
+    let data_style = options.line_styles.first().copied().unwrap_or_default();
+    let data_color = data_style.color.unwrap_or(BLUE);
+    chart.draw_series(times.iter().zip(angles).map(|(t, theta)| Circle::new((*t, *theta), 2, data_color.filled())))?
+        .label("data")
+        .legend(move |(x, y)| Circle::new((x + 10, y), 3, data_color.filled()));
+
+    let fit_style = options.line_styles.get(1).copied().unwrap_or_default();
+    let fit_color = fit_style.color.unwrap_or(RED).stroke_width(fit_style.width);
+    chart.draw_series(LineSeries::new(times.iter().copied().zip(result.fitted.iter().copied()), fit_color))?
+        .label("fit")
+        .legend(move |(x, y)| PathElement::new(vec![(x, y), (x + 20, y)], fit_color));
+
+    chart.configure_series_labels()
+        .position(SeriesLabelPosition::UpperRight)
+        .border_style(BLACK)
+        .background_style(WHITE.mix(0.8))
+        .draw()?;
+
+    let residual_range = auto_range(result.residuals.iter().copied());
+    let mut residual_chart = ChartBuilder::on(&lower)
+        .margin(5)
+        .x_label_area_size(30)
+        .y_label_area_size(50)
+        .build_cartesian_2d(x_range.0..x_range.1, residual_range.0..residual_range.1)?;
+    residual_chart.configure_mesh().x_desc(&options.x_label).y_desc("Residual (rad)").draw()?;
+    residual_chart.draw_series(LineSeries::new(vec![(x_range.0, 0.0), (x_range.1, 0.0)], BLACK.stroke_width(1)))?;
+    residual_chart.draw_series(times.iter().zip(&result.residuals).map(|(t, r)| Circle::new((*t, *r), 2, data_color.filled())))?;
+
+    root.present()?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+fn synthetic_measurements(truth : &PendulumParameters, n : usize, dt : f64, noise : f64) -> (Vec<f64>, Vec<f64>) {
+    //deterministic pseudo noise so the test does not need a random number generator
+    let times : Vec<f64> = (0..n).map(|i| i as f64 * dt).collect();
+    let angles = simulate_angles(truth, &times, 0.005).unwrap().iter().enumerate()
+        .map(|(i, theta)| theta + noise * (12.9898 * i as f64).sin())
+        .collect();
+    (times, angles)
+}
+
+#[test]
+fn test_fit_recovers_parameters() {
+    let truth = PendulumParameters {l : 2.0, g : 9.81, b : 0.5, theta0 : 1.2, d_theta0 : 0.3};
+    let (times, angles) = synthetic_measurements(&truth, 300, 0.03, 0.01);
+    let options = FitOptions {
+        initial : PendulumParameters {l : 1.5, g : 9.81, b : 0.3, theta0 : 1.1, d_theta0 : 0.0},
+        ..Default::default()
+    };
+    let result = fit_pendulum(&times, &angles, &options).unwrap();
+    assert!(result.converged(), "{}", result.stop);
+    assert!((result.parameters.l - truth.l).abs() < 0.02);
+    assert!((result.parameters.b - truth.b).abs() < 0.02);
+    assert!((result.parameters.theta0 - truth.theta0).abs() < 0.01);
+    assert!(result.rms < 0.01);
+    for (parameter, sigma) in &result.uncertainties {
+        assert!(*sigma > 0.0);
+        assert!((result.parameters.get(*parameter) - truth.get(*parameter)).abs() < 5.0 * sigma + 1e-3);
+    }
+}
+
+#[test]
+fn test_fit_rejects_l_and_g() {
+    let (times, angles) = synthetic_measurements(&PendulumParameters {theta0 : 0.5, ..Default::default()}, 50, 0.05, 0.0);
+    let options = FitOptions {fit : vec![FitParameter::L, FitParameter::G], ..Default::default()};
+    assert!(fit_pendulum(&times, &angles, &options).is_err());
+}
+
+#[test]
+fn test_fit_rejects_bad_max_step() {
+    let (times, angles) = synthetic_measurements(&PendulumParameters {theta0 : 0.5, ..Default::default()}, 50, 0.05, 0.0);
+    for max_step in [0.0, -0.01, f64::NAN] {
+        assert!(fit_pendulum(&times, &angles, &FitOptions {max_step, ..Default::default()}).is_err());
+        assert!(simulate_angles(&PendulumParameters::default(), &times, max_step).is_err());
+    }
+}
+
+#[test]
+fn test_read_angle_csv() {
+    let path = std::env::temp_dir().join(format!("pendulum_angles_{}.csv", std::process::id()));
+    fs::write(&path, "# measured\nt,theta\n0.0,0.5\n0.1, 0.45\n0.2,0.3,extra\n").unwrap();
+    let (times, angles) = read_angle_csv(path.to_str().unwrap()).unwrap();
+    assert_eq!(times, vec![0.0, 0.1, 0.2]);
+    assert_eq!(angles, vec![0.5, 0.45, 0.3]);
+    fs::write(&path, "0.0,0.5\n0.1,oops\n").unwrap();
+    assert!(read_angle_csv(path.to_str().unwrap()).is_err());
+    fs::remove_file(&path).unwrap();
+}
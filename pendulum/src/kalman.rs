@@ -0,0 +1,412 @@
+use crate::math::make_propogate_rk4;
+use sim_common::plot_2d::{auto_range, gradient_colors, PlotBackend, PlotOptions};
+use sim_common::trajectory::{Metadata, Trajectory};
+use plotters::coord::Shift;
+use plotters::prelude::*;
+use rand::{rngs::StdRng, SeedableRng};
+use rand_distr::{Distribution, Normal};
+use std::error::Error;
+
+pub(crate) type Matrix2 = [[f64; 2]; 2];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FilterModel {
+    pub l : f64,
+    pub g : f64,
+    pub b : f64,
+    pub process_noise : f64, //spectral density of the unmodelled angular acceleration (rad^2/s^3)
+    pub measurement_noise : f64, //standard deviation of a measured angle (rad)
+}
+
+impl FilterModel {
+    pub(crate) fn propogate(&self, x : &[f64; 2], dt : f64) -> [f64; 2] {
+        let x = make_propogate_rk4(self.l, self.g, self.b, dt)(0.0, x);
+        [x[0], x[1]]
+    }
+
+    pub(crate) fn process_covariance(&self, dt : f64) -> Matrix2 {
+        //white noise acceleration integrated over one step
+        let q = self.process_noise;
+        [[q * dt.powi(3) / 3.0, q * dt.powi(2) / 2.0], [q * dt.powi(2) / 2.0, q * dt]]
+    }
+}
+
+pub trait StateFilter {
+    fn name(&self) -> &str;
+    fn model(&self) -> &FilterModel;
+    fn predict(&mut self, dt : f64);
+    fn update(&mut self, angle : f64);
+    fn state(&self) -> [f64; 2]; //(theta, d_theta)
+    fn covariance(&self) -> Matrix2;
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExtendedKalmanFilter {
+    pub model : FilterModel,
+    pub x : [f64; 2],
+    pub p : Matrix2,
+}
+
+impl ExtendedKalmanFilter {
+    pub fn new(model : FilterModel, x0 : [f64; 2], p0 : Matrix2) -> Self {
+        ExtendedKalmanFilter {model, x : x0, p : p0}
+    }
+}
+
+impl StateFilter for ExtendedKalmanFilter {
+    fn name(&self) -> &str {
+        "ekf"
+    }
+
+    fn model(&self) -> &FilterModel {
+        &self.model
+    }
+
+    fn predict(&mut self, dt : f64) {
+        //the jacobian of one rk4 step is taken by central differences, so it matches the integrator exactly
+        let h = 1e-6;
+        let mut f = [[0.0; 2]; 2];
+        for j in 0..2 {
+            let mut plus = self.x;
+            let mut minus = self.x;
+            plus[j] += h;
+            minus[j] -= h;
+            let (plus, minus) = (self.model.propogate(&plus, dt), self.model.propogate(&minus, dt));
+            for i in 0..2 {
+                f[i][j] = (plus[i] - minus[i]) / (2.0 * h);
+            }
+        }
+        self.x = self.model.propogate(&self.x, dt);
+        self.p = add(&multiply(&multiply(&f, &self.p), &transpose(&f)), &self.model.process_covariance(dt));
+    }
+
+    fn update(&mut self, angle : f64) {
+        (self.x, self.p) = angle_update(&self.x, &self.p, angle, self.model.measurement_noise);
+    }
+
+    fn state(&self) -> [f64; 2] {
+        self.x
+    }
+
+    fn covariance(&self) -> Matrix2 {
+        self.p
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct UnscentedKalmanFilter {
+    pub model : FilterModel,
+    pub x : [f64; 2],
+    pub p : Matrix2,
+    pub alpha : f64, //spread of the sigma points
+    pub beta : f64, //2 is optimal for gaussian priors
+    pub kappa : f64,
+}
+
+impl UnscentedKalmanFilter {
+    pub fn new(model : FilterModel, x0 : [f64; 2], p0 : Matrix2) -> Self {
+        UnscentedKalmanFilter {model, x : x0, p : p0, alpha : 1.0, beta : 2.0, kappa : 1.0}
+    }
+}
+
+impl StateFilter for UnscentedKalmanFilter {
+    fn name(&self) -> &str {
+        "ukf"
+    }
+
+    fn model(&self) -> &FilterModel {
+        &self.model
+    }
+
+    fn predict(&mut self, dt : f64) {
+        /*
+        five sigma points x, x +- sqrt(n + lambda) L_j with L the cholesky factor of p, pushed through one rk4 step
+        the measurement is linear in the state, so the update is the plain kalman update
+         */
+        let n = 2.0;
+        let lambda = self.alpha * self.alpha * (n + self.kappa) - n;
+        let scale = (n + lambda).sqrt();
+        let root = cholesky(&self.p);
+        let mut points = vec![self.x];
+        for column in transpose(&root) {
+            points.push([self.x[0] + scale * column[0], self.x[1] + scale * column[1]]);
+            points.push([self.x[0] - scale * column[0], self.x[1] - scale * column[1]]);
+        }
+        let points : Vec<[f64; 2]> = points.iter().map(|point| self.model.propogate(point, dt)).collect();
+
+        let mean_weight = |k : usize| if k == 0 {lambda / (n + lambda)} else {0.5 / (n + lambda)};
+        let covariance_weight = |k : usize| if k == 0 {mean_weight(0) + 1.0 - self.alpha * self.alpha + self.beta} else {mean_weight(k)};
+        let mut x = [0.0; 2];
+        for (k, point) in points.iter().enumerate() {
+            x[0] += mean_weight(k) * point[0];
+            x[1] += mean_weight(k) * point[1];
+        }
+        let mut p = self.model.process_covariance(dt);
+        for (k, point) in points.iter().enumerate() {
+            let d = [point[0] - x[0], point[1] - x[1]];
+            for i in 0..2 {
+                for j in 0..2 {
+                    p[i][j] += covariance_weight(k) * d[i] * d[j];
+                }
+            }
+        }
+        self.x = x;
+        self.p = p;
+    }
+
+    fn update(&mut self, angle : f64) {
+        (self.x, self.p) = angle_update(&self.x, &self.p, angle, self.model.measurement_noise);
+    }
+
+    fn state(&self) -> [f64; 2] {
+        self.x
+    }
+
+    fn covariance(&self) -> Matrix2 {
+        self.p
+    }
+}
+
+fn angle_update(x : &[f64; 2], p : &Matrix2, angle : f64, sigma : f64) -> ([f64; 2], Matrix2) {
+    //kalman update for h = [1, 0], joseph form keeps p symmetric and positive
+    let s = p[0][0] + sigma * sigma;
+    let k = [p[0][0] / s, p[1][0] / s];
+    let innovation = angle - x[0];
+    let x = [x[0] + k[0] * innovation, x[1] + k[1] * innovation];
+    let a = [[1.0 - k[0], 0.0], [-k[1], 1.0]];
+    let mut p = multiply(&multiply(&a, p), &transpose(&a));
+    for i in 0..2 {
+        for j in 0..2 {
+            p[i][j] += k[i] * k[j] * sigma * sigma;
+        }
+    }
+    (x, p)
+}
+
+fn multiply(a : &Matrix2, b : &Matrix2) -> Matrix2 {
+    let mut c = [[0.0; 2]; 2];
+    for i in 0..2 {
+        for j in 0..2 {
+            c[i][j] = a[i][0] * b[0][j] + a[i][1] * b[1][j];
+        }
+    }
+    c
+}
+
+fn transpose(a : &Matrix2) -> Matrix2 {
+    [[a[0][0], a[1][0]], [a[0][1], a[1][1]]]
+}
+
+fn add(a : &Matrix2, b : &Matrix2) -> Matrix2 {
+    [[a[0][0] + b[0][0], a[0][1] + b[0][1]], [a[1][0] + b[1][0], a[1][1] + b[1][1]]]
+}
+
+pub(crate) fn cholesky(a : &Matrix2) -> Matrix2 {
+    //lower triangular l with l l^T = a, clamped at zero for covariances that lost definiteness to rounding
+    let l00 = a[0][0].max(0.0).sqrt();
+    let l10 = if l00 > 0.0 {a[1][0] / l00} else {0.0};
+    let l11 = (a[1][1] - l10 * l10).max(0.0).sqrt();
+    [[l00, 0.0], [l10, l11]]
+}
+
+pub fn run_filter<F : StateFilter>(filter : &mut F, times : &[f64], angles : &[f64]) -> Result<Trajectory, Box<dyn Error>> {
+    /*
+    filters angle measurements in time order, NaN angles are treated as missing and only predicted
+    the estimate is a trajectory with states theta and d_theta and derived sigma_theta and sigma_d_theta
+     */
+    if times.len() != angles.len() {
+        return Err(format!("got {} times for {} angles", times.len(), angles.len()).into());
+    }
+    if times.windows(2).any(|w| w[1] <= w[0]) {
+        return Err("measurement times must be strictly increasing".into());
+    }
+    let model = *filter.model();
+    let dt = if times.len() > 1 {(times[times.len() - 1] - times[0]) / (times.len() - 1) as f64} else {0.0};
+    let metadata = Metadata::new("pendulum", filter.name(), dt)
+        .with_parameter("l", model.l)
+        .with_parameter("g", model.g)
+        .with_parameter("b", model.b)
+        .with_parameter("process_noise", model.process_noise)
+        .with_parameter("measurement_noise", model.measurement_noise);
+    let mut estimate = Trajectory::new(metadata, &["theta", "d_theta"]);
+    let mut sigma_theta = Vec::with_capacity(times.len());
+    let mut sigma_d_theta = Vec::with_capacity(times.len());
+
+    for (i, (t, angle)) in times.iter().zip(angles).enumerate() {
+        if i > 0 {
+            filter.predict(t - times[i - 1]);
+        }
+        if !angle.is_nan() {
+            filter.update(*angle);
+        }
+        let p = filter.covariance();
+        estimate.push(*t, &filter.state());
+        sigma_theta.push(p[0][0].max(0.0).sqrt());
+        sigma_d_theta.push(p[1][1].max(0.0).sqrt());
+    }
+    estimate.derived.insert("sigma_theta".to_string(), sigma_theta);
+    estimate.derived.insert("sigma_d_theta".to_string(), sigma_d_theta);
+    Ok(estimate)
+}
+
+pub fn noisy_angles(truth : &Trajectory, sigma : f64, seed : u64) -> Result<Vec<f64>, Box<dyn Error>> {
+    //synthetic measurements, the theta series plus gaussian noise from a seeded generator
+    let theta = truth.series("theta").ok_or("trajectory has no 'theta' state")?;
+    let normal = Normal::new(0.0, sigma)?;
+    let mut rng = StdRng::seed_from_u64(seed);
+    Ok(theta.iter().map(|theta| theta + normal.sample(&mut rng)).collect())
+}
+
+pub fn plot_estimates(truth : &Trajectory, estimates : &[Trajectory], titles : &[&str], angles : &[f64], options : &PlotOptions) -> Result<(), Box<dyn Error>> {
+    /*
+    theta and d_theta panels with the ground truth, the measured angles and every estimate with a shaded 3 sigma band
+    the measurements are drawn at the times of the first estimate
+     */
+    if estimates.is_empty() {
+        return Err("no estimates to plot".into());
+    }
+    if titles.len() != estimates.len() {
+        return Err(format!("got {} titles for {} estimates", titles.len(), estimates.len()).into());
+    }
+    if angles.len() != estimates[0].len() {
+        return Err(format!("got {} angles for {} estimated samples", angles.len(), estimates[0].len()).into());
+    }
+    for (estimate, title) in estimates.iter().zip(titles) {
+        for name in ["theta", "d_theta", "sigma_theta", "sigma_d_theta"] {
+            if estimate.series(name).is_none() {
+                return Err(format!("estimate '{}' has no '{}' series", title, name).into());
+            }
+        }
+    }
+    if truth.is_empty() || truth.series("theta").is_none() || truth.series("d_theta").is_none() {
+        return Err("the ground truth needs 'theta' and 'd_theta' samples".into());
+    }
+    match options.backend()? {
+        PlotBackend::Svg => {
+            let root = SVGBackend::new(&options.path, options.size).into_drawing_area();
+            draw_estimates(&root, truth, estimates, titles, angles, options)
+        },
+        PlotBackend::Png => {
+            let root = BitMapBackend::new(&options.path, options.size).into_drawing_area();
+            draw_estimates(&root, truth, estimates, titles, angles, options)
+        },
+    }
+}
+
+fn draw_estimates<DB : DrawingBackend>(root : &DrawingArea<DB, Shift>, truth : &Trajectory, estimates : &[Trajectory], titles : &[&str], angles : &[f64], options : &PlotOptions) -> Result<(), Box<dyn Error>>
+where DB::ErrorType : 'static {
+    root.fill(&WHITE)?;
+    let root = root.titled(&options.title, ("sans-serif", 30).into_font())?;
+    let areas = root.split_evenly((2, 1));
+    let x_range = options.x_range.unwrap_or((estimates[0].times[0], estimates[0].times[estimates[0].len() - 1]));
+    let colors = gradient_colors(estimates.len());
+
+    for (k, (state, sigma, y_label)) in [("theta", "sigma_theta", "Angle (rad)"), ("d_theta", "sigma_d_theta", "Angular velocity (rad/s)")].iter().enumerate() {
+        let is_bottom = k == 1;
+        let truth_values = truth.series(state).unwrap();
+        let bands : Vec<(Vec<f64>, Vec<f64>)> = estimates.iter()
+            .map(|estimate| (estimate.series(state).unwrap(), estimate.series(sigma).unwrap()))
+            .collect();
+        let mut values : Vec<f64> = truth_values.clone();
+        for (mean, sigma) in &bands {
+            values.extend(mean.iter().zip(sigma).flat_map(|(m, s)| [m - 3.0 * s, m + 3.0 * s]));
+        }
+        if k == 0 {
+            values.extend(angles.iter().filter(|angle| !angle.is_nan()));
+        }
+        let y_range = auto_range(values.into_iter());
+
+        let mut chart = ChartBuilder::on(&areas[k])
+            .margin(5)
+            .x_label_area_size(if is_bottom {30} else {15})
+            .y_label_area_size(50)
+            .build_cartesian_2d(x_range.0..x_range.1, y_range.0..y_range.1)?;
+        let mut mesh = chart.configure_mesh();
+        mesh.y_desc(*y_label);
+        if is_bottom {
+            mesh.x_desc(&options.x_label);
+        }
+        mesh.draw()?;
+
+        for (i, ((mean, sigma), estimate)) in bands.iter().zip(estimates).enumerate() {
+            let color = colors[i];
+            let upper = estimate.times.iter().zip(mean.iter().zip(sigma)).map(|(t, (m, s))| (*t, m + 3.0 * s));
+            let lower = estimate.times.iter().zip(mean.iter().zip(sigma)).map(|(t, (m, s))| (*t, m - 3.0 * s)).rev();
+            chart.draw_series(std::iter::once(Polygon::new(upper.chain(lower).collect::<Vec<_>>(), color.mix(0.2).filled())))?;
+        }
+        if k == 0 {
+            chart.draw_series(estimates[0].times.iter().zip(angles).filter(|(_, angle)| !angle.is_nan())
+                .map(|(t, angle)| Circle::new((*t, *angle), 2, RGBColor(150, 150, 150).filled())))?
+                .label("measured")
+                .legend(|(x, y)| Circle::new((x + 10, y), 3, RGBColor(150, 150, 150).filled()));
+        }
+        chart.draw_series(LineSeries::new(truth.times.iter().copied().zip(truth_values), BLACK.stroke_width(2)))?
+            .label("truth")
+            .legend(|(x, y)| PathElement::new(vec![(x, y), (x + 20, y)], BLACK.stroke_width(2)));
+        for (i, ((mean, _), estimate)) in bands.iter().zip(estimates).enumerate() {
+            let line_style = options.line_styles.get(i).copied().unwrap_or_default();
+            let style = line_style.color.unwrap_or(colors[i]).stroke_width(line_style.width);
+            chart.draw_series(LineSeries::new(estimate.times.iter().copied().zip(mean.iter().copied()), style))?
+                .label(format!("{} (3 sigma)", titles[i]))
+                .legend(move |(x, y)| PathElement::new(vec![(x, y), (x + 20, y)], style));
+        }
+
+        chart.configure_series_labels()
+            .position(SeriesLabelPosition::UpperRight)
+            .border_style(BLACK)
+            .background_style(WHITE.mix(0.8))
+            .draw()?;
+    }
+
+    root.present()?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+fn simulate_truth(model : &FilterModel, theta0 : f64, dt : f64, steps : usize) -> Trajectory {
+    let propogate = make_propogate_rk4(model.l, model.g, model.b, dt);
+    let mut truth = Trajectory::new(Metadata::new("pendulum", "rk4", dt), &["theta", "d_theta"]);
+    let mut x = vec![theta0, 0.0];
+    for i in 0..steps {
+        truth.push(i as f64 * dt, &x);
+        x = propogate(i as f64 * dt, &x);
+    }
+    truth
+}
+
+#[test]
+fn test_filters_track_the_truth() {
+    //both filters start far off in velocity and should settle well below the measurement noise
+    let model = FilterModel {l : 2.0, g : 9.81, b : 0.3, process_noise : 1e-4, measurement_noise : 0.05};
+    let truth = simulate_truth(&model, 1.0, 0.02, 1000);
+    let angles = noisy_angles(&truth, model.measurement_noise, 7).unwrap();
+    let p0 = [[0.1, 0.0], [0.0, 1.0]];
+    let ekf = run_filter(&mut ExtendedKalmanFilter::new(model, [angles[0], 0.5], p0), &truth.times, &angles).unwrap();
+    let ukf = run_filter(&mut UnscentedKalmanFilter::new(model, [angles[0], 0.5], p0), &truth.times, &angles).unwrap();
+
+    for estimate in [ekf, ukf] {
+        let settled = 200..truth.len();
+        let errors : Vec<f64> = settled.clone().map(|i| estimate.states[i][0] - truth.states[i][0]).collect();
+        let rms = (errors.iter().map(|e| e * e).sum::<f64>() / errors.len() as f64).sqrt();
+        assert!(rms < 0.5 * model.measurement_noise);
+        let sigma = &estimate.derived["sigma_theta"];
+        let inside = settled.zip(&errors).filter(|(i, e)| e.abs() <= 3.0 * sigma[*i]).count();
+        assert!(inside as f64 >= 0.95 * errors.len() as f64);
+    }
+}
+
+#[test]
+fn test_missing_measurements_grow_uncertainty() {
+    let model = FilterModel {l : 1.0, g : 9.81, b : 0.0, process_noise : 1e-3, measurement_noise : 0.01};
+    let truth = simulate_truth(&model, 0.3, 0.05, 40);
+    let mut angles = noisy_angles(&truth, model.measurement_noise, 1).unwrap();
+    assert_eq!(angles, noisy_angles(&truth, model.measurement_noise, 1).unwrap());
+    for angle in angles.iter_mut().skip(20) {
+        *angle = f64::NAN;
+    }
+    let estimate = run_filter(&mut ExtendedKalmanFilter::new(model, [0.3, 0.0], [[0.01, 0.0], [0.0, 0.01]]), &truth.times, &angles).unwrap();
+    let sigma = &estimate.derived["sigma_theta"];
+    assert!(sigma[39] > sigma[19]);
+    assert!(run_filter(&mut ExtendedKalmanFilter::new(model, [0.3, 0.0], [[0.01, 0.0], [0.0, 0.01]]), &truth.times, &angles[1..]).is_err());
+}
@@ -1,10 +1,41 @@
 mod math;
 pub use math::make_propogate_euler;
 pub use math::make_propogate_rk4;
-pub use math::lerp1d;
+pub use sim_common::math::lerp1d;
+pub use math::energy;
+pub use math::eom;
+pub use math::solve_linear;
+
+pub use sim_common::trajectory::{Trajectory, Metadata};
+pub use sim_common::trajectory_io::{write_npy, read_npy};
 
 mod plot_2d;
-pub use plot_2d::plot_theta_vecs;
+pub use plot_2d::{plot_theta_vecs, plot_phase_portrait, separatrix};
+pub use sim_common::plot_2d::{PlotOptions, PlotBackend, LineStyle};
+
+mod plot_diagnostics;
+pub use plot_diagnostics::plot_diagnostics;
+
+pub use sim_common::spectrum::{Window, Spectrum, power_spectrum, trajectory_spectrum, resample, is_uniform, plot_spectrum};
+
+mod analysis;
+pub use analysis::{analyze_oscillation, OscillationSummary, CycleDetection};
+
+mod estimation;
+pub use estimation::{fit_pendulum, simulate_angles, read_angle_csv, plot_fit, FitOptions, FitResult, FitStop, FitParameter, PendulumParameters};
+
+mod kalman;
+pub use kalman::{run_filter, noisy_angles, plot_estimates, StateFilter, FilterModel, ExtendedKalmanFilter, UnscentedKalmanFilter};
+
+mod particle_filter;
+pub use particle_filter::{ParticleFilter, Resampling};
+
+mod stochastic;
+pub use stochastic::{make_stochastic_pendulum, thermal_noise, first_passage_time};
+pub use sim_common::stochastic::{StochasticIntegrator, Noise, NoiseType, SdeScheme};
+
+mod animate_2d;
+pub use animate_2d::{animate_gif, GifOptions};
 
 mod draw_3d;
-pub use draw_3d::draw_3d;
\ No newline at end of file
+pub use draw_3d::{draw_3d, replay_3d};
@@ -1,6 +1,63 @@
-use pendulum::{make_propogate_euler, make_propogate_rk4, plot_theta_vecs, draw_3d};
+use pendulum::{make_propogate_euler, make_propogate_rk4, energy, plot_theta_vecs, plot_phase_portrait, plot_diagnostics, PlotOptions, animate_gif, GifOptions, trajectory_spectrum, plot_spectrum, Window, analyze_oscillation, CycleDetection, draw_3d, replay_3d, Trajectory, Metadata, read_angle_csv, fit_pendulum, plot_fit, FitOptions, PendulumParameters, noisy_angles, run_filter, plot_estimates, FilterModel, ExtendedKalmanFilter, UnscentedKalmanFilter, ParticleFilter, Resampling, make_stochastic_pendulum, thermal_noise, first_passage_time, SdeScheme};
+
+fn get_argument(name : &str) -> Option<String> {
+    let args: Vec<String> = std::env::args().collect();
+    match args.iter().position(|arg| arg == name) {
+        Some(i) => match args.get(i + 1) {
+            Some(value) => Some(value.clone()),
+            None => {
+                eprintln!("Error: missing value for argument '{}'", name);
+                std::process::exit(1);
+            }
+        },
+        None => None,
+    }
+}
+
+fn with_suffix(filename : &str, suffix : &str) -> String {
+    //data.csv -> data_rk4.csv
+    match filename.rsplit_once('.') {
+        Some((stem, extension)) => format!("{}_{}.{}", stem, suffix, extension),
+        None => format!("{}_{}", filename, suffix),
+    }
+}
 
 fn main() {
+    //--replay FILE plays back a saved trajectory instead of simulating
+    if let Some(replay) = get_argument("--replay") {
+        let trajectory = Trajectory::load(&replay).expect("loading trajectory failed");
+        replay_3d(&trajectory).expect("replaying trajectory failed");
+        return;
+    }
+    //--fit FILE estimates l, b and the initial state from a csv of time stamped angles
+    if let Some(fit) = get_argument("--fit") {
+        let (times, angles) = read_angle_csv(&fit).expect("reading measurements failed");
+        if times.is_empty() {
+            eprintln!("Error: no measurements in {}", fit);
+            std::process::exit(1);
+        }
+        let options = FitOptions {
+            initial : PendulumParameters {l : 1.0, g : 9.81, b : 0.1, theta0 : angles[0], d_theta0 : 0.0},
+            ..Default::default()
+        };
+        let result = fit_pendulum(&times, &angles, &options).expect("fitting failed");
+        println!("Fit {} after {} iterations, rms residual {:.3e} rad", result.stop, result.iterations, result.rms);
+        for (parameter, sigma) in &result.uncertainties {
+            println!("  {} = {:.5} +- {:.5}", parameter.name(), result.parameters.get(*parameter), sigma);
+        }
+        let fit_options = PlotOptions {path : "fit.svg".to_string(), title : "Pendulum Fit".to_string(), y_label : "Angle (rad)".to_string(), ..Default::default()};
+        plot_fit(&times, &angles, &result, &fit_options).expect("plotting fit failed");
+        println!("The fit was saved as {}.", fit_options.path);
+        return;
+    }
+    //--output FILE saves both trajectories, format chosen by extension (.csv, .json, .npz, .npy)
+    let output = get_argument("--output");
+    //--gif FILE writes a 2d animation of the rk4 run, --draw false skips the 3d window (e.g. on CI)
+    let gif = get_argument("--gif");
+    //--temperature T adds a thermally driven run (T is kT/m in J/kg), --seed N makes its noise reproducible
+    let temperature = get_argument("--temperature").map(|value| value.parse::<f64>().expect("--temperature must be a number"));
+    let seed = get_argument("--seed").map(|value| value.parse::<u64>().expect("--seed must be a non-negative integer")).unwrap_or(0);
+    let draw = get_argument("--draw").map(|value| value != "false").unwrap_or(true);
     let l: f64 = 2.0;
     let g: f64 = 9.81;
     let dt: f64 = 0.06;
@@ -9,19 +66,21 @@ fn main() {
     let max_time: f64 = 10.0 * (2.0 * pi * (l/g).sqrt());
     let iterations : usize = (max_time / dt) as usize;
 
-    let mut x_k_euler = vec![3.14/2.0, 0.0];
+    let make_metadata = |integrator : &str| Metadata::new("pendulum", integrator, dt)
+        .with_parameter("l", l)
+        .with_parameter("g", g)
+        .with_parameter("b", b);
+
+    let mut x_k_euler = vec![pi/2.0, 0.0];
     let propogate_euler = make_propogate_euler(l, g, b, dt);
+    let mut euler = Trajectory::new(make_metadata("euler"), &["theta", "d_theta"]);
 
     let mut x_k_rk4 = x_k_euler.clone();
     let propogate_rk4 = make_propogate_rk4(l, g, b, dt);
+    let mut rk4 = Trajectory::new(make_metadata("rk4"), &["theta", "d_theta"]);
 
-    let mut theta_euler_values = Vec::with_capacity(iterations);
-    let mut theta_rk4_values = Vec::with_capacity(iterations);
-    let mut time_values = Vec::with_capacity(iterations);
-
-    theta_euler_values.push(x_k_euler[0]);
-    theta_rk4_values.push(x_k_rk4[0]);
-    time_values.push(0.0);
+    euler.push(0.0, &x_k_euler);
+    rk4.push(0.0, &x_k_rk4);
 
     let mut t = 0.0;
     for _ in 1..iterations {
@@ -29,20 +88,133 @@ fn main() {
         x_k_euler = propogate_euler(&x_k_euler);
         x_k_rk4 = propogate_rk4(t, &x_k_rk4);
         
-        theta_euler_values.push(x_k_euler[0]);
-        theta_rk4_values.push(x_k_rk4[0]);
-        time_values.push(t);
+        euler.push(t, &x_k_euler);
+        rk4.push(t, &x_k_rk4);
     }
+    euler.add_derived("energy", |_t, x| energy(x, l, g));
+    rk4.add_derived("energy", |_t, x| energy(x, l, g));
+    let trajectories = vec![euler, rk4];
+    let titles = ["euler", "rk4"];
+    let rk4 = &trajectories[1];
 
-    //mental note: theta_values now owns theta_euler_values and theta_rk4_values
-    let theta_values = vec!(theta_euler_values,theta_rk4_values);
+    if let Some(output) = output {
+        for trajectory in &trajectories {
+            let filename = with_suffix(&output, &trajectory.metadata.integrator);
+            trajectory.save(&filename).expect("saving trajectory failed");
+            println!("Saved the {} trajectory to {}.", trajectory.metadata.integrator, filename);
+        }
+    }
+
+    let theta_values : Vec<Vec<f64>> = trajectories.iter().map(|trajectory| trajectory.column(0)).collect();
     //make a 2d plot
-    plot_theta_vecs(&time_values,
+    let plot_options = PlotOptions {title : "Pendulum Motion".to_string(), y_label : "Angle (rad)".to_string(), ..Default::default()};
+    plot_theta_vecs(&rk4.times,
          &theta_values,
-         &vec!("euler","rk4")).expect("plotting failed");
+         &titles,
+         &plot_options).expect("plotting failed");
+    //phase portrait of both integrators over the direction field
+    let phase_options = PlotOptions {
+        path : "phase.svg".to_string(),
+        title : "Phase Portrait".to_string(),
+        x_label : "Angle (rad)".to_string(),
+        y_label : "Angular velocity (rad/s)".to_string(),
+        ..Default::default()
+    };
+    plot_phase_portrait(&trajectories, &titles, l, g, b, &phase_options)
+        .expect("plotting phase portrait failed");
+    //stacked angle, velocity, energy and error panels, rk4 is the reference
+    let diagnostics_options = PlotOptions {
+        path : "diagnostics.svg".to_string(),
+        size : (800, 1000),
+        title : "Pendulum Diagnostics".to_string(),
+        ..Default::default()
+    };
+    plot_diagnostics(&trajectories, &titles, 1, &diagnostics_options)
+        .expect("plotting diagnostics failed");
+    //compare the dominant frequency with the small angle natural frequency
+    let natural_frequency = (g/l).sqrt() / (2.0 * pi);
+    let spectra : Vec<_> = trajectories.iter()
+        .map(|trajectory| trajectory_spectrum(trajectory, "theta", None, Window::Hann).expect("spectrum failed"))
+        .collect();
+    for (spectrum, name) in spectra.iter().zip(titles) {
+        if let Some(frequency) = spectrum.dominant_frequency() {
+            println!("Dominant frequency ({}): {:.4} Hz ({:.4} rad/s), sqrt(g/l) = {:.4} rad/s",
+                name, frequency, 2.0 * pi * frequency, (g/l).sqrt());
+        }
+    }
+    //measured period and damping against the small angle prediction zeta = b / (2 sqrt(g/l))
+    for (trajectory, name) in trajectories.iter().zip(titles) {
+        match analyze_oscillation(trajectory, "theta", CycleDetection::ZeroCrossings) {
+            Ok(summary) => println!("Oscillation ({}): period {:.4} s, log decrement {:.4}, damping ratio {:.4} (expected {:.4}), Q {:.2}",
+                name, summary.mean_period, summary.logarithmic_decrement, summary.damping_ratio, b / (2.0 * (g/l).sqrt()), summary.quality_factor),
+            Err(e) => println!("Oscillation ({}): {}", name, e),
+        }
+    }
+    //track the rk4 run from noisy angles only, starting with an unknown velocity
+    let filter_model = FilterModel {l, g, b, process_noise : 1e-3, measurement_noise : 0.05};
+    let angles = noisy_angles(rk4, filter_model.measurement_noise, 0).expect("generating measurements failed");
+    let p0 = [[filter_model.measurement_noise.powi(2), 0.0], [0.0, 1.0]];
+    let mut particle_filter = ParticleFilter::new(filter_model, 1000, [angles[0], 0.0], [p0[0][0].sqrt(), p0[1][1].sqrt()], Resampling::Systematic, 0).expect("creating the particle filter failed");
+    let estimates = vec![
+        run_filter(&mut ExtendedKalmanFilter::new(filter_model, [angles[0], 0.0], p0), &rk4.times, &angles).expect("filtering failed"),
+        run_filter(&mut UnscentedKalmanFilter::new(filter_model, [angles[0], 0.0], p0), &rk4.times, &angles).expect("filtering failed"),
+        run_filter(&mut particle_filter, &rk4.times, &angles).expect("filtering failed"),
+    ];
+    for estimate in &estimates {
+        let squared_error : f64 = estimate.states.iter().zip(&rk4.states).map(|(e, x)| (e[0] - x[0]).powi(2)).sum();
+        println!("Filter ({}): rms angle error {:.4} rad from measurements with sigma {:.4} rad",
+            estimate.metadata.integrator, (squared_error / rk4.len() as f64).sqrt(), filter_model.measurement_noise);
+    }
+    let mean_ess = particle_filter.ess_history.iter().sum::<f64>() / particle_filter.ess_history.len() as f64;
+    println!("Particle filter: mean effective sample size {:.1} of {}, resampled {} times",
+        mean_ess, particle_filter.particles.len(), particle_filter.resample_count);
+    let kalman_options = PlotOptions {
+        path : "kalman.svg".to_string(),
+        size : (800, 800),
+        title : "Kalman Filter Estimates".to_string(),
+        ..Default::default()
+    };
+    plot_estimates(rk4, &estimates, &["ekf", "ukf", "pf"], &angles, &kalman_options)
+        .expect("plotting estimates failed");
+    let spectrum_options = PlotOptions {
+        path : "spectrum.svg".to_string(),
+        title : "Angle Power Spectrum".to_string(),
+        x_label : "Frequency (Hz)".to_string(),
+        y_label : "Power".to_string(),
+        ..Default::default()
+    };
+    plot_spectrum(&spectra, &titles, &[(natural_frequency, "sqrt(g/l)/2pi")], &spectrum_options)
+        .expect("plotting spectrum failed");
+
+    if let Some(temperature) = temperature {
+        //stochastic heun from the same start, driven by noise in balance with the damping
+        let mut stochastic = make_stochastic_pendulum(l, g, b, dt, thermal_noise(l, b, temperature), SdeScheme::Heun, seed);
+        let mut x = rk4.states[0].clone();
+        let mut theta_stochastic = vec![x[0]];
+        for t in &rk4.times[..rk4.len() - 1] {
+            x = stochastic.propogate(*t, &x);
+            theta_stochastic.push(x[0]);
+        }
+        match first_passage_time(&rk4.times, &theta_stochastic, pi) {
+            Some(t) => println!("Thermal run (T = {} J/kg): escaped over the upright position at t = {:.3} s", temperature, t),
+            None => println!("Thermal run (T = {} J/kg): did not escape over the upright position", temperature),
+        }
+        let stochastic_options = PlotOptions {path : "stochastic.svg".to_string(), title : "Thermal Pendulum".to_string(), y_label : "Angle (rad)".to_string(), ..Default::default()};
+        plot_theta_vecs(&rk4.times, &[rk4.column(0), theta_stochastic], &["rk4", "heun (thermal)"], &stochastic_options)
+            .expect("plotting stochastic run failed");
+        println!("Saved the thermal run to {}.", stochastic_options.path);
+    }
+
+    if let Some(gif) = gif {
+        animate_gif(rk4, &GifOptions {path : gif.clone(), ..Default::default()}).expect("animating failed");
+        println!("Saved the animation to {}.", gif);
+    }
     //make a 3d drawing
-    draw_3d(&time_values, &theta_values[1], l as f32);
+    if draw {
+        draw_3d(&rk4.times, &rk4.column(0), l as f32);
+    }
     
-    println!("Finished the program. The plot was saved as plot.png.");
+    println!("Finished the program. The plots were saved as {}, {}, {}, {} and {}.",
+        plot_options.path, phase_options.path, diagnostics_options.path, kalman_options.path, spectrum_options.path);
 
 }
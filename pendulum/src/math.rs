@@ -1,8 +1,8 @@
-pub fn make_propogate_euler(l : f64, g : f64, b : f64, dt : f64) -> impl Fn(&Vec<f64>) -> Vec<f64> {
+pub fn make_propogate_euler(l : f64, g : f64, b : f64, dt : f64) -> impl Fn(&[f64]) -> Vec<f64> {
     move |x_k| propogate_euler(x_k, l, g, b, dt)
 }
 
-fn propogate_euler(x_k: &Vec<f64>, l : f64, g : f64, b : f64, dt : f64) -> Vec<f64> {
+fn propogate_euler(x_k: &[f64], l : f64, g : f64, b : f64, dt : f64) -> Vec<f64> {
     /*
     x_k - state at time k [theta_k, d_theta_k]
     l - length of pendulum
@@ -24,15 +24,14 @@ fn propogate_euler(x_k: &Vec<f64>, l : f64, g : f64, b : f64, dt : f64) -> Vec<f
     let d_theta_kp1 = d_theta_k + d2_theta_k * dt;
 
     
-    let x_kp1 = vec![theta_kp1, d_theta_kp1];
-    return x_kp1;
+    vec![theta_kp1, d_theta_kp1]
 }
 
-pub fn make_propogate_rk4(l : f64, g : f64, b : f64, dt : f64) -> impl Fn(f64, &Vec<f64>) -> Vec<f64> {
+pub fn make_propogate_rk4(l : f64, g : f64, b : f64, dt : f64) -> impl Fn(f64, &[f64]) -> Vec<f64> {
     move |t, x| propogate_rk4(t, x, l, g, b, dt)
 }
 
-fn propogate_rk4(t : f64, x: &Vec<f64>, l : f64, g : f64, b : f64, dt : f64) -> Vec<f64> {
+fn propogate_rk4(t : f64, x: &[f64], l : f64, g : f64, b : f64, dt : f64) -> Vec<f64> {
     /*
     x - current state [theta_k, d_theta_k]
     l - length of pendulum
@@ -46,15 +45,10 @@ fn propogate_rk4(t : f64, x: &Vec<f64>, l : f64, g : f64, b : f64, dt : f64) ->
 
      //define derivative function (continuous time)
      //t is here just for consistency with the RK4 algorithm
-     let f = |_t: f64, x: &Vec<f64>| -> Vec<f64> {
-        let theta = x[0];
-        let d_theta = x[1];
-        let d2_theta = -g/l * theta.sin() - b * d_theta;
-        return vec![d_theta, d2_theta];
-    };
+     let f = |_t: f64, x: &[f64]| -> Vec<f64> { eom(x, l, g, b) };
 
     //RK4 integration
-    let k1 = f(t, &x);
+    let k1 = f(t, x);
     
     let x4k2_0 = x[0] + dt/2.0 * k1[0]; 
     let x4k2_1 = x[1] + dt/2.0 * k1[1];
@@ -73,43 +67,56 @@ fn propogate_rk4(t : f64, x: &Vec<f64>, l : f64, g : f64, b : f64, dt : f64) ->
 
     let row1 = x[0] + dt/6.0 * (k1[0] + 2.0*k2[0] + 2.0*k3[0] + k4[0]);
     let row2 = x[1] + dt/6.0 * (k1[1] + 2.0*k2[1] + 2.0*k3[1] + k4[1]);
-    return vec![row1, row2];
+    vec![row1, row2]
 }
 
-pub fn lerp1d<T>(x : T, x_vec : &Vec<T>, y_vec : &Vec<T>) -> T
-    where T: std::ops::Add<Output = T>
-            + std::ops::Sub<Output = T> 
-            + std::ops::Div<Output = T> 
-            + std::ops::Mul<Output = T> 
-            + std::cmp::PartialOrd
-            + std::marker::Copy {
-    //find the two points in x_vec that are closest to x
-    //interpolate between the two points
-    //return the interpolated value
-
-    if x < x_vec[0] {
-        return y_vec[0];
-    }
-    else if x > x_vec[x_vec.len()-1] {
-        return y_vec[y_vec.len()-1];
+pub fn eom(x : &[f64], l : f64, g : f64, b : f64) -> Vec<f64> {
+    //continuous time derivative of the state [theta, d_theta]
+    let theta = x[0];
+    let d_theta = x[1];
+    let d2_theta = -g/l * theta.sin() - b * d_theta;
+    vec![d_theta, d2_theta]
+}
+
+pub fn energy(x : &[f64], l : f64, g : f64) -> f64 {
+    //total mechanical energy per unit bob mass, zero at rest in the bottom position
+    let theta = x[0];
+    let d_theta = x[1];
+    0.5 * (l * d_theta).powi(2) + g * l * (1.0 - theta.cos())
+}
+
+pub fn solve_linear(a : &[Vec<f64>], b : &[f64]) -> Option<Vec<f64>> {
+    /*
+    solves a x = b for a small dense square matrix
+    gaussian elimination with partial pivoting, None if a is singular
+     */
+    let n = b.len();
+    let mut m : Vec<Vec<f64>> = a.iter().zip(b).map(|(row, bi)| {
+        let mut row = row.clone();
+        row.push(*bi);
+        row
+    }).collect();
+    for col in 0..n {
+        let pivot = (col..n).max_by(|&i, &j| m[i][col].abs().total_cmp(&m[j][col].abs()))?;
+        if m[pivot][col].abs() < 1e-300 {
+            return None;
+        }
+        m.swap(col, pivot);
+        let (upper, lower) = m.split_at_mut(col + 1);
+        let pivot_row = &upper[col];
+        for row in lower.iter_mut() {
+            let factor = row[col] / pivot_row[col];
+            for (value, pivot_value) in row.iter_mut().zip(pivot_row).skip(col) {
+                *value -= factor * pivot_value;
+            }
+        }
     }
-    else
-    {
-    //find the two points in x_vec that are closest to x
-    //use binary search because x_vec is sorted
-    let i = match x_vec.binary_search_by(|&probe| probe.partial_cmp(&x).unwrap()) {
-        Ok(index) => index,
-        Err(index) => index,
-    };
-    let x0 = x_vec[i-1];
-    let x1 = x_vec[i];
-    let y0 = y_vec[i-1];
-    let y1 = y_vec[i];
-
-    //interpolate between the two points
-    let y = y0 + (y1 - y0) / (x1 - x0) * (x - x0);
-    return y;
+    let mut x = vec![0.0; n];
+    for row in (0..n).rev() {
+        let sum : f64 = (row + 1..n).map(|k| m[row][k] * x[k]).sum();
+        x[row] = (m[row][n] - sum) / m[row][row];
     }
+    Some(x)
 }
 
 #[test]
@@ -151,10 +158,11 @@ fn test_propogate_rk4() {
 }
 
 #[test]
-fn test_lerp1d() {
-    let x_vec = vec![0.0, 1.0, 2.0, 3.0];
-    let y_vec = vec![3.0, 2.0, 1.0, 0.0];
-    let x = 0.5;
-    let y = lerp1d(x, &x_vec, &y_vec);
-    assert!(y == 2.5);
+fn test_solve_linear() {
+    let a = vec![vec![0.0, 2.0, 1.0], vec![1.0, 1.0, 0.0], vec![3.0, 0.0, 1.0]];
+    let x = solve_linear(&a, &[5.0, 3.0, 4.0]).unwrap();
+    for (xi, expected) in x.iter().zip([1.0, 2.0, 1.0]) {
+        assert!((xi - expected).abs() < 1e-12);
+    }
+    assert!(solve_linear(&[vec![1.0, 2.0], vec![2.0, 4.0]], &[1.0, 2.0]).is_none());
 }
@@ -0,0 +1,218 @@
+use crate::kalman::{cholesky, FilterModel, Matrix2, StateFilter};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use rand_distr::StandardNormal;
+use rayon::prelude::*;
+use std::error::Error;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Resampling {
+    Systematic, //one uniform offset shared by all strata
+    Stratified, //an independent uniform in every stratum
+}
+
+impl Resampling {
+    pub fn indices<R : Rng>(&self, weights : &[f64], rng : &mut R) -> Vec<usize> {
+        //draws weights.len() particle indices, each particle is picked in proportion to its (normalized) weight
+        let n = weights.len();
+        let total : f64 = weights.iter().sum();
+        let offset : f64 = rng.gen();
+        let mut indices = Vec::with_capacity(n);
+        let mut cumulative = 0.0;
+        let mut j = 0;
+        for i in 0..n {
+            let u = match self {
+                Resampling::Systematic => (i as f64 + offset) / n as f64,
+                Resampling::Stratified => (i as f64 + rng.gen::<f64>()) / n as f64,
+            };
+            while j < n - 1 && cumulative + weights[j] / total < u {
+                cumulative += weights[j] / total;
+                j += 1;
+            }
+            indices.push(j);
+        }
+        indices
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ParticleFilter {
+    pub model : FilterModel,
+    pub particles : Vec<[f64; 2]>,
+    pub weights : Vec<f64>, //normalized
+    pub resampling : Resampling,
+    pub resample_threshold : f64, //resample when the effective sample size drops below this fraction of the particles
+    pub ess_history : Vec<f64>, //effective sample size after every update, before resampling
+    pub resample_count : usize,
+    rng : StdRng,
+}
+
+impl ParticleFilter {
+    pub fn new(model : FilterModel, count : usize, x0 : [f64; 2], spread : [f64; 2], resampling : Resampling, seed : u64) -> Result<Self, Box<dyn Error>> {
+        //particles start gaussian around x0 with standard deviations spread
+        if count == 0 {
+            return Err("the particle filter needs at least one particle".into());
+        }
+        //the likelihood divides by the measurement noise
+        if !(model.measurement_noise.is_finite() && model.measurement_noise > 0.0) {
+            return Err(format!("the particle filter needs a positive measurement noise, got {}", model.measurement_noise).into());
+        }
+        let mut rng = StdRng::seed_from_u64(seed);
+        let particles = (0..count).map(|_| {
+            let (a, b) : (f64, f64) = (rng.sample(StandardNormal), rng.sample(StandardNormal));
+            [x0[0] + spread[0] * a, x0[1] + spread[1] * b]
+        }).collect();
+        Ok(ParticleFilter {
+            model,
+            particles,
+            weights : vec![1.0 / count as f64; count],
+            resampling,
+            resample_threshold : 0.5,
+            ess_history : Vec::new(),
+            resample_count : 0,
+            rng,
+        })
+    }
+
+    pub fn effective_sample_size(&self) -> f64 {
+        1.0 / self.weights.iter().map(|w| w * w).sum::<f64>()
+    }
+
+    pub fn resample(&mut self) {
+        let indices = self.resampling.indices(&self.weights, &mut self.rng);
+        self.particles = indices.iter().map(|&i| self.particles[i]).collect();
+        self.weights = vec![1.0 / self.particles.len() as f64; self.particles.len()];
+        self.resample_count += 1;
+    }
+}
+
+impl StateFilter for ParticleFilter {
+    fn name(&self) -> &str {
+        "pf"
+    }
+
+    fn model(&self) -> &FilterModel {
+        &self.model
+    }
+
+    fn predict(&mut self, dt : f64) {
+        /*
+        every particle takes one rk4 step plus a draw of the process noise
+        the noise is drawn up front from the single seeded generator, so the parallel step stays reproducible
+         */
+        let root = cholesky(&self.model.process_covariance(dt));
+        let noise : Vec<[f64; 2]> = (0..self.particles.len()).map(|_| {
+            let (a, b) : (f64, f64) = (self.rng.sample(StandardNormal), self.rng.sample(StandardNormal));
+            [root[0][0] * a, root[1][0] * a + root[1][1] * b]
+        }).collect();
+        let model = self.model;
+        self.particles.par_iter_mut().zip(noise.par_iter()).for_each(|(particle, noise)| {
+            let x = model.propogate(particle, dt);
+            *particle = [x[0] + noise[0], x[1] + noise[1]];
+        });
+    }
+
+    fn update(&mut self, angle : f64) {
+        //gaussian likelihood of the angle, weights are handled in log space so distant particles do not underflow all at once
+        let sigma = self.model.measurement_noise;
+        let log_weights : Vec<f64> = self.particles.par_iter().zip(self.weights.par_iter())
+            .map(|(particle, w)| w.ln() - 0.5 * ((angle - particle[0]) / sigma).powi(2))
+            .collect();
+        let max = log_weights.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+        let weights : Vec<f64> = log_weights.iter().map(|log_w| (log_w - max).exp()).collect();
+        let total : f64 = weights.iter().sum();
+        self.weights = weights.iter().map(|w| w / total).collect();
+
+        let ess = self.effective_sample_size();
+        self.ess_history.push(ess);
+        if ess < self.resample_threshold * self.particles.len() as f64 {
+            self.resample();
+        }
+    }
+
+    fn state(&self) -> [f64; 2] {
+        //weighted mean, for a multimodal cloud look at the particles themselves
+        let mut x = [0.0; 2];
+        for (particle, w) in self.particles.iter().zip(&self.weights) {
+            x[0] += w * particle[0];
+            x[1] += w * particle[1];
+        }
+        x
+    }
+
+    fn covariance(&self) -> Matrix2 {
+        let x = self.state();
+        let mut p = [[0.0; 2]; 2];
+        for (particle, w) in self.particles.iter().zip(&self.weights) {
+            let d = [particle[0] - x[0], particle[1] - x[1]];
+            for i in 0..2 {
+                for j in 0..2 {
+                    p[i][j] += w * d[i] * d[j];
+                }
+            }
+        }
+        p
+    }
+}
+
+#[test]
+fn test_resampling_follows_weights() {
+    let mut rng = StdRng::seed_from_u64(3);
+    for resampling in [Resampling::Systematic, Resampling::Stratified] {
+        //zero weight particles are never picked
+        let indices = resampling.indices(&[0.0, 0.5, 0.0, 0.5], &mut rng);
+        assert_eq!(indices.len(), 4);
+        assert!(indices.iter().all(|i| *i == 1 || *i == 3));
+
+        let weights = [0.1, 0.2, 0.3, 0.4];
+        let mut counts = [0usize; 4];
+        for _ in 0..1000 {
+            for i in resampling.indices(&weights, &mut rng) {
+                counts[i] += 1;
+            }
+        }
+        for (count, w) in counts.iter().zip(weights) {
+            assert!((*count as f64 / 4000.0 - w).abs() < 0.02);
+        }
+    }
+    //systematic resampling keeps every particle whose weight is at least 1/n
+    let indices = Resampling::Systematic.indices(&[0.25; 4], &mut rng);
+    assert_eq!(indices, vec![0, 1, 2, 3]);
+}
+
+#[test]
+fn test_particle_filter_near_upright() {
+    //released just off the inverted position, where a linearized filter cannot tell which way it falls
+    use crate::kalman::{noisy_angles, run_filter};
+    use crate::math::make_propogate_rk4;
+    use sim_common::trajectory::{Metadata, Trajectory};
+    use std::f64::consts::PI;
+    let model = FilterModel {l : 1.0, g : 9.81, b : 0.1, process_noise : 1e-3, measurement_noise : 0.05};
+    let dt = 0.02;
+    let propogate = make_propogate_rk4(model.l, model.g, model.b, dt);
+    let mut truth = Trajectory::new(Metadata::new("pendulum", "rk4", dt), &["theta", "d_theta"]);
+    let mut x = vec![PI - 0.05, 0.0];
+    for i in 0..300 {
+        truth.push(i as f64 * dt, &x);
+        x = propogate(i as f64 * dt, &x);
+    }
+    let angles = noisy_angles(&truth, model.measurement_noise, 11).unwrap();
+
+    let run = |seed : u64| {
+        let mut filter = ParticleFilter::new(model, 2000, [PI, 0.0], [0.1, 0.3], Resampling::Systematic, seed).unwrap();
+        let estimate = run_filter(&mut filter, &truth.times, &angles).unwrap();
+        (estimate, filter)
+    };
+    let (estimate, filter) = run(5);
+    let settled = 100..truth.len();
+    let rms = (settled.clone().map(|i| (estimate.states[i][0] - truth.states[i][0]).powi(2)).sum::<f64>() / settled.len() as f64).sqrt();
+    assert!(rms < model.measurement_noise);
+    assert_eq!(filter.ess_history.len(), truth.len());
+    assert!(filter.ess_history.iter().all(|ess| *ess >= 1.0 && *ess <= 2000.0 + 1e-6));
+    assert!(filter.resample_count > 0);
+    //the same seed gives the same estimate even though particles are propagated in parallel
+    assert_eq!(run(5).0.states, estimate.states);
+    //no particles or an exact measurement cannot be weighted
+    assert!(ParticleFilter::new(model, 0, [PI, 0.0], [0.1, 0.3], Resampling::Systematic, 5).is_err());
+    let exact = FilterModel {measurement_noise : 0.0, ..model};
+    assert!(ParticleFilter::new(exact, 2000, [PI, 0.0], [0.1, 0.3], Resampling::Systematic, 5).is_err());
+}
@@ -1,54 +1,239 @@
+use crate::math::eom;
+use sim_common::plot_2d::{auto_range, gradient_colors, PlotBackend, PlotOptions};
+use sim_common::trajectory::Trajectory;
+use plotters::coord::Shift;
 use plotters::prelude::*;
+use std::error::Error;
+use std::f64::consts::PI;
 
-pub fn plot_theta_vecs(time_values : &Vec<f64>, theta_values: &Vec<Vec<f64>>, titles : &Vec<&str>) -> Result<(), Box<dyn std::error::Error>> {
+pub fn plot_theta_vecs(time_values : &[f64], theta_values: &[Vec<f64>], titles : &[&str], options : &PlotOptions) -> Result<(), Box<dyn Error>> {
     //make sure that the length of element in theta_values is the same as length of time_values
-        for i in 0..theta_values.len(){
-            assert_eq!(theta_values[i].len(), time_values.len());
+    if time_values.is_empty() {
+        return Err("time_values is empty".into());
+    }
+    for (i, theta_vec) in theta_values.iter().enumerate() {
+        if theta_vec.len() != time_values.len() {
+            return Err(format!("theta_values[{}] has {} values but time_values has {}",
+                i, theta_vec.len(), time_values.len()).into());
         }
-    let root = SVGBackend::new("plot.svg", (640, 480)).into_drawing_area();
+    }
+    if titles.len() != theta_values.len() {
+        return Err(format!("got {} titles for {} series", titles.len(), theta_values.len()).into());
+    }
 
+    match options.backend()? {
+        PlotBackend::Svg => {
+            let root = SVGBackend::new(&options.path, options.size).into_drawing_area();
+            draw_theta_vecs(&root, time_values, theta_values, titles, options)
+        },
+        PlotBackend::Png => {
+            let root = BitMapBackend::new(&options.path, options.size).into_drawing_area();
+            draw_theta_vecs(&root, time_values, theta_values, titles, options)
+        },
+    }
+}
+
+fn draw_theta_vecs<DB : DrawingBackend>(root : &DrawingArea<DB, Shift>, time_values : &[f64], theta_values: &[Vec<f64>], titles : &[&str], options : &PlotOptions) -> Result<(), Box<dyn Error>>
+where DB::ErrorType : 'static {
     root.fill(&WHITE)?;
 
-    let mut chart = ChartBuilder::on(&root)
-        .caption("Pendulum Motion", ("sans-serif", 40).into_font())
+    let x_range = match options.x_range {
+        Some(range) => range,
+        None => (time_values[0], time_values[time_values.len() -1]),
+    };
+    let y_range = match options.y_range {
+        Some(range) => range,
+        None => auto_range(theta_values.iter().flatten().copied()),
+    };
+
+    let mut chart = ChartBuilder::on(root)
+        .caption(&options.title, ("sans-serif", 40).into_font())
         .margin(10)
         .x_label_area_size(30)
         .y_label_area_size(30)
-        .build_cartesian_2d(time_values[0]..time_values[time_values.len() -1], -3f64..3f64)?;
+        .build_cartesian_2d(x_range.0..x_range.1, y_range.0..y_range.1)?;
 
     chart.configure_mesh()
-        .x_desc("Time (s)")
-        .y_desc("Angle (rad)")
+        .x_desc(&options.x_label)
+        .y_desc(&options.y_label)
         .draw()?;
 
-    let n = theta_values.len();
-    let c0_t = (255.0, 0.0, 0.0);
-    let cn_t = (0.0, 0.0, 255.0);
-    let colors: Vec<RGBColor> = (0..n).map(|i| {
-        let ratio = i as f64 / n as f64;
-        let r = c0_t.0 + (cn_t.0 - c0_t.0) * ratio;
-        let g = c0_t.1 + (cn_t.1 - c0_t.1) * ratio;
-        let b = c0_t.2 + (cn_t.2 - c0_t.2) * ratio;
-        RGBColor(r as u8, g as u8, b as u8)
-    }).collect();
-
-    for i in 0..n{
-        let color = colors[i].clone();
-        chart.draw_series(LineSeries::new(
-            time_values.iter().zip(theta_values[i].iter()).map(|(x, y)| (*x, *y)),
-            color,
-        ))?.label(titles[i])
-        .legend(move |(x, y)| PathElement::new(vec![(x, y), (x + 20, y)], &color));
-    }
-    
+    let colors = gradient_colors(theta_values.len());
+    for (i, theta_vec) in theta_values.iter().enumerate() {
+        let line_style = options.line_styles.get(i).copied().unwrap_or_default();
+        let color = line_style.color.unwrap_or(colors[i]);
+        let style = color.stroke_width(line_style.width);
+        let points = time_values.iter().zip(theta_vec.iter()).map(|(x, y)| (*x, *y));
+        let series = if line_style.dashed {
+            chart.draw_series(DashedLineSeries::new(points, 5, 5, style))?
+        } else {
+            chart.draw_series(LineSeries::new(points, style))?
+        };
+        series.label(titles[i])
+            .legend(move |(x, y)| PathElement::new(vec![(x, y), (x + 20, y)], style));
+    }
+
     chart.configure_series_labels()
         .position(SeriesLabelPosition::UpperRight)
-        .border_style(&BLACK)
-        .background_style(&WHITE.mix(0.8))
-        .draw()
-        .unwrap();
+        .border_style(BLACK)
+        .background_style(WHITE.mix(0.8))
+        .draw()?;
 
     root.present()?;
 
     Ok(())
 }
+
+const QUIVER_GRID : usize = 25; //arrows per axis in the direction field
+
+pub fn plot_phase_portrait(trajectories : &[Trajectory], titles : &[&str], l : f64, g : f64, b : f64, options : &PlotOptions) -> Result<(), Box<dyn Error>> {
+    /*
+    draws (theta, d_theta) of every trajectory over the direction field of the pendulum EOM
+    the undamped separatrix through theta = ±pi is overlaid as a dashed line
+     */
+    if titles.len() != trajectories.len() {
+        return Err(format!("got {} titles for {} trajectories", titles.len(), trajectories.len()).into());
+    }
+    let mut phase_curves = Vec::with_capacity(trajectories.len());
+    for (trajectory, title) in trajectories.iter().zip(titles) {
+        let theta = trajectory.series("theta").ok_or(format!("trajectory '{}' has no 'theta' state", title))?;
+        let d_theta = trajectory.series("d_theta").ok_or(format!("trajectory '{}' has no 'd_theta' state", title))?;
+        phase_curves.push(theta.into_iter().zip(d_theta).collect::<Vec<(f64, f64)>>());
+    }
+
+    match options.backend()? {
+        PlotBackend::Svg => {
+            let root = SVGBackend::new(&options.path, options.size).into_drawing_area();
+            draw_phase_portrait(&root, &phase_curves, titles, l, g, b, options)
+        },
+        PlotBackend::Png => {
+            let root = BitMapBackend::new(&options.path, options.size).into_drawing_area();
+            draw_phase_portrait(&root, &phase_curves, titles, l, g, b, options)
+        },
+    }
+}
+
+pub fn separatrix(theta : f64, l : f64, g : f64) -> f64 {
+    //positive branch of the undamped separatrix, the curve with the energy of the upright position
+    2.0 * (g / l).sqrt() * (theta / 2.0).cos().abs()
+}
+
+fn draw_phase_portrait<DB : DrawingBackend>(root : &DrawingArea<DB, Shift>, phase_curves : &[Vec<(f64, f64)>], titles : &[&str], l : f64, g : f64, b : f64, options : &PlotOptions) -> Result<(), Box<dyn Error>>
+where DB::ErrorType : 'static {
+    root.fill(&WHITE)?;
+
+    //by default show at least one full turn and the whole separatrix
+    let separatrix_max = separatrix(0.0, l, g);
+    let x_range = match options.x_range {
+        Some(range) => range,
+        None => auto_range(phase_curves.iter().flatten().map(|p| p.0).chain([-PI, PI])),
+    };
+    let y_range = match options.y_range {
+        Some(range) => range,
+        None => auto_range(phase_curves.iter().flatten().map(|p| p.1).chain([-separatrix_max, separatrix_max])),
+    };
+
+    let mut chart = ChartBuilder::on(root)
+        .caption(&options.title, ("sans-serif", 40).into_font())
+        .margin(10)
+        .x_label_area_size(30)
+        .y_label_area_size(40)
+        .build_cartesian_2d(x_range.0..x_range.1, y_range.0..y_range.1)?;
+
+    chart.configure_mesh()
+        .x_desc(&options.x_label)
+        .y_desc(&options.y_label)
+        .draw()?;
+
+    //direction field, arrows normalized to the grid cell so both axes look alike
+    let dx = (x_range.1 - x_range.0) / QUIVER_GRID as f64;
+    let dy = (y_range.1 - y_range.0) / QUIVER_GRID as f64;
+    let arrow_color = RGBColor(150, 150, 150);
+    for i in 0..QUIVER_GRID {
+        for j in 0..QUIVER_GRID {
+            let x = x_range.0 + (i as f64 + 0.5) * dx;
+            let y = y_range.0 + (j as f64 + 0.5) * dy;
+            let x_dot = eom(&[x, y], l, g, b);
+            let (u, v) = (x_dot[0] / dx, x_dot[1] / dy);
+            let norm = u.hypot(v);
+            if norm == 0.0 {
+                continue;
+            }
+            let (u, v) = (u / norm, v / norm);
+            let tail = (x - 0.4 * dx * u, y - 0.4 * dy * v);
+            let head = (x + 0.4 * dx * u, y + 0.4 * dy * v);
+            //arrow head sides are the direction rotated by ±150 degrees
+            let side = |angle : f64| {
+                let (sin, cos) = angle.sin_cos();
+                (head.0 + 0.3 * dx * (u * cos - v * sin), head.1 + 0.3 * dy * (u * sin + v * cos))
+            };
+            chart.draw_series(std::iter::once(PathElement::new(
+                vec![tail, head, side(5.0 * PI / 6.0), head, side(-5.0 * PI / 6.0)], arrow_color)))?;
+        }
+    }
+
+    //separatrix
+    let n_points = 400;
+    let separatrix_style = BLACK.stroke_width(2);
+    for sign in [1.0, -1.0] {
+        let points : Vec<(f64, f64)> = (0..=n_points)
+            .map(|k| x_range.0 + (x_range.1 - x_range.0) * k as f64 / n_points as f64)
+            .map(|x| (x, sign * separatrix(x, l, g)))
+            .collect();
+        let series = chart.draw_series(DashedLineSeries::new(points, 8, 4, separatrix_style))?;
+        if sign > 0.0 {
+            series.label("separatrix")
+                .legend(move |(x, y)| PathElement::new(vec![(x, y), (x + 20, y)], separatrix_style));
+        }
+    }
+
+    let colors = gradient_colors(phase_curves.len());
+    for (i, curve) in phase_curves.iter().enumerate() {
+        let line_style = options.line_styles.get(i).copied().unwrap_or_default();
+        let style = line_style.color.unwrap_or(colors[i]).stroke_width(line_style.width);
+        let series = if line_style.dashed {
+            chart.draw_series(DashedLineSeries::new(curve.iter().copied(), 5, 5, style))?
+        } else {
+            chart.draw_series(LineSeries::new(curve.iter().copied(), style))?
+        };
+        series.label(titles[i])
+            .legend(move |(x, y)| PathElement::new(vec![(x, y), (x + 20, y)], style));
+        //mark the initial condition
+        if let Some(start) = curve.first() {
+            chart.draw_series(std::iter::once(Circle::new(*start, 4, style.filled())))?;
+        }
+    }
+
+    chart.configure_series_labels()
+        .position(SeriesLabelPosition::UpperRight)
+        .border_style(BLACK)
+        .background_style(WHITE.mix(0.8))
+        .draw()?;
+
+    root.present()?;
+
+    Ok(())
+}
+
+#[test]
+fn test_plot_theta_vecs_errors() {
+    let options = PlotOptions {path : std::env::temp_dir().join("pendulum_test_plot.svg").to_str().unwrap().to_string(), ..Default::default()};
+    let time_values = vec![0.0, 1.0, 2.0];
+    assert!(plot_theta_vecs(&time_values, &[vec![0.0, 1.0]], &["short"], &options).is_err());
+    assert!(plot_theta_vecs(&time_values, &[vec![0.0, 1.0, 2.0]], &[], &options).is_err());
+    assert!(plot_theta_vecs(&time_values, &[vec![0.0, 5.0, -5.0]], &["ok"], &options).is_ok());
+    std::fs::remove_file(&options.path).unwrap();
+    //the backend always follows the path, so a .jpg path fails instead of writing svg into it
+    let options = PlotOptions {path : "plot.jpg".to_string(), ..Default::default()};
+    assert!(plot_theta_vecs(&time_values, &[vec![0.0, 1.0, 2.0]], &["ok"], &options).is_err());
+}
+
+#[test]
+fn test_separatrix() {
+    //the separatrix has the energy of the upright position everywhere
+    let (l, g) = (2.0, 9.81);
+    for theta in [-3.0, -1.0, 0.0, 0.5, 2.5, 4.0] {
+        let e = crate::math::energy(&[theta, separatrix(theta, l, g)], l, g);
+        assert!((e - 2.0 * g * l).abs() < 1e-9);
+    }
+}
@@ -0,0 +1,169 @@
+use crate::math::energy;
+use sim_common::math::lerp1d;
+use sim_common::plot_2d::{auto_range, gradient_colors, PlotBackend, PlotOptions};
+use sim_common::trajectory::Trajectory;
+use plotters::coord::Shift;
+use plotters::prelude::*;
+use std::error::Error;
+
+struct Panel {
+    y_label : String,
+    series : Vec<(usize, Vec<(f64, f64)>)>, //(trajectory index, (t, value)) for every trajectory shown in the panel
+}
+
+pub fn plot_diagnostics(trajectories : &[Trajectory], titles : &[&str], reference : usize, options : &PlotOptions) -> Result<(), Box<dyn Error>> {
+    /*
+    stacked panels sharing the time axis:
+    theta, d_theta, total energy and theta minus the theta of trajectories[reference]
+    energy is taken from the derived "energy" series, or computed from the l and g parameters
+    options.y_range is ignored, every panel fits its own data
+     */
+    if trajectories.is_empty() {
+        return Err("no trajectories to plot".into());
+    }
+    if titles.len() != trajectories.len() {
+        return Err(format!("got {} titles for {} trajectories", titles.len(), trajectories.len()).into());
+    }
+    if reference >= trajectories.len() {
+        return Err(format!("reference index {} out of range for {} trajectories", reference, trajectories.len()).into());
+    }
+    if let Some(i) = trajectories.iter().position(|trajectory| trajectory.is_empty()) {
+        return Err(format!("trajectory '{}' is empty", titles[i]).into());
+    }
+
+    let mut thetas = Vec::with_capacity(trajectories.len());
+    let mut d_thetas = Vec::with_capacity(trajectories.len());
+    let mut energies = Vec::with_capacity(trajectories.len());
+    for (trajectory, title) in trajectories.iter().zip(titles) {
+        thetas.push(trajectory.series("theta").ok_or(format!("trajectory '{}' has no 'theta' state", title))?);
+        d_thetas.push(trajectory.series("d_theta").ok_or(format!("trajectory '{}' has no 'd_theta' state", title))?);
+        energies.push(total_energy(trajectory).ok_or(format!("trajectory '{}' has no energy and no 'l' and 'g' parameters", title))?);
+    }
+
+    let with_times = |trajectory : &Trajectory, values : &[f64]| -> Vec<(f64, f64)> {
+        trajectory.times.iter().copied().zip(values.iter().copied()).collect()
+    };
+    let panel = |y_label : &str, values : &[Vec<f64>]| Panel {
+        y_label : y_label.to_string(),
+        series : trajectories.iter().zip(values).enumerate()
+            .map(|(i, (trajectory, values))| (i, with_times(trajectory, values)))
+            .collect(),
+    };
+
+    //reference theta is interpolated onto the times of every other trajectory
+    let reference_trajectory = &trajectories[reference];
+    let error_panel = Panel {
+        y_label : format!("Angle - {} (rad)", titles[reference]),
+        series : trajectories.iter().zip(&thetas).enumerate()
+            .filter(|(i, _)| *i != reference)
+            .map(|(i, (trajectory, theta))| {
+                let error = trajectory.times.iter().zip(theta)
+                    .map(|(t, theta)| (*t, theta - lerp1d(*t, &reference_trajectory.times, &thetas[reference])))
+                    .collect();
+                (i, error)
+            })
+            .collect(),
+    };
+
+    let panels = vec![
+        panel("Angle (rad)", &thetas),
+        panel("Angular velocity (rad/s)", &d_thetas),
+        panel("Energy (J/kg)", &energies),
+        error_panel,
+    ];
+
+    match options.backend()? {
+        PlotBackend::Svg => {
+            let root = SVGBackend::new(&options.path, options.size).into_drawing_area();
+            draw_panels(&root, &panels, trajectories, titles, options)
+        },
+        PlotBackend::Png => {
+            let root = BitMapBackend::new(&options.path, options.size).into_drawing_area();
+            draw_panels(&root, &panels, trajectories, titles, options)
+        },
+    }
+}
+
+fn total_energy(trajectory : &Trajectory) -> Option<Vec<f64>> {
+    if let Some(values) = trajectory.derived.get("energy") {
+        return Some(values.clone());
+    }
+    let l = *trajectory.metadata.parameters.get("l")?;
+    let g = *trajectory.metadata.parameters.get("g")?;
+    Some(trajectory.map_states(|x| energy(x, l, g)))
+}
+
+fn draw_panels<DB : DrawingBackend>(root : &DrawingArea<DB, Shift>, panels : &[Panel], trajectories : &[Trajectory], titles : &[&str], options : &PlotOptions) -> Result<(), Box<dyn Error>>
+where DB::ErrorType : 'static {
+    root.fill(&WHITE)?;
+    let root = root.titled(&options.title, ("sans-serif", 30).into_font())?;
+
+    //all panels share the time axis of the longest trajectory
+    let x_range = match options.x_range {
+        Some(range) => range,
+        None => {
+            let t0 = trajectories.iter().map(|t| t.times[0]).fold(f64::INFINITY, f64::min);
+            let t1 = trajectories.iter().map(|t| t.times[t.len() - 1]).fold(f64::NEG_INFINITY, f64::max);
+            (t0, t1)
+        },
+    };
+    let colors = gradient_colors(trajectories.len());
+    let areas = root.split_evenly((panels.len(), 1));
+
+    for (k, (area, panel)) in areas.iter().zip(panels).enumerate() {
+        let is_bottom = k == panels.len() - 1;
+        let y_range = auto_range(panel.series.iter().flat_map(|(_, points)| points.iter().map(|p| p.1)));
+        let mut chart = ChartBuilder::on(area)
+            .margin(5)
+            .x_label_area_size(if is_bottom {30} else {15})
+            .y_label_area_size(50)
+            .build_cartesian_2d(x_range.0..x_range.1, y_range.0..y_range.1)?;
+
+        let mut mesh = chart.configure_mesh();
+        mesh.y_desc(&panel.y_label);
+        if is_bottom {
+            mesh.x_desc(&options.x_label);
+        }
+        mesh.draw()?;
+
+        for (i, points) in &panel.series {
+            let i = *i;
+            let line_style = options.line_styles.get(i).copied().unwrap_or_default();
+            let style = line_style.color.unwrap_or(colors[i]).stroke_width(line_style.width);
+            let series = if line_style.dashed {
+                chart.draw_series(DashedLineSeries::new(points.iter().copied(), 5, 5, style))?
+            } else {
+                chart.draw_series(LineSeries::new(points.iter().copied(), style))?
+            };
+            series.label(titles[i])
+                .legend(move |(x, y)| PathElement::new(vec![(x, y), (x + 20, y)], style));
+        }
+
+        chart.configure_series_labels()
+            .position(SeriesLabelPosition::UpperRight)
+            .border_style(BLACK)
+            .background_style(WHITE.mix(0.8))
+            .draw()?;
+    }
+
+    root.present()?;
+
+    Ok(())
+}
+
+#[test]
+fn test_plot_diagnostics_errors() {
+    use sim_common::trajectory::Metadata;
+    let metadata = Metadata::new("pendulum", "euler", 0.1).with_parameter("l", 1.0).with_parameter("g", 9.81);
+    let mut trajectory = Trajectory::new(metadata, &["theta", "d_theta"]);
+    for i in 0..10 {
+        trajectory.push(0.1 * i as f64, &[0.1 * i as f64, 1.0]);
+    }
+    let path = std::env::temp_dir().join(format!("pendulum_diagnostics_{}.svg", std::process::id()));
+    let options = PlotOptions {path : path.to_str().unwrap().to_string(), ..Default::default()};
+    let trajectories = vec![trajectory.clone(), trajectory];
+    assert!(plot_diagnostics(&trajectories, &["a"], 0, &options).is_err());
+    assert!(plot_diagnostics(&trajectories, &["a", "b"], 2, &options).is_err());
+    assert!(plot_diagnostics(&trajectories, &["a", "b"], 1, &options).is_ok());
+    std::fs::remove_file(&options.path).unwrap();
+}
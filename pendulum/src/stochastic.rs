@@ -0,0 +1,45 @@
+use crate::math::eom;
+use sim_common::stochastic::{Noise, SdeScheme, StochasticIntegrator};
+
+pub fn make_stochastic_pendulum(l : f64, g : f64, b : f64, dt : f64, noise : Noise, scheme : SdeScheme, seed : u64) -> StochasticIntegrator<impl Fn(f64, &[f64]) -> Vec<f64>> {
+    //the pendulum eom as the drift, noise is a state sized [theta, d_theta] description
+    StochasticIntegrator::new(dt, move |_t, x| eom(x, l, g, b), noise, scheme, seed)
+}
+
+pub fn thermal_noise(l : f64, b : f64, temperature : f64) -> Noise {
+    /*
+    additive angular acceleration noise in balance with the damping b (fluctuation dissipation)
+    temperature is kT/m in J/kg, so in equilibrium the mean kinetic energy per unit mass 0.5 (l d_theta)^2 is temperature / 2
+     */
+    Noise::additive(&[0.0, (2.0 * b * temperature).sqrt() / l])
+}
+
+pub fn first_passage_time(times : &[f64], values : &[f64], threshold : f64) -> Option<f64> {
+    //first time |value| reaches threshold, e.g. pi for an escape over the upright position
+    times.iter().zip(values).find(|(_, value)| value.abs() >= threshold).map(|(t, _)| *t)
+}
+
+#[test]
+fn test_thermal_pendulum_equipartition() {
+    //small oscillations at temperature T share it equally, <(l d_theta)^2> = <g l theta^2> = T
+    let (l, g, b, temperature, dt) = (1.0, 9.81, 1.0, 0.01, 0.01);
+    let mut pendulum = make_stochastic_pendulum(l, g, b, dt, thermal_noise(l, b, temperature), SdeScheme::Heun, 3);
+    let mut x = vec![0.0, 0.0];
+    let (mut kinetic, mut potential) = (0.0, 0.0);
+    let steps = 200000;
+    for i in 0..steps {
+        x = pendulum.propogate(i as f64 * dt, &x);
+        kinetic += (l * x[1]).powi(2);
+        potential += g * l * x[0].powi(2);
+    }
+    assert!((kinetic / steps as f64 - temperature).abs() < 0.1 * temperature);
+    assert!((potential / steps as f64 - temperature).abs() < 0.1 * temperature);
+
+    //the same seed replays the same path
+    let run = || {
+        let mut pendulum = make_stochastic_pendulum(l, g, b, dt, thermal_noise(l, b, temperature), SdeScheme::EulerMaruyama, 9);
+        (0..10).fold(vec![0.1, 0.0], |x, i| pendulum.propogate(i as f64 * dt, &x))
+    };
+    assert_eq!(run(), run());
+    assert_eq!(first_passage_time(&[0.0, 1.0, 2.0], &[0.0, -3.5, 1.0], 3.0), Some(1.0));
+}
@@ -0,0 +1,6 @@
+pub mod math;
+pub mod trajectory;
+pub mod trajectory_io;
+pub mod plot_2d;
+pub mod spectrum;
+pub mod stochastic;
@@ -0,0 +1,45 @@
+pub fn lerp1d<T>(x : T, x_vec : &[T], y_vec : &[T]) -> T
+    where T: std::ops::Add<Output = T>
+            + std::ops::Sub<Output = T> 
+            + std::ops::Div<Output = T> 
+            + std::ops::Mul<Output = T> 
+            + std::cmp::PartialOrd
+            + std::marker::Copy {
+    //find the two points in x_vec that are closest to x
+    //interpolate between the two points
+    //return the interpolated value
+
+    if x < x_vec[0] {
+        y_vec[0]
+    }
+    else if x > x_vec[x_vec.len()-1] {
+        y_vec[y_vec.len()-1]
+    }
+    else
+    {
+    //find the two points in x_vec that are closest to x
+    //use binary search because x_vec is sorted
+    let i = match x_vec.binary_search_by(|&probe| probe.partial_cmp(&x).unwrap()) {
+        Ok(index) => return y_vec[index],
+        Err(index) => index,
+    };
+    let x0 = x_vec[i-1];
+    let x1 = x_vec[i];
+    let y0 = y_vec[i-1];
+    let y1 = y_vec[i];
+
+    //interpolate between the two points
+    y0 + (y1 - y0) / (x1 - x0) * (x - x0)
+    }
+}
+
+#[test]
+fn test_lerp1d() {
+    let x_vec = vec![0.0, 1.0, 2.0, 3.0];
+    let y_vec = vec![3.0, 2.0, 1.0, 0.0];
+    let x = 0.5;
+    let y = lerp1d(x, &x_vec, &y_vec);
+    assert!(y == 2.5);
+    assert!(lerp1d(0.0, &x_vec, &y_vec) == 3.0);
+    assert!(lerp1d(2.0, &x_vec, &y_vec) == 1.0);
+}
@@ -0,0 +1,114 @@
+use plotters::prelude::*;
+use std::error::Error;
+use std::path::Path;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PlotBackend {
+    Svg,
+    Png,
+}
+
+impl PlotBackend {
+    pub fn from_path(path : &str) -> Result<Self, Box<dyn Error>> {
+        //chooses the backend by file extension
+        let extension = Path::new(path).extension()
+            .map(|e| e.to_string_lossy().to_lowercase())
+            .unwrap_or_default();
+        match extension.as_str() {
+            "svg" => Ok(PlotBackend::Svg),
+            "png" => Ok(PlotBackend::Png),
+            _ => Err(format!("can not infer plot format from '{}', use .svg or .png", path).into()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LineStyle {
+    pub color : Option<RGBColor>, //None keeps the default red to blue gradient
+    pub width : u32,
+    pub dashed : bool,
+}
+
+impl Default for LineStyle {
+    fn default() -> Self {
+        LineStyle {color : None, width : 1, dashed : false}
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PlotOptions {
+    pub path : String, //.svg or .png, the extension chooses the backend
+    pub size : (u32, u32),
+    pub title : String,
+    pub x_label : String,
+    pub y_label : String,
+    pub x_range : Option<(f64, f64)>, //None fits the range to the data
+    pub y_range : Option<(f64, f64)>,
+    pub line_styles : Vec<LineStyle>, //one per series, series without an entry use the default style
+}
+
+impl Default for PlotOptions {
+    fn default() -> Self {
+        PlotOptions {
+            path : "plot.svg".to_string(),
+            size : (640, 480),
+            title : "".to_string(),
+            x_label : "Time (s)".to_string(),
+            y_label : "".to_string(),
+            x_range : None,
+            y_range : None,
+            line_styles : Vec::new(),
+        }
+    }
+}
+
+impl PlotOptions {
+    pub fn with_path(path : &str) -> Result<Self, Box<dyn Error>> {
+        //default options writing to path, fails for paths without a supported extension
+        PlotBackend::from_path(path)?;
+        Ok(PlotOptions {path : path.to_string(), ..Default::default()})
+    }
+
+    pub fn backend(&self) -> Result<PlotBackend, Box<dyn Error>> {
+        PlotBackend::from_path(&self.path)
+    }
+}
+
+pub fn gradient_colors(n : usize) -> Vec<RGBColor> {
+    //n colors going from red to blue
+    let c0_t = (255.0, 0.0, 0.0);
+    let cn_t = (0.0, 0.0, 255.0);
+    (0..n).map(|i| {
+        let ratio = i as f64 / n as f64;
+        let r = c0_t.0 + (cn_t.0 - c0_t.0) * ratio;
+        let g = c0_t.1 + (cn_t.1 - c0_t.1) * ratio;
+        let b = c0_t.2 + (cn_t.2 - c0_t.2) * ratio;
+        RGBColor(r as u8, g as u8, b as u8)
+    }).collect()
+}
+
+pub fn auto_range<I : Iterator<Item = f64>>(values : I) -> (f64, f64) {
+    //range of the finite values with a 5% margin, (-1, 1) when there are none
+    let (min, max) = values.filter(|v| v.is_finite())
+        .fold((f64::INFINITY, f64::NEG_INFINITY), |(min, max), v| (min.min(v), max.max(v)));
+    if min > max {
+        return (-1.0, 1.0);
+    }
+    let margin = if max > min {0.05 * (max - min)} else {1.0};
+    (min - margin, max + margin)
+}
+
+#[test]
+fn test_plot_backend_from_path() {
+    assert!(PlotBackend::from_path("plot.jpg").is_err());
+    assert_eq!(PlotBackend::from_path("plot.PNG").unwrap(), PlotBackend::Png);
+    assert_eq!(PlotOptions {path : "run.png".to_string(), ..Default::default()}.backend().unwrap(), PlotBackend::Png);
+    assert!(PlotOptions::with_path("plot").is_err());
+}
+
+#[test]
+fn test_auto_range() {
+    assert_eq!(auto_range([0.0, 10.0, f64::NAN].into_iter()), (-0.5, 10.5));
+    assert_eq!(auto_range([2.0].into_iter()), (1.0, 3.0));
+    assert_eq!(auto_range(std::iter::empty()), (-1.0, 1.0));
+}
@@ -0,0 +1,244 @@
+use crate::math::lerp1d;
+use crate::plot_2d::{auto_range, gradient_colors, PlotBackend, PlotOptions};
+use crate::trajectory::Trajectory;
+use plotters::coord::Shift;
+use plotters::prelude::*;
+use rustfft::{num_complex::Complex, FftPlanner};
+use std::error::Error;
+use std::f64::consts::PI;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Window {
+    Rectangular,
+    Hann,
+    Hamming,
+    Blackman,
+}
+
+impl Window {
+    pub fn coefficients(&self, n : usize) -> Vec<f64> {
+        if n < 2 {
+            return vec![1.0; n];
+        }
+        (0..n).map(|i| {
+            let x = 2.0 * PI * i as f64 / (n - 1) as f64;
+            match self {
+                Window::Rectangular => 1.0,
+                Window::Hann => 0.5 - 0.5 * x.cos(),
+                Window::Hamming => 0.54 - 0.46 * x.cos(),
+                Window::Blackman => 0.42 - 0.5 * x.cos() + 0.08 * (2.0 * x).cos(),
+            }
+        }).collect()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Spectrum {
+    pub frequencies : Vec<f64>, //Hz, from 0 to the nyquist frequency
+    pub power : Vec<f64>,
+}
+
+impl Spectrum {
+    pub fn peaks(&self, count : usize) -> Vec<(f64, f64)> {
+        /*
+        the strongest local maxima as (frequency, power), strongest first
+        the frequency is refined by fitting a parabola through the peak bin and its neighbors
+         */
+        let p = &self.power;
+        let mut peaks : Vec<(f64, f64)> = (1..p.len().saturating_sub(1))
+            .filter(|&i| p[i] > p[i - 1] && p[i] >= p[i + 1])
+            .map(|i| {
+                let denominator = p[i - 1] - 2.0 * p[i] + p[i + 1];
+                let offset = if denominator != 0.0 {0.5 * (p[i - 1] - p[i + 1]) / denominator} else {0.0};
+                let df = self.frequencies[1] - self.frequencies[0];
+                (self.frequencies[i] + offset * df, p[i])
+            })
+            .collect();
+        peaks.sort_by(|a, b| b.1.total_cmp(&a.1));
+        peaks.truncate(count);
+        peaks
+    }
+
+    pub fn dominant_frequency(&self) -> Option<f64> {
+        self.peaks(1).first().map(|peak| peak.0)
+    }
+}
+
+pub fn is_uniform(times : &[f64], tolerance : f64) -> bool {
+    //true if every step differs from the mean step by less than tolerance (relative)
+    if times.len() < 2 {
+        return true;
+    }
+    let dt = (times[times.len() - 1] - times[0]) / (times.len() - 1) as f64;
+    times.windows(2).all(|w| ((w[1] - w[0]) - dt).abs() <= tolerance * dt.abs())
+}
+
+pub fn resample(times : &[f64], values : &[f64], dt : f64) -> Result<(Vec<f64>, Vec<f64>), Box<dyn Error>> {
+    //linear interpolation onto a uniform grid with step dt
+    if !dt.is_finite() || dt <= 0.0 {
+        return Err(format!("resample step must be positive, got {}", dt).into());
+    }
+    if times.is_empty() || times.len() != values.len() {
+        return Err(format!("got {} times for {} values", times.len(), values.len()).into());
+    }
+    let n = ((times[times.len() - 1] - times[0]) / dt).floor() as usize + 1;
+    let new_times : Vec<f64> = (0..n).map(|i| times[0] + i as f64 * dt).collect();
+    let new_values = new_times.iter().map(|t| lerp1d(*t, times, values)).collect();
+    Ok((new_times, new_values))
+}
+
+pub fn power_spectrum(values : &[f64], dt : f64, window : Window) -> Spectrum {
+    /*
+    one sided power spectrum of a uniformly sampled signal
+    the mean is removed before windowing so the zero frequency bin does not hide the peaks
+     */
+    let n = values.len();
+    let mean = values.iter().sum::<f64>() / n.max(1) as f64;
+    let window = window.coefficients(n);
+    let window_power : f64 = window.iter().map(|w| w * w).sum::<f64>().max(f64::MIN_POSITIVE);
+
+    let mut buffer : Vec<Complex<f64>> = values.iter().zip(&window)
+        .map(|(v, w)| Complex::new((v - mean) * w, 0.0))
+        .collect();
+    FftPlanner::new().plan_fft_forward(n).process(&mut buffer);
+
+    let n_bins = n / 2 + 1;
+    let frequencies = (0..n_bins).map(|k| k as f64 / (n as f64 * dt)).collect();
+    let power = buffer.iter().take(n_bins).enumerate().map(|(k, c)| {
+        //fold the negative frequencies into the positive ones
+        let scale = if k == 0 || 2 * k == n {1.0} else {2.0};
+        scale * c.norm_sqr() / window_power
+    }).collect();
+    Spectrum {frequencies, power}
+}
+
+pub fn trajectory_spectrum(trajectory : &Trajectory, name : &str, resample_dt : Option<f64>, window : Window) -> Result<Spectrum, Box<dyn Error>> {
+    //power spectrum of a state or derived series, resampled first if resample_dt is given
+    let values = trajectory.series(name).ok_or(format!("trajectory has no series '{}'", name))?;
+    if trajectory.len() < 4 {
+        return Err("at least 4 samples are needed for a spectrum".into());
+    }
+    match resample_dt {
+        Some(dt) => {
+            let (_, values) = resample(&trajectory.times, &values, dt)?;
+            Ok(power_spectrum(&values, dt, window))
+        },
+        None => {
+            if !is_uniform(&trajectory.times, 1e-6) {
+                return Err("trajectory is not uniformly sampled, pass a resample step".into());
+            }
+            let dt = (trajectory.times[trajectory.len() - 1] - trajectory.times[0]) / (trajectory.len() - 1) as f64;
+            Ok(power_spectrum(&values, dt, window))
+        },
+    }
+}
+
+pub fn plot_spectrum(spectra : &[Spectrum], titles : &[&str], markers : &[(f64, &str)], options : &PlotOptions) -> Result<(), Box<dyn Error>> {
+    /*
+    power against frequency on a log scale
+    markers are vertical lines at (frequency, label), e.g. sqrt(g/l)/(2 pi) for the pendulum
+     */
+    if titles.len() != spectra.len() {
+        return Err(format!("got {} titles for {} spectra", titles.len(), spectra.len()).into());
+    }
+    if spectra.iter().any(|spectrum| spectrum.frequencies.len() < 2) {
+        return Err("spectra need at least two frequency bins".into());
+    }
+    match options.backend()? {
+        PlotBackend::Svg => {
+            let root = SVGBackend::new(&options.path, options.size).into_drawing_area();
+            draw_spectrum(&root, spectra, titles, markers, options)
+        },
+        PlotBackend::Png => {
+            let root = BitMapBackend::new(&options.path, options.size).into_drawing_area();
+            draw_spectrum(&root, spectra, titles, markers, options)
+        },
+    }
+}
+
+fn draw_spectrum<DB : DrawingBackend>(root : &DrawingArea<DB, Shift>, spectra : &[Spectrum], titles : &[&str], markers : &[(f64, &str)], options : &PlotOptions) -> Result<(), Box<dyn Error>>
+where DB::ErrorType : 'static {
+    root.fill(&WHITE)?;
+
+    //the zero frequency bin is skipped, it only holds what is left of the mean
+    let x_range = match options.x_range {
+        Some(range) => range,
+        None => (0.0, spectra.iter().map(|s| s.frequencies[s.frequencies.len() - 1]).fold(0.0, f64::max)),
+    };
+    let y_range = match options.y_range {
+        Some(range) => range,
+        None => {
+            let (min, max) = auto_range(spectra.iter().flat_map(|s| s.power.iter().skip(1).copied()).filter(|p| *p > 0.0));
+            (min.max(max * 1e-12).max(f64::MIN_POSITIVE), max)
+        },
+    };
+
+    let mut chart = ChartBuilder::on(root)
+        .caption(&options.title, ("sans-serif", 40).into_font())
+        .margin(10)
+        .x_label_area_size(30)
+        .y_label_area_size(60)
+        .build_cartesian_2d(x_range.0..x_range.1, (y_range.0..y_range.1).log_scale())?;
+
+    chart.configure_mesh()
+        .x_desc(&options.x_label)
+        .y_desc(&options.y_label)
+        .y_label_formatter(&|y| format!("{:.0e}", y))
+        .draw()?;
+
+    let colors = gradient_colors(spectra.len());
+    for (i, spectrum) in spectra.iter().enumerate() {
+        let line_style = options.line_styles.get(i).copied().unwrap_or_default();
+        let style = line_style.color.unwrap_or(colors[i]).stroke_width(line_style.width);
+        let points = spectrum.frequencies.iter().zip(&spectrum.power).skip(1)
+            .filter(|(_, p)| **p > 0.0)
+            .map(|(f, p)| (*f, *p));
+        chart.draw_series(LineSeries::new(points, style))?
+            .label(titles[i])
+            .legend(move |(x, y)| PathElement::new(vec![(x, y), (x + 20, y)], style));
+    }
+
+    for (frequency, label) in markers {
+        let style = BLACK.stroke_width(1);
+        chart.draw_series(DashedLineSeries::new(vec![(*frequency, y_range.0), (*frequency, y_range.1)], 6, 4, style))?
+            .label(*label)
+            .legend(move |(x, y)| PathElement::new(vec![(x, y), (x + 20, y)], style));
+    }
+
+    chart.configure_series_labels()
+        .position(SeriesLabelPosition::UpperRight)
+        .border_style(BLACK)
+        .background_style(WHITE.mix(0.8))
+        .draw()?;
+
+    root.present()?;
+
+    Ok(())
+}
+
+#[test]
+fn test_power_spectrum_peaks() {
+    //two sines, the stronger at 2 Hz, sampled at 50 Hz
+    let dt = 0.02;
+    let values : Vec<f64> = (0..1000)
+        .map(|i| i as f64 * dt)
+        .map(|t| 3.0 * (2.0 * PI * 2.0 * t).sin() + (2.0 * PI * 7.3 * t).sin() + 1.0)
+        .collect();
+    let spectrum = power_spectrum(&values, dt, Window::Hann);
+    assert_eq!(spectrum.frequencies.len(), 501);
+    assert!((spectrum.frequencies[500] - 25.0).abs() < 1e-12);
+    let peaks = spectrum.peaks(2);
+    assert!((peaks[0].0 - 2.0).abs() < 0.01);
+    assert!((peaks[1].0 - 7.3).abs() < 0.02);
+}
+
+#[test]
+fn test_resample() {
+    let (times, values) = resample(&[0.0, 1.0, 2.0], &[0.0, 2.0, 0.0], 0.5).unwrap();
+    assert_eq!(times, vec![0.0, 0.5, 1.0, 1.5, 2.0]);
+    assert_eq!(values, vec![0.0, 1.0, 2.0, 1.0, 0.0]);
+    for dt in [0.0, -0.1, f64::NAN, f64::INFINITY] {
+        assert!(resample(&[0.0, 1.0], &[0.0, 1.0], dt).is_err());
+    }
+    assert!(resample(&[0.0, 1.0], &[0.0], 0.5).is_err());
+}
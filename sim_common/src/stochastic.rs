@@ -0,0 +1,158 @@
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use rand_distr::StandardNormal;
+use serde::Deserialize;
+use std::str::FromStr;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum NoiseType {
+    Additive, //dW scaled by sigma
+    Multiplicative, //dW scaled by sigma * x
+}
+
+impl FromStr for NoiseType {
+    type Err = String;
+
+    fn from_str(s : &str) -> Result<Self, Self::Err> {
+        match s {
+            "additive" => Ok(NoiseType::Additive),
+            "multiplicative" => Ok(NoiseType::Multiplicative),
+            _ => Err(format!("expected additive or multiplicative, got '{}'", s)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Noise {
+    pub kind : NoiseType,
+    pub sigma : Vec<f64>, //one intensity per state component, zero for components without noise
+}
+
+impl Noise {
+    pub fn additive(sigma : &[f64]) -> Self {
+        Noise {kind : NoiseType::Additive, sigma : sigma.to_vec()}
+    }
+
+    pub fn multiplicative(sigma : &[f64]) -> Self {
+        Noise {kind : NoiseType::Multiplicative, sigma : sigma.to_vec()}
+    }
+
+    pub fn diffusion(&self, x : &[f64]) -> Vec<f64> {
+        //diagonal of G(x) in dx = f dt + G dW
+        match self.kind {
+            NoiseType::Additive => self.sigma.clone(),
+            NoiseType::Multiplicative => self.sigma.iter().zip(x).map(|(s, x)| s * x).collect(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SdeScheme {
+    EulerMaruyama, //strong order 0.5, converges to the ito solution
+    Heun, //predictor corrector, converges to the stratonovich solution (the same as ito for additive noise)
+}
+
+impl SdeScheme {
+    pub fn name(&self) -> &'static str {
+        match self {
+            SdeScheme::EulerMaruyama => "euler_maruyama",
+            SdeScheme::Heun => "heun",
+        }
+    }
+}
+
+pub fn stream_seed(seed : u64, stream : u64) -> u64 {
+    //splitmix64 of (seed, stream), so generators seeded from the same seed for different purposes do not share draws
+    let mut z = seed.wrapping_add(stream.wrapping_add(1).wrapping_mul(0x9e3779b97f4a7c15));
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+    z ^ (z >> 31)
+}
+
+pub struct StochasticIntegrator<F>
+where F : Fn(f64, &[f64]) -> Vec<f64> {
+    dt : f64,
+    f : F,
+    noise : Noise,
+    scheme : SdeScheme,
+    rng : StdRng,
+}
+
+impl<F> StochasticIntegrator<F>
+where F : Fn(f64, &[f64]) -> Vec<f64> {
+    pub fn new(dt : f64, f : F, noise : Noise, scheme : SdeScheme, seed : u64) -> Self {
+        StochasticIntegrator {dt, f, noise, scheme, rng : StdRng::seed_from_u64(seed)}
+    }
+
+    pub fn propogate(&mut self, t : f64, x : &[f64]) -> Vec<f64> {
+        //one step of dx = f(t, x) dt + G(x) dW with independent wiener increments per component
+        let n = x.len();
+        assert_eq!(self.noise.sigma.len(), n, "noise must have one sigma per state component");
+        let sqrt_dt = self.dt.sqrt();
+        let dw : Vec<f64> = (0..n).map(|_| sqrt_dt * self.rng.sample::<f64, _>(StandardNormal)).collect();
+
+        let drift = (self.f)(t, x);
+        let diffusion = self.noise.diffusion(x);
+        let euler : Vec<f64> = (0..n).map(|i| x[i] + drift[i] * self.dt + diffusion[i] * dw[i]).collect();
+        match self.scheme {
+            SdeScheme::EulerMaruyama => euler,
+            SdeScheme::Heun => {
+                let drift_predicted = (self.f)(t + self.dt, &euler);
+                let diffusion_predicted = self.noise.diffusion(&euler);
+                (0..n).map(|i| x[i]
+                    + 0.5 * (drift[i] + drift_predicted[i]) * self.dt
+                    + 0.5 * (diffusion[i] + diffusion_predicted[i]) * dw[i])
+                    .collect()
+            },
+        }
+    }
+}
+
+#[test]
+fn test_ornstein_uhlenbeck_variance() {
+    //dx = -x dt + sigma dW has stationary variance sigma^2 / 2
+    let sigma = 0.8;
+    for scheme in [SdeScheme::EulerMaruyama, SdeScheme::Heun] {
+        let mut integrator = StochasticIntegrator::new(0.01, |_t, x : &[f64]| vec![-x[0]], Noise::additive(&[sigma]), scheme, 42);
+        let mut x = vec![0.0];
+        let mut sum_squares = 0.0;
+        let steps = 200000;
+        for i in 0..steps {
+            x = integrator.propogate(i as f64 * 0.01, &x);
+            sum_squares += x[0] * x[0];
+        }
+        let variance = sum_squares / steps as f64;
+        assert!((variance - sigma * sigma / 2.0).abs() < 0.05 * sigma * sigma / 2.0);
+    }
+}
+
+#[test]
+fn test_multiplicative_noise_ito_and_stratonovich() {
+    //for dx = sigma x dW the ito mean stays at x0, the stratonovich mean grows like exp(sigma^2 t / 2)
+    let (sigma, dt, steps, paths) = (0.5, 0.01, 100, 4000);
+    let mean = |scheme : SdeScheme| {
+        let mut integrator = StochasticIntegrator::new(dt, |_t, x : &[f64]| vec![0.0; x.len()], Noise::multiplicative(&[sigma]), scheme, 1);
+        (0..paths).map(|_| {
+            let mut x = vec![1.0];
+            for i in 0..steps {
+                x = integrator.propogate(i as f64 * dt, &x);
+            }
+            x[0]
+        }).sum::<f64>() / paths as f64
+    };
+    let t = dt * steps as f64;
+    assert!((mean(SdeScheme::EulerMaruyama) - 1.0).abs() < 0.03);
+    assert!((mean(SdeScheme::Heun) - (0.5 * sigma * sigma * t).exp()).abs() < 0.03);
+}
+
+#[test]
+fn test_stream_seeds_differ() {
+    //streams of one seed, and the same stream of neighboring seeds, never collide with each other or the plain seeds
+    let mut seeds : Vec<u64> = (0..100).flat_map(|seed| (0..100).map(move |stream| stream_seed(seed, stream))).collect();
+    seeds.extend(0..10_000);
+    let count = seeds.len();
+    seeds.sort();
+    seeds.dedup();
+    assert_eq!(seeds.len(), count);
+    assert_eq!(stream_seed(3, 4), stream_seed(3, 4));
+}
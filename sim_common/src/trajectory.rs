@@ -0,0 +1,160 @@
+use std::collections::BTreeMap;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+pub struct Metadata {
+    pub model : String,
+    pub parameters : BTreeMap<String, f64>,
+    #[serde(default)]
+    pub labels : BTreeMap<String, String>, //settings that are not numbers, e.g. the name of a potential
+    pub integrator : String,
+    pub dt : f64,
+}
+
+impl Metadata {
+    pub fn new(model : &str, integrator : &str, dt : f64) -> Self {
+        Metadata {model : model.to_string(), parameters : BTreeMap::new(), labels : BTreeMap::new(), integrator : integrator.to_string(), dt}
+    }
+
+    pub fn with_parameter(mut self, name : &str, value : f64) -> Self {
+        self.parameters.insert(name.to_string(), value);
+        self
+    }
+
+    pub fn with_label(mut self, name : &str, value : &str) -> Self {
+        self.labels.insert(name.to_string(), value.to_string());
+        self
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+pub struct Trajectory {
+    pub metadata : Metadata,
+    pub state_names : Vec<String>,
+    pub times : Vec<f64>,
+    pub states : Vec<Vec<f64>>, //outer vector is time, inner vector is the full state
+    pub derived : BTreeMap<String, Vec<f64>>, //named quantities computed from the states, by time
+}
+
+impl Trajectory {
+    pub fn new<S : ToString>(metadata : Metadata, state_names : &[S]) -> Self {
+        Trajectory {
+            metadata,
+            state_names : state_names.iter().map(|s| s.to_string()).collect(),
+            ..Default::default()
+        }
+    }
+
+    pub fn push(&mut self, t : f64, state : &[f64]) {
+        assert_eq!(state.len(), self.state_names.len(), "state must have one value per state name");
+        assert!(self.derived.is_empty(), "push all states before adding derived quantities");
+        self.times.push(t);
+        self.states.push(state.to_vec());
+    }
+
+    pub fn len(&self) -> usize {
+        self.times.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.times.is_empty()
+    }
+
+    pub fn add_derived<F>(&mut self, name : &str, f : F)
+    where F : Fn(f64, &[f64]) -> f64 {
+        let values = self.iter().map(|(t, x)| f(t, x)).collect();
+        self.derived.insert(name.to_string(), values);
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (f64, &[f64])> {
+        self.times.iter().zip(self.states.iter()).map(|(t, x)| (*t, x.as_slice()))
+    }
+
+    pub fn state_index(&self, name : &str) -> Option<usize> {
+        self.state_names.iter().position(|s| s == name)
+    }
+
+    pub fn column(&self, index : usize) -> Vec<f64> {
+        self.states.iter().map(|x| x[index]).collect()
+    }
+
+    pub fn series(&self, name : &str) -> Option<Vec<f64>> {
+        //looks up a state by name first, then a derived quantity
+        match self.state_index(name) {
+            Some(index) => Some(self.column(index)),
+            None => self.derived.get(name).cloned(),
+        }
+    }
+
+    pub fn times_f32(&self) -> Vec<f32> {
+        self.times.iter().map(|t| *t as f32).collect()
+    }
+
+    pub fn map_states<T, F>(&self, f : F) -> Vec<T>
+    where F : Fn(&[f64]) -> T {
+        self.states.iter().map(|x| f(x)).collect()
+    }
+
+    pub fn slice_time(&self, t_start : f64, t_end : f64) -> Trajectory {
+        //keeps samples with t_start <= t <= t_end
+        let indices : Vec<usize> = (0..self.len())
+            .filter(|&i| self.times[i] >= t_start && self.times[i] <= t_end)
+            .collect();
+        self.select(&indices)
+    }
+
+    pub fn decimate(&self, step : usize) -> Trajectory {
+        //keeps every step-th sample, starting from the first
+        assert!(step > 0, "decimation step must be positive");
+        let indices : Vec<usize> = (0..self.len()).step_by(step).collect();
+        let mut trajectory = self.select(&indices);
+        trajectory.metadata.dt = self.metadata.dt * step as f64;
+        trajectory
+    }
+
+    fn select(&self, indices : &[usize]) -> Trajectory {
+        Trajectory {
+            metadata : self.metadata.clone(),
+            state_names : self.state_names.clone(),
+            times : indices.iter().map(|&i| self.times[i]).collect(),
+            states : indices.iter().map(|&i| self.states[i].clone()).collect(),
+            derived : self.derived.iter()
+                .map(|(name, values)| (name.clone(), indices.iter().map(|&i| values[i]).collect()))
+                .collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+fn make_test_trajectory() -> Trajectory {
+    let metadata = Metadata::new("sphere_springs", "rk4", 0.5).with_parameter("R", 1.0);
+    let mut trajectory = Trajectory::new(metadata, &["theta_0", "theta_dot_0"]);
+    for i in 0..10 {
+        let t = 0.5 * i as f64;
+        trajectory.push(t, &[t, -t]);
+    }
+    trajectory.add_derived("sum", |_t, x| x[0] + x[1]);
+    trajectory
+}
+
+#[test]
+fn test_trajectory_series() {
+    let trajectory = make_test_trajectory();
+    assert_eq!(trajectory.len(), 10);
+    assert_eq!(trajectory.series("theta_0").unwrap(), trajectory.times);
+    assert_eq!(trajectory.series("sum").unwrap(), vec![0.0; 10]);
+    assert!(trajectory.series("energy").is_none());
+}
+
+#[test]
+fn test_trajectory_slice_and_decimate() {
+    let trajectory = make_test_trajectory();
+    let sliced = trajectory.slice_time(1.0, 2.0);
+    assert_eq!(sliced.times, vec![1.0, 1.5, 2.0]);
+    assert_eq!(sliced.derived["sum"].len(), 3);
+
+    let decimated = trajectory.decimate(3);
+    assert_eq!(decimated.times, vec![0.0, 1.5, 3.0, 4.5]);
+    assert_eq!(decimated.column(1), vec![0.0, -1.5, -3.0, -4.5]);
+    assert_eq!(decimated.metadata.dt, 1.5);
+}
@@ -0,0 +1,499 @@
+use crate::trajectory::{Metadata, Trajectory};
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+
+impl Trajectory {
+    pub fn save(&self, filename : &str) -> Result<(), Box<dyn Error>> {
+        //format is chosen by the file extension: .csv, .json, .npz or .npy
+        match extension(filename).as_str() {
+            "csv" => self.write_csv(filename),
+            "json" => self.write_json(filename),
+            "npz" => self.write_npz(filename),
+            "npy" => write_npy(filename, &self.to_table()),
+            other => Err(format!("unsupported trajectory format '{}' for '{}'", other, filename).into()),
+        }
+    }
+
+    pub fn load(filename : &str) -> Result<Trajectory, Box<dyn Error>> {
+        //.npy files carry no column names or metadata, so they can not be loaded as a trajectory
+        match extension(filename).as_str() {
+            "csv" => Trajectory::read_csv(filename),
+            "json" => Trajectory::read_json(filename),
+            "npz" => Trajectory::read_npz(filename),
+            other => Err(format!("unsupported trajectory format '{}' for '{}'", other, filename).into()),
+        }
+    }
+
+    pub fn column_names(&self) -> Vec<String> {
+        //t, then the states, then the derived quantities
+        let mut names = vec!["t".to_string()];
+        names.extend(self.state_names.iter().cloned());
+        names.extend(self.derived.keys().cloned());
+        names
+    }
+
+    pub fn to_table(&self) -> Vec<Vec<f64>> {
+        //one row per time step, columns as in column_names
+        (0..self.len()).map(|i| {
+            let mut row = Vec::with_capacity(1 + self.state_names.len() + self.derived.len());
+            row.push(self.times[i]);
+            row.extend_from_slice(&self.states[i]);
+            row.extend(self.derived.values().map(|values| values[i]));
+            row
+        }).collect()
+    }
+
+    pub fn write_csv(&self, filename : &str) -> Result<(), Box<dyn Error>> {
+        /*
+        metadata is written as '#' comment lines before the header, e.g.
+        # model=sphere_springs
+        # parameter.R=2
+        # label.potential=coulomb
+        # states=8
+        t,theta_0,phi_0,theta_dot_0,phi_dot_0,theta_1,...
+         */
+        let mut writer = BufWriter::new(File::create(filename)?);
+        writeln!(writer, "# model={}", self.metadata.model)?;
+        writeln!(writer, "# integrator={}", self.metadata.integrator)?;
+        writeln!(writer, "# dt={}", self.metadata.dt)?;
+        for (name, value) in &self.metadata.parameters {
+            writeln!(writer, "# parameter.{}={}", name, value)?;
+        }
+        for (name, value) in &self.metadata.labels {
+            writeln!(writer, "# label.{}={}", name, value)?;
+        }
+        writeln!(writer, "# states={}", self.state_names.len())?;
+        writeln!(writer, "{}", self.column_names().join(","))?;
+        for row in self.to_table() {
+            let row : Vec<String> = row.iter().map(|v| v.to_string()).collect();
+            writeln!(writer, "{}", row.join(","))?;
+        }
+        writer.flush()?;
+        Ok(())
+    }
+
+    pub fn read_csv(filename : &str) -> Result<Trajectory, Box<dyn Error>> {
+        let reader = BufReader::new(File::open(filename)?);
+        let mut metadata = Metadata::default();
+        let mut n_states : Option<usize> = None;
+        let mut header : Option<Vec<String>> = None;
+        let mut rows : Vec<Vec<f64>> = Vec::new();
+
+        for (line_number, line) in reader.lines().enumerate() {
+            let line = line?;
+            let line = line.trim();
+            if line.is_empty() {
+                continue;
+            }
+            if let Some(comment) = line.strip_prefix('#') {
+                let (key, value) = match comment.trim().split_once('=') {
+                    Some(pair) => pair,
+                    None => continue,
+                };
+                match key {
+                    "model" => metadata.model = value.to_string(),
+                    "integrator" => metadata.integrator = value.to_string(),
+                    "dt" => metadata.dt = value.parse()?,
+                    "states" => n_states = Some(value.parse()?),
+                    _ => if let Some(name) = key.strip_prefix("parameter.") {
+                        metadata.parameters.insert(name.to_string(), value.parse()?);
+                    } else if let Some(name) = key.strip_prefix("label.") {
+                        metadata.labels.insert(name.to_string(), value.to_string());
+                    },
+                }
+            } else if header.is_none() {
+                header = Some(line.split(',').map(|s| s.trim().to_string()).collect());
+            } else {
+                let row = line.split(',')
+                    .map(|s| s.trim().parse::<f64>())
+                    .collect::<Result<Vec<f64>, _>>()
+                    .map_err(|e| format!("{}:{}: {}", filename, line_number + 1, e))?;
+                rows.push(row);
+            }
+        }
+
+        let header = header.ok_or(format!("{}: missing header line", filename))?;
+        if header.first().map(|s| s.as_str()) != Some("t") {
+            return Err(format!("{}: first column must be 't'", filename).into());
+        }
+        //without a states comment every column is treated as a state
+        let n_states = n_states.unwrap_or(header.len() - 1);
+        from_table(metadata, &header[1..], n_states, &rows)
+            .map_err(|e| format!("{}: {}", filename, e).into())
+    }
+
+    pub fn write_json(&self, filename : &str) -> Result<(), Box<dyn Error>> {
+        //json has no NaN or infinity, serde_json would write them as null and the file could not be read back
+        let values = self.times.iter().chain(self.states.iter().flatten()).chain(self.derived.values().flatten())
+            .chain(self.metadata.parameters.values()).chain([&self.metadata.dt]);
+        if let Some(value) = values.into_iter().find(|v| !v.is_finite()) {
+            return Err(format!("{}: json can not store the non-finite value {}, use csv or npz", filename, value).into());
+        }
+        let mut writer = BufWriter::new(File::create(filename)?);
+        serde_json::to_writer(&mut writer, self)?;
+        writer.flush()?;
+        Ok(())
+    }
+
+    pub fn read_json(filename : &str) -> Result<Trajectory, Box<dyn Error>> {
+        let reader = BufReader::new(File::open(filename)?);
+        let trajectory : Trajectory = serde_json::from_reader(reader)?;
+        check_lengths(&trajectory).map_err(|e| format!("{}: {}", filename, e))?;
+        Ok(trajectory)
+    }
+
+    pub fn write_npz(&self, filename : &str) -> Result<(), Box<dyn Error>> {
+        /*
+        arrays, as numpy.load sees them:
+        t - (len,) times
+        states - (len, n_states)
+        state_names - (n_states,) strings
+        derived_<name> - (len,) for every derived quantity
+        metadata - 0-d string holding the metadata as json
+         */
+        let mut zip = zip::ZipWriter::new(File::create(filename)?);
+        let options = zip::write::SimpleFileOptions::default()
+            .compression_method(zip::CompressionMethod::Stored);
+        let n_states = self.state_names.len();
+
+        let mut add_array = |name : &str, bytes : Vec<u8>| -> Result<(), Box<dyn Error>> {
+            zip.start_file(format!("{}.npy", name), options)?;
+            zip.write_all(&bytes)?;
+            Ok(())
+        };
+        add_array("t", npy_bytes_f64(&self.times, &[self.len()]))?;
+        let states : Vec<f64> = self.states.iter().flatten().copied().collect();
+        add_array("states", npy_bytes_f64(&states, &[self.len(), n_states]))?;
+        add_array("state_names", npy_bytes_str(&self.state_names, &[n_states]))?;
+        for (name, values) in &self.derived {
+            add_array(&format!("derived_{}", name), npy_bytes_f64(values, &[values.len()]))?;
+        }
+        add_array("metadata", npy_bytes_str(&[serde_json::to_string(&self.metadata)?], &[]))?;
+        zip.finish()?;
+        Ok(())
+    }
+
+    pub fn read_npz(filename : &str) -> Result<Trajectory, Box<dyn Error>> {
+        let mut zip = zip::ZipArchive::new(File::open(filename)?)?;
+        let mut arrays : BTreeMap<String, NpyArray> = BTreeMap::new();
+        for i in 0..zip.len() {
+            let mut file = zip.by_index(i)?;
+            let name = file.name().trim_end_matches(".npy").to_string();
+            let mut bytes = Vec::new();
+            file.read_to_end(&mut bytes)?;
+            arrays.insert(name, parse_npy(&bytes)?);
+        }
+        let mut take = |name : &str| arrays.remove(name)
+            .ok_or(format!("{}: missing array '{}'", filename, name));
+
+        let times = take("t")?.into_f64()?;
+        let states = take("states")?;
+        let state_names = take("state_names")?.into_strings()?;
+        let metadata : Metadata = match take("metadata")?.into_strings()?.first() {
+            Some(json) => serde_json::from_str(json)?,
+            None => Metadata::default(),
+        };
+        let n_states = state_names.len();
+        if states.shape != [times.len(), n_states] {
+            return Err(format!("{}: states have shape {:?}, expected [{}, {}]",
+                filename, states.shape, times.len(), n_states).into());
+        }
+        let states : Vec<Vec<f64>> = if n_states == 0 {
+            vec![Vec::new(); times.len()]
+        } else {
+            states.into_f64()?.chunks(n_states).map(|x| x.to_vec()).collect()
+        };
+
+        let mut derived = BTreeMap::new();
+        for (name, array) in arrays {
+            if let Some(name) = name.strip_prefix("derived_") {
+                derived.insert(name.to_string(), array.into_f64()?);
+            }
+        }
+        let trajectory = Trajectory {metadata, state_names, times, states, derived};
+        check_lengths(&trajectory).map_err(|e| format!("{}: {}", filename, e))?;
+        Ok(trajectory)
+    }
+}
+
+fn check_lengths(trajectory : &Trajectory) -> Result<(), String> {
+    //one state per time with a value per state name, and one value per time in every derived series
+    if trajectory.states.len() != trajectory.times.len() {
+        return Err(format!("{} states for {} times", trajectory.states.len(), trajectory.times.len()));
+    }
+    if let Some(i) = trajectory.states.iter().position(|x| x.len() != trajectory.state_names.len()) {
+        return Err(format!("state {} has {} values, expected {}", i, trajectory.states[i].len(), trajectory.state_names.len()));
+    }
+    for (name, values) in &trajectory.derived {
+        if values.len() != trajectory.times.len() {
+            return Err(format!("derived '{}' has {} values for {} times", name, values.len(), trajectory.times.len()));
+        }
+    }
+    Ok(())
+}
+
+fn from_table(metadata : Metadata, names : &[String], n_states : usize, rows : &[Vec<f64>]) -> Result<Trajectory, String> {
+    //names excludes the leading time column
+    if n_states > names.len() {
+        return Err(format!("{} states declared but only {} columns", n_states, names.len()));
+    }
+    let mut trajectory = Trajectory::new(metadata, &names[..n_states]);
+    for (i, row) in rows.iter().enumerate() {
+        if row.len() != names.len() + 1 {
+            return Err(format!("row {} has {} values, expected {}", i, row.len(), names.len() + 1));
+        }
+        trajectory.times.push(row[0]);
+        trajectory.states.push(row[1..=n_states].to_vec());
+    }
+    for (j, name) in names.iter().enumerate().skip(n_states) {
+        trajectory.derived.insert(name.clone(), rows.iter().map(|row| row[j + 1]).collect());
+    }
+    Ok(trajectory)
+}
+
+fn extension(filename : &str) -> String {
+    Path::new(filename).extension()
+        .map(|e| e.to_string_lossy().to_lowercase())
+        .unwrap_or_default()
+}
+
+pub fn write_npy(filename : &str, table : &[Vec<f64>]) -> Result<(), Box<dyn Error>> {
+    //writes a 2d float64 array, one row per element of table
+    let cols = table.first().map(|row| row.len()).unwrap_or(0);
+    if table.iter().any(|row| row.len() != cols) {
+        return Err("all rows of an npy table must have the same length".into());
+    }
+    let data : Vec<f64> = table.iter().flatten().copied().collect();
+    let mut file = File::create(filename)?;
+    file.write_all(&npy_bytes_f64(&data, &[table.len(), cols]))?;
+    Ok(())
+}
+
+pub fn read_npy(filename : &str) -> Result<Vec<Vec<f64>>, Box<dyn Error>> {
+    //reads a 1d or 2d float64 array, a 1d array is returned as a single column
+    let mut bytes = Vec::new();
+    File::open(filename)?.read_to_end(&mut bytes)?;
+    let array = parse_npy(&bytes)?;
+    let cols = match array.shape.as_slice() {
+        [_] => 1,
+        [_, cols] => *cols,
+        shape => return Err(format!("{}: expected a 1d or 2d array, got shape {:?}", filename, shape).into()),
+    };
+    let data = array.into_f64()?;
+    if cols == 0 {
+        return Ok(Vec::new());
+    }
+    Ok(data.chunks(cols).map(|row| row.to_vec()).collect())
+}
+
+//npy format: https://numpy.org/doc/stable/reference/generated/numpy.lib.format.html
+const NPY_MAGIC : &[u8] = b"\x93NUMPY";
+
+struct NpyArray {
+    descr : String,
+    shape : Vec<usize>,
+    data : Vec<u8>,
+}
+
+impl NpyArray {
+    fn into_f64(self) -> Result<Vec<f64>, Box<dyn Error>> {
+        if self.descr != "<f8" {
+            return Err(format!("expected a '<f8' array, got '{}'", self.descr).into());
+        }
+        Ok(self.data.chunks_exact(8)
+            .map(|b| f64::from_le_bytes(b.try_into().unwrap()))
+            .collect())
+    }
+
+    fn into_strings(self) -> Result<Vec<String>, Box<dyn Error>> {
+        //numpy stores unicode strings as fixed width utf-32, padded with zeros
+        let width : usize = match self.descr.strip_prefix("<U") {
+            Some(width) => width.parse()?,
+            None => return Err(format!("expected a '<U' array, got '{}'", self.descr).into()),
+        };
+        if width == 0 {
+            return Ok(vec![String::new(); self.shape.iter().product()]);
+        }
+        self.data.chunks(4 * width).map(|item| {
+            item.chunks_exact(4)
+                .map(|b| u32::from_le_bytes(b.try_into().unwrap()))
+                .take_while(|&c| c != 0)
+                .map(|c| char::from_u32(c).ok_or("invalid character in npy string".into()))
+                .collect::<Result<String, Box<dyn Error>>>()
+        }).collect()
+    }
+}
+
+fn npy_header(descr : &str, shape : &[usize]) -> Vec<u8> {
+    let shape = match shape {
+        [n] => format!("({},)", n),
+        _ => format!("({})", shape.iter().map(|n| n.to_string()).collect::<Vec<_>>().join(", ")),
+    };
+    let mut header = format!("{{'descr': '{}', 'fortran_order': False, 'shape': {}, }}", descr, shape);
+    //pad with spaces so the data starts on a 64 byte boundary
+    let unpadded = NPY_MAGIC.len() + 4 + header.len() + 1;
+    header.push_str(&" ".repeat((64 - unpadded % 64) % 64));
+    header.push('\n');
+
+    let mut bytes = NPY_MAGIC.to_vec();
+    bytes.extend_from_slice(&[1, 0]);
+    bytes.extend_from_slice(&(header.len() as u16).to_le_bytes());
+    bytes.extend_from_slice(header.as_bytes());
+    bytes
+}
+
+fn npy_bytes_f64(data : &[f64], shape : &[usize]) -> Vec<u8> {
+    let mut bytes = npy_header("<f8", shape);
+    for v in data {
+        bytes.extend_from_slice(&v.to_le_bytes());
+    }
+    bytes
+}
+
+fn npy_bytes_str(data : &[String], shape : &[usize]) -> Vec<u8> {
+    let width = data.iter().map(|s| s.chars().count()).max().unwrap_or(0).max(1);
+    let mut bytes = npy_header(&format!("<U{}", width), shape);
+    for s in data {
+        let chars : Vec<char> = s.chars().collect();
+        for i in 0..width {
+            let c = chars.get(i).map(|c| *c as u32).unwrap_or(0);
+            bytes.extend_from_slice(&c.to_le_bytes());
+        }
+    }
+    bytes
+}
+
+fn parse_npy(bytes : &[u8]) -> Result<NpyArray, Box<dyn Error>> {
+    if bytes.len() < 10 || &bytes[..6] != NPY_MAGIC {
+        return Err("not an npy file".into());
+    }
+    let (header_start, header_len) = match bytes[6] {
+        1 => (10, u16::from_le_bytes([bytes[8], bytes[9]]) as usize),
+        2 | 3 => {
+            if bytes.len() < 12 {
+                return Err("truncated npy header".into());
+            }
+            (12, u32::from_le_bytes([bytes[8], bytes[9], bytes[10], bytes[11]]) as usize)
+        },
+        version => return Err(format!("unsupported npy version {}", version).into()),
+    };
+    let data_start = header_start + header_len;
+    if bytes.len() < data_start {
+        return Err("truncated npy header".into());
+    }
+    let header = std::str::from_utf8(&bytes[header_start..data_start])?;
+
+    let descr = header_value(header, "descr")
+        .map(|v| v.trim_matches(['\'', '"']).to_string())
+        .ok_or("npy header has no descr")?;
+    if header_value(header, "fortran_order") != Some("False") {
+        return Err("fortran ordered npy arrays are not supported".into());
+    }
+    let shape = header_value(header, "shape").ok_or("npy header has no shape")?;
+    let shape = shape.trim_matches(['(', ')'])
+        .split(',')
+        .map(|s| s.trim())
+        .filter(|s| !s.is_empty())
+        .map(|s| s.parse::<usize>())
+        .collect::<Result<Vec<usize>, _>>()?;
+
+    let item_size = match descr.as_str() {
+        "<f8" => 8,
+        d if d.starts_with("<U") => 4 * d[2..].parse::<usize>()?,
+        d => return Err(format!("unsupported npy dtype '{}'", d).into()),
+    };
+    let data_len = item_size * shape.iter().product::<usize>();
+    if bytes.len() < data_start + data_len {
+        return Err("truncated npy data".into());
+    }
+    Ok(NpyArray {descr, shape, data : bytes[data_start..data_start + data_len].to_vec()})
+}
+
+fn header_value<'a>(header : &'a str, key : &str) -> Option<&'a str> {
+    //finds the value of key in a python dict literal like {'descr': '<f8', 'shape': (3, 2), }
+    let start = header.find(&format!("'{}'", key))? + key.len() + 2;
+    let rest = header[start..].trim_start().strip_prefix(':')?.trim_start();
+    let end = if rest.starts_with('(') {
+        rest.find(')')? + 1
+    } else {
+        rest.find([',', '}'])?
+    };
+    Some(rest[..end].trim())
+}
+
+#[cfg(test)]
+fn make_test_trajectory() -> Trajectory {
+    let metadata = Metadata::new("sphere_springs", "rk4", 0.1)
+        .with_parameter("R", 2.0)
+        .with_parameter("N", 2.0)
+        .with_label("potential", "riesz:1.5");
+    let names = ["theta_0", "phi_0", "theta_dot_0", "phi_dot_0", "theta_1", "phi_1", "theta_dot_1", "phi_dot_1"];
+    let mut trajectory = Trajectory::new(metadata, &names);
+    for i in 0..25 {
+        let t = 0.1 * i as f64;
+        trajectory.push(t, &[t.sin() / 3.0, -t.cos() * 1e-7, 0.0, 1.0, t, -t, 1e9 * t, 0.5]);
+    }
+    trajectory.add_derived("energy", |t, x| x[0] * t + 0.1);
+    trajectory
+}
+
+#[test]
+fn test_trajectory_round_trip() {
+    let trajectory = make_test_trajectory();
+    let dir = std::env::temp_dir();
+    for extension in ["csv", "json", "npz"] {
+        let path = dir.join(format!("sim_common_round_trip_{}.{}", std::process::id(), extension));
+        let path = path.to_str().unwrap();
+        trajectory.save(path).unwrap();
+        let loaded = Trajectory::load(path).unwrap();
+        std::fs::remove_file(path).unwrap();
+        assert_eq!(loaded, trajectory, "round trip through {} changed the data", extension);
+    }
+}
+
+#[test]
+fn test_malformed_files_are_rejected() {
+    //rows and derived series that do not match the times fail on loading, not later when they are indexed
+    let dir = std::env::temp_dir();
+    let mut short_state = make_test_trajectory();
+    short_state.states[3].pop();
+    let mut short_derived = make_test_trajectory();
+    short_derived.derived.get_mut("energy").unwrap().pop();
+    for (k, trajectory) in [short_state, short_derived].iter().enumerate() {
+        let json = dir.join(format!("sim_common_malformed_{}_{}.json", std::process::id(), k));
+        let json = json.to_str().unwrap();
+        std::fs::write(json, serde_json::to_string(trajectory).unwrap()).unwrap();
+        assert!(Trajectory::load(json).is_err());
+        std::fs::remove_file(json).unwrap();
+    }
+    let npz = dir.join(format!("sim_common_malformed_{}.npz", std::process::id()));
+    let npz = npz.to_str().unwrap();
+    let mut trajectory = make_test_trajectory();
+    trajectory.derived.insert("short".to_string(), vec![1.0; 3]);
+    trajectory.write_npz(npz).unwrap();
+    assert!(Trajectory::load(npz).is_err());
+    std::fs::remove_file(npz).unwrap();
+
+    //json has no NaN, it is refused instead of being written as null
+    let json = dir.join(format!("sim_common_nan_{}.json", std::process::id()));
+    let json = json.to_str().unwrap();
+    let mut trajectory = make_test_trajectory();
+    trajectory.states[2][1] = f64::NAN;
+    assert!(trajectory.save(json).is_err());
+    let _ = std::fs::remove_file(json);
+}
+
+#[test]
+fn test_npy_round_trip() {
+    let trajectory = make_test_trajectory();
+    let path = std::env::temp_dir().join(format!("sim_common_round_trip_{}.npy", std::process::id()));
+    let path = path.to_str().unwrap();
+    trajectory.save(path).unwrap();
+    let table = read_npy(path).unwrap();
+    std::fs::remove_file(path).unwrap();
+    assert_eq!(table, trajectory.to_table());
+    assert!(Trajectory::load(path).is_err());
+}
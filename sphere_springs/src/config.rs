@@ -0,0 +1,452 @@
+use std::error::Error;
+use std::fs::File;
+use serde::Deserialize;
+use crate::trajectory::Metadata;
+use crate::stochastic::{NoiseType, SdeScheme};
+use crate::ensemble::Sampler;
+use crate::potential::Interaction;
+use crate::topology::NetworkSpec;
+use crate::minimize::Method;
+use crate::forces::ForceMethod;
+use crate::initial::Initializer;
+use std::str::FromStr;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Formulation {
+    Cartesian, //positions and velocities in 3d, projected back onto the sphere after every step
+    Spherical, //theta, phi and their rates, singular at the poles
+}
+
+impl FromStr for Formulation {
+    type Err = String;
+
+    fn from_str(s : &str) -> Result<Self, Self::Err> {
+        match s {
+            "cartesian" => Ok(Formulation::Cartesian),
+            "spherical" => Ok(Formulation::Spherical),
+            _ => Err(format!("expected cartesian or spherical, got '{}'", s)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Integrator {
+    Rk4, //deterministic, ignores the noise
+    EulerMaruyama,
+    Heun,
+}
+
+impl Integrator {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Integrator::Rk4 => "rk4",
+            Integrator::EulerMaruyama => SdeScheme::EulerMaruyama.name(),
+            Integrator::Heun => SdeScheme::Heun.name(),
+        }
+    }
+
+    pub fn scheme(&self) -> Option<SdeScheme> {
+        //the stochastic scheme, None for the deterministic integrator
+        match self {
+            Integrator::Rk4 => None,
+            Integrator::EulerMaruyama => Some(SdeScheme::EulerMaruyama),
+            Integrator::Heun => Some(SdeScheme::Heun),
+        }
+    }
+}
+
+impl FromStr for Integrator {
+    type Err = String;
+
+    fn from_str(s : &str) -> Result<Self, Self::Err> {
+        [Integrator::Rk4, Integrator::EulerMaruyama, Integrator::Heun].into_iter()
+            .find(|integrator| integrator.name() == s)
+            .ok_or(format!("expected rk4, euler_maruyama or heun, got '{}'", s))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub radius : f64, //R - radius of the big sphere
+    pub mass : f64, //M - mass of each particle
+    pub stiffness : f64, //K - spring constant between particles, the energy scale of the other potentials
+    pub damping : f64, //C - friction coefficient with the big sphere
+    pub particles : usize, //N - number of particles
+    pub formulation : Formulation, //coordinates the state is integrated in
+    pub potential : Interaction, //pair interaction on the great circle angle
+    pub network : Option<NetworkSpec>, //springs between these pairs only, instead of the potential between all pairs
+    pub rest_length : Option<f64>, //rest arclength of the network springs, the mean initial edge length when not set
+    pub forces : ForceMethod, //how the pair forces are summed without a network
+    pub initializer : Initializer, //where the particles start
+    pub temperature : f64, //T - initial velocities are thermal at this temperature, boltzmann constant 1
+    pub output : Option<String>, //file to save the trajectory to, format chosen by extension
+    pub replay : Option<String>, //trajectory file to play back instead of simulating
+    pub draw : bool, //open the 3d viewer at the end of the run
+    pub record_every : Option<usize>, //keep every n-th step, about 1000 recorded states (fewer for large N) when not set
+    pub spectrum : Option<String>, //plot of the power spectrum of the particle coordinates
+    pub structure : Option<String>, //voronoi analysis of the final configuration, one csv row per particle
+    pub pair_statistics : Option<String>, //csv of g(theta) and the nearest neighbor arclength distribution
+    pub pair_plot : Option<String>, //histograms of g(theta) and of the nearest neighbor arclengths
+    pub bins : usize, //bins of both pair histograms
+    pub analysis_window : Option<f64>, //seconds at the end of the run the pair statistics are averaged over, the last state when not set
+    pub diagnostics : Option<String>, //energy and angular momentum time series, format chosen by extension
+    pub diagnostics_plot : Option<String>, //plot of the energy and angular momentum time series
+    pub drift_tolerance : f64, //relative drift of a conserved quantity that gets flagged
+    pub stop_kinetic_energy : Option<f64>, //end the run once the kinetic energy stayed below this for stop_window
+    pub stop_speed : Option<f64>, //end the run once every particle stayed slower than this for stop_window
+    pub stop_energy_change : Option<f64>, //end the run once the potential energy changed by less than this fraction over stop_window
+    pub stop_window : f64, //seconds of simulated time the stopping criteria have to hold
+    pub max_wall_time : Option<f64>, //end the run after this many seconds of real time
+    pub integrator : Integrator,
+    pub noise : f64, //sigma - noise intensity (rad/s^(1/2)) on the angular velocities, R sigma on the cartesian velocities for additive noise
+    pub noise_type : NoiseType,
+    pub seed : u64, //seed of the initial state, the noise generator draws from a separate stream of it
+    pub ensemble : usize, //number of realizations to run instead of a single simulation, 0 for none
+    pub ensemble_plot : Option<String>, //plot of the ensemble statistics
+    pub vary_stiffness : Option<Sampler>, //K of every realization is drawn from this distribution
+    pub vary_damping : Option<Sampler>, //C of every realization is drawn from this distribution
+    pub minimize : Option<Method>, //minimize the potential energy directly instead of running the dynamics
+    pub restarts : usize, //random starting configurations of the minimization
+    pub tolerance : f64, //largest tangential force left at a minimum
+    pub max_iterations : usize, //iterations of every minimization
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {radius : 2.0, mass : 1.0, stiffness : 2.0, damping : 1.0, particles : 4, formulation : Formulation::Cartesian,
+            potential : Interaction::TangentSpring, network : None, rest_length : None, forces : ForceMethod::AllPairs,
+            initializer : Initializer::Uniform, temperature : 0.0, output : None, replay : None, draw : true, record_every : None, spectrum : None, structure : None,
+            pair_statistics : None, pair_plot : None, bins : 50, analysis_window : None,
+            diagnostics : None, diagnostics_plot : None, drift_tolerance : 1e-6,
+            stop_kinetic_energy : None, stop_speed : None, stop_energy_change : None, stop_window : 1.0, max_wall_time : None,
+            integrator : Integrator::Rk4, noise : 0.0, noise_type : NoiseType::Additive, seed : 0,
+            ensemble : 0, ensemble_plot : None, vary_stiffness : None, vary_damping : None,
+            minimize : None, restarts : 1, tolerance : 1e-8, max_iterations : 100000}
+    }
+}
+
+impl Config {
+    pub fn from_file(filename : &str) -> Result<Self, Box<dyn Error>> {
+        let file = File::open(filename)
+            .map_err(|e| format!("could not open config file '{}': {}", filename, e))?;
+        let config : Config = serde_yaml::from_reader(file)
+            .map_err(|e| format!("could not parse config file '{}': {}", filename, e))?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    pub fn from_args(args : &[String]) -> Result<Self, Box<dyn Error>> {
+        /*
+        args - command line arguments, without the program name
+        a config file given with --config is read first, other flags override its values
+         */
+        let mut pairs : Vec<(&str, &str)> = Vec::new();
+        let mut iter = args.iter();
+        while let Some(flag) = iter.next() {
+            match iter.next() {
+                Some(value) => pairs.push((flag, value)),
+                None => return Err(format!("missing value for argument '{}'", flag).into()),
+            }
+        }
+
+        let mut config = Config::default();
+        for (_, filename) in pairs.iter().filter(|(flag, _)| *flag == "--config") {
+            config = Config::from_file(filename)?;
+        }
+        for (flag, value) in pairs {
+            match flag {
+                "--config" => {},
+                "--R" => config.radius = parse_value(flag, value)?,
+                "--M" => config.mass = parse_value(flag, value)?,
+                "--K" => config.stiffness = parse_value(flag, value)?,
+                "--C" => config.damping = parse_value(flag, value)?,
+                "--N" => config.particles = parse_value(flag, value)?,
+                "--formulation" => config.formulation = parse_value(flag, value)?,
+                "--potential" => config.potential = parse_value(flag, value)?,
+                "--network" => config.network = Some(parse_value(flag, value)?),
+                "--rest_length" => config.rest_length = Some(parse_value(flag, value)?),
+                "--forces" => config.forces = parse_value(flag, value)?,
+                "--init" => config.initializer = parse_value(flag, value)?,
+                "--temperature" => config.temperature = parse_value(flag, value)?,
+                "--output" => config.output = Some(value.to_string()),
+                "--replay" => config.replay = Some(value.to_string()),
+                "--draw" => config.draw = parse_value(flag, value)?,
+                "--record_every" => config.record_every = Some(parse_value(flag, value)?),
+                "--spectrum" => config.spectrum = Some(value.to_string()),
+                "--structure" => config.structure = Some(value.to_string()),
+                "--pair_statistics" => config.pair_statistics = Some(value.to_string()),
+                "--pair_plot" => config.pair_plot = Some(value.to_string()),
+                "--bins" => config.bins = parse_value(flag, value)?,
+                "--analysis_window" => config.analysis_window = Some(parse_value(flag, value)?),
+                "--diagnostics" => config.diagnostics = Some(value.to_string()),
+                "--diagnostics_plot" => config.diagnostics_plot = Some(value.to_string()),
+                "--drift_tolerance" => config.drift_tolerance = parse_value(flag, value)?,
+                "--stop_kinetic_energy" => config.stop_kinetic_energy = Some(parse_value(flag, value)?),
+                "--stop_speed" => config.stop_speed = Some(parse_value(flag, value)?),
+                "--stop_energy_change" => config.stop_energy_change = Some(parse_value(flag, value)?),
+                "--stop_window" => config.stop_window = parse_value(flag, value)?,
+                "--max_wall_time" => config.max_wall_time = Some(parse_value(flag, value)?),
+                "--integrator" => config.integrator = parse_value(flag, value)?,
+                "--noise" => config.noise = parse_value(flag, value)?,
+                "--noise_type" => config.noise_type = parse_value(flag, value)?,
+                "--seed" => config.seed = parse_value(flag, value)?,
+                "--ensemble" => config.ensemble = parse_value(flag, value)?,
+                "--ensemble_plot" => config.ensemble_plot = Some(value.to_string()),
+                "--vary_K" => config.vary_stiffness = Some(parse_value(flag, value)?),
+                "--vary_C" => config.vary_damping = Some(parse_value(flag, value)?),
+                "--minimize" => config.minimize = Some(parse_value(flag, value)?),
+                "--restarts" => config.restarts = parse_value(flag, value)?,
+                "--tolerance" => config.tolerance = parse_value(flag, value)?,
+                "--max_iterations" => config.max_iterations = parse_value(flag, value)?,
+                _ => return Err(format!("unknown argument '{}'", flag).into()),
+            }
+        }
+        config.validate()?;
+        Ok(config)
+    }
+
+    pub fn from_metadata(metadata : &Metadata) -> Result<Self, Box<dyn Error>> {
+        //rebuilds the physical parameters of a recorded run, a model with a network has to be connected at the first recorded state
+        if metadata.model != "sphere_springs" {
+            return Err(format!("expected a sphere_springs trajectory, got '{}'", metadata.model).into());
+        }
+        let get = |name : &str| metadata.parameters.get(name).copied()
+            .ok_or(format!("trajectory metadata has no parameter '{}'", name));
+        let config = Config {
+            radius : get("R")?,
+            mass : get("M")?,
+            stiffness : get("K")?,
+            damping : get("C")?,
+            particles : get("N")? as usize,
+            //recordings from before the cartesian formulation are spherical
+            formulation : if metadata.parameters.get("cartesian") == Some(&1.0) {Formulation::Cartesian} else {Formulation::Spherical},
+            //older recordings have no noise
+            noise : metadata.parameters.get("noise").copied().unwrap_or(0.0),
+            integrator : metadata.integrator.parse().unwrap_or(Integrator::Rk4),
+            //recordings from before the other potentials are tangent springs
+            potential : match metadata.labels.get("potential") {
+                Some(potential) => potential.parse().map_err(|e| format!("trajectory metadata has an invalid potential: {}", e))?,
+                None => Interaction::TangentSpring,
+            },
+            network : match metadata.labels.get("network") {
+                Some(network) => Some(network.parse().map_err(|e| format!("trajectory metadata has an invalid network: {}", e))?),
+                None => None,
+            },
+            rest_length : metadata.parameters.get("rest_length").copied(),
+            ..Config::default()
+        };
+        config.validate()?;
+        Ok(config)
+    }
+
+    pub fn validate(&self) -> Result<(), Box<dyn Error>> {
+        if self.particles < 2 {
+            return Err(format!("N must be at least 2, got {}", self.particles).into());
+        }
+        if !self.radius.is_finite() || self.radius <= 0.0 {
+            return Err(format!("R must be positive, got {}", self.radius).into());
+        }
+        if !self.mass.is_finite() || self.mass <= 0.0 {
+            return Err(format!("M must be positive, got {}", self.mass).into());
+        }
+        //K also sets the run time, 10 periods of sqrt(K/M)
+        if !self.stiffness.is_finite() || self.stiffness <= 0.0 {
+            return Err(format!("K must be positive, got {}", self.stiffness).into());
+        }
+        if !self.damping.is_finite() || self.damping < 0.0 {
+            return Err(format!("C must be non-negative, got {}", self.damping).into());
+        }
+        if let Some(rest_length) = self.rest_length {
+            if !rest_length.is_finite() || rest_length < 0.0 {
+                return Err(format!("rest_length must be non-negative, got {}", rest_length).into());
+            }
+        }
+        if !self.noise.is_finite() || self.noise < 0.0 {
+            return Err(format!("noise must be non-negative, got {}", self.noise).into());
+        }
+        if self.forces == ForceMethod::Cells(None) && self.potential.build(self.stiffness).range(self.radius).is_none() {
+            return Err("cells needs a cutoff, like cells:1.0, for potentials without a finite range".into());
+        }
+        if !self.drift_tolerance.is_finite() || self.drift_tolerance <= 0.0 {
+            return Err(format!("drift_tolerance must be positive, got {}", self.drift_tolerance).into());
+        }
+        for (name, value) in [("stop_kinetic_energy", self.stop_kinetic_energy), ("stop_speed", self.stop_speed),
+                              ("stop_energy_change", self.stop_energy_change), ("max_wall_time", self.max_wall_time)] {
+            if value.is_some_and(|v| !v.is_finite() || v < 0.0) {
+                return Err(format!("{} must be non-negative, got {}", name, value.unwrap()).into());
+            }
+        }
+        if self.record_every == Some(0) {
+            return Err("record_every must be at least 1".into());
+        }
+        if self.bins == 0 {
+            return Err("bins must be at least 1".into());
+        }
+        if self.analysis_window.is_some_and(|window| !window.is_finite() || window < 0.0) {
+            return Err(format!("analysis_window must be non-negative, got {}", self.analysis_window.unwrap()).into());
+        }
+        if !self.stop_window.is_finite() || self.stop_window < 0.0 {
+            return Err(format!("stop_window must be non-negative, got {}", self.stop_window).into());
+        }
+        if !self.temperature.is_finite() || self.temperature < 0.0 {
+            return Err(format!("temperature must be non-negative, got {}", self.temperature).into());
+        }
+        if self.restarts == 0 {
+            return Err("restarts must be at least 1".into());
+        }
+        if !self.tolerance.is_finite() || self.tolerance <= 0.0 {
+            return Err(format!("tolerance must be positive, got {}", self.tolerance).into());
+        }
+        if self.noise > 0.0 && self.integrator.scheme().is_none() {
+            return Err("noise needs the euler_maruyama or heun integrator".into());
+        }
+        Ok(())
+    }
+}
+
+fn parse_value<T>(flag : &str, value : &str) -> Result<T, Box<dyn Error>>
+where T : std::str::FromStr,
+      T::Err : std::fmt::Display {
+    value.parse::<T>()
+        .map_err(|e| format!("invalid value '{}' for argument '{}': {}", value, flag, e).into())
+}
+
+#[test]
+fn test_config_from_args() {
+    let args : Vec<String> = ["--N", "6", "--K", "3.5"].iter().map(|s| s.to_string()).collect();
+    let config = Config::from_args(&args).unwrap();
+    assert_eq!(config.particles, 6);
+    assert_eq!(config.stiffness, 3.5);
+    assert_eq!(config.radius, Config::default().radius);
+
+    assert!(Config::from_args(&[]).is_ok());
+    assert!(Config::from_args(&["--N".to_string()]).is_err());
+    assert!(Config::from_args(&["--N".to_string(), "four".to_string()]).is_err());
+    assert!(Config::from_args(&["--N".to_string(), "1".to_string()]).is_err());
+    assert!(Config::from_args(&["--R".to_string(), "-1".to_string()]).is_err());
+    assert!(Config::from_args(&["--K".to_string(), "0".to_string()]).is_err());
+    assert!(Config::from_args(&["--C".to_string(), "-0.5".to_string()]).is_err());
+    assert!(Config::from_args(&["--C".to_string(), "NaN".to_string()]).is_err());
+    assert!(Config::from_args(&["--C".to_string(), "0".to_string()]).is_ok());
+    assert!(Config::from_args(&["--Q".to_string(), "1".to_string()]).is_err());
+}
+
+#[test]
+fn test_config_potential_args() {
+    let config = Config::from_args(&["--potential".to_string(), "riesz:2".to_string()]).unwrap();
+    assert_eq!(config.potential, Interaction::Riesz(2.0));
+
+    let args : Vec<String> = ["--potential", "soft_sphere:0.5", "--forces", "cells"].iter().map(|s| s.to_string()).collect();
+    assert_eq!(Config::from_args(&args).unwrap().forces, ForceMethod::Cells(None));
+    assert!(Config::from_args(&["--forces".to_string(), "cells".to_string()]).is_err());
+    assert!(Config::from_args(&["--forces".to_string(), "barnes_hut:0".to_string()]).is_err());
+
+    let args : Vec<String> = ["--init", "cap:0.5", "--temperature", "0.1"].iter().map(|s| s.to_string()).collect();
+    let config = Config::from_args(&args).unwrap();
+    assert_eq!((config.initializer, config.temperature), (Initializer::Cap(0.5), 0.1));
+    assert!(Config::from_args(&["--init".to_string(), "cube".to_string()]).is_err());
+    assert!(Config::from_args(&["--temperature".to_string(), "-1".to_string()]).is_err());
+}
+
+#[test]
+fn test_config_network_args() {
+    let args : Vec<String> = ["--network", "nearest:4", "--rest_length", "0.8"].iter().map(|s| s.to_string()).collect();
+    let config = Config::from_args(&args).unwrap();
+    assert_eq!(config.network, Some(NetworkSpec::Nearest(4)));
+    assert_eq!(config.rest_length, Some(0.8));
+    assert!(Config::from_args(&["--network".to_string(), "mesh".to_string()]).is_err());
+    assert!(Config::from_args(&["--rest_length".to_string(), "-1".to_string()]).is_err());
+}
+
+#[test]
+fn test_config_noise_args() {
+    let args : Vec<String> = ["--integrator", "heun", "--noise", "0.2", "--noise_type", "multiplicative", "--seed", "9"].iter().map(|s| s.to_string()).collect();
+    let config = Config::from_args(&args).unwrap();
+    assert_eq!(config.integrator, Integrator::Heun);
+    assert_eq!(config.noise_type, NoiseType::Multiplicative);
+    assert_eq!((config.noise, config.seed), (0.2, 9));
+    assert!(Config::from_args(&["--noise".to_string(), "0.2".to_string()]).is_err());
+    assert!(Config::from_args(&["--integrator".to_string(), "rk5".to_string()]).is_err());
+}
+
+#[test]
+fn test_config_ensemble_args() {
+    let args : Vec<String> = ["--ensemble", "20", "--vary_K", "uniform:1:3"].iter().map(|s| s.to_string()).collect();
+    let config = Config::from_args(&args).unwrap();
+    assert_eq!(config.ensemble, 20);
+    assert_eq!(config.vary_stiffness, Some(Sampler::Uniform(1.0, 3.0)));
+    assert!(Config::from_args(&["--vary_C".to_string(), "normal:1".to_string()]).is_err());
+}
+
+#[test]
+fn test_config_minimize_args() {
+    let args : Vec<String> = ["--minimize", "fire", "--restarts", "8", "--tolerance", "1e-6"].iter().map(|s| s.to_string()).collect();
+    let config = Config::from_args(&args).unwrap();
+    assert_eq!(config.minimize, Some(Method::Fire));
+    assert_eq!((config.restarts, config.tolerance), (8, 1e-6));
+    assert!(Config::from_args(&["--minimize".to_string(), "newton".to_string()]).is_err());
+    assert!(Config::from_args(&["--restarts".to_string(), "0".to_string()]).is_err());
+}
+
+#[test]
+fn test_config_stopping_args() {
+    let args : Vec<String> = ["--stop_kinetic_energy", "1e-6", "--stop_window", "2", "--max_wall_time", "60"].iter().map(|s| s.to_string()).collect();
+    let config = Config::from_args(&args).unwrap();
+    assert_eq!((config.stop_kinetic_energy, config.stop_window, config.max_wall_time), (Some(1e-6), 2.0, Some(60.0)));
+    assert!(Config::from_args(&["--stop_speed".to_string(), "-1".to_string()]).is_err());
+}
+
+#[test]
+fn test_config_recording_args() {
+    assert_eq!(Config::from_args(&["--structure".to_string(), "cells.csv".to_string()]).unwrap().structure.as_deref(), Some("cells.csv"));
+    let args : Vec<String> = ["--pair_statistics", "pairs.csv", "--bins", "30", "--analysis_window", "2.5"].iter().map(|s| s.to_string()).collect();
+    let config = Config::from_args(&args).unwrap();
+    assert_eq!((config.pair_statistics.as_deref(), config.bins, config.analysis_window), (Some("pairs.csv"), 30, Some(2.5)));
+    assert!(Config::from_args(&["--bins".to_string(), "0".to_string()]).is_err());
+    assert_eq!(Config::from_args(&["--record_every".to_string(), "10".to_string()]).unwrap().record_every, Some(10));
+    assert!(Config::from_args(&["--record_every".to_string(), "0".to_string()]).is_err());
+    let args : Vec<String> = ["--diagnostics", "energy.csv", "--drift_tolerance", "1e-9"].iter().map(|s| s.to_string()).collect();
+    let config = Config::from_args(&args).unwrap();
+    assert_eq!((config.diagnostics.as_deref(), config.drift_tolerance), (Some("energy.csv"), 1e-9));
+    assert!(Config::from_args(&["--drift_tolerance".to_string(), "0".to_string()]).is_err());
+}
+
+#[test]
+fn test_config_from_metadata() {
+    use rand::{rngs::StdRng, SeedableRng};
+    let config = Config {radius : 3.0, particles : 7, ..Config::default()};
+    let metadata = crate::model::SphereSprings::new(config.clone()).unwrap().metadata(0.01);
+    assert_eq!(Config::from_metadata(&metadata).unwrap(), config);
+    let config = Config {integrator : Integrator::EulerMaruyama, noise : 0.5, ..Config::default()};
+    let metadata = crate::model::SphereSprings::new(config.clone()).unwrap().metadata(0.01);
+    assert_eq!(Config::from_metadata(&metadata).unwrap(), config);
+    let config = Config {formulation : Formulation::Spherical, ..Config::default()};
+    let metadata = crate::model::SphereSprings::new(config.clone()).unwrap().metadata(0.01);
+    assert_eq!(Config::from_metadata(&metadata).unwrap(), config);
+    let config = Config {potential : Interaction::Riesz(2.0), ..Config::default()};
+    let metadata = crate::model::SphereSprings::new(config.clone()).unwrap().metadata(0.01);
+    assert_eq!(Config::from_metadata(&metadata).unwrap(), config);
+    //a networked model needs positions to be built, the recording restores the same springs from its first state
+    let config = Config {particles : 6, network : Some(NetworkSpec::Nearest(2)), rest_length : Some(0.5), ..Config::default()};
+    let (model, x0) = crate::model::SphereSprings::with_state(config.clone(), &mut StdRng::seed_from_u64(3)).unwrap();
+    let restored = Config::from_metadata(&model.metadata(0.01)).unwrap();
+    assert_eq!(restored, config);
+    assert_eq!(crate::model::SphereSprings::connected(restored, &x0).unwrap().edge_arclengths(&x0), model.edge_arclengths(&x0));
+    //recordings from before the potential was stored are tangent springs, an unreadable one is an error
+    let mut metadata = crate::model::SphereSprings::new(Config::default()).unwrap().metadata(0.01);
+    metadata.labels.clear();
+    assert_eq!(Config::from_metadata(&metadata).unwrap().potential, Interaction::TangentSpring);
+    metadata.labels.insert("potential".to_string(), "gravity".to_string());
+    assert!(Config::from_metadata(&metadata).is_err());
+    assert!(Config::from_metadata(&metadata.with_label("potential", "coulomb").with_label("network", "mesh")).is_err());
+    assert!(Config::from_metadata(&Metadata::new("pendulum", "rk4", 0.01)).is_err());
+}
+
+#[test]
+fn test_config_from_file() {
+    let config = Config::from_file("params.yaml").unwrap();
+    assert_eq!(config, Config::default());
+}
@@ -0,0 +1,199 @@
+use crate::math::{cross, norm};
+use crate::model::SphereSprings;
+use crate::plot_2d::{auto_range, gradient_colors, PlotBackend, PlotOptions};
+use crate::trajectory::Trajectory;
+use plotters::coord::Shift;
+use plotters::prelude::*;
+use std::error::Error;
+
+pub const SERIES : [&str; 8] = ["kinetic_energy", "potential_energy", "dissipated_energy", "total_energy",
+    "angular_momentum_x", "angular_momentum_y", "angular_momentum_z", "angular_momentum"];
+
+pub fn add_diagnostics(model : &SphereSprings, trajectory : &mut Trajectory) {
+    /*
+    adds the energies and the total angular momentum about the center as derived series
+    dissipated_energy comes from simulate, recordings without it are integrated over the recorded states
+    total_energy is kinetic + potential + dissipated, constant without noise
+     */
+    if !trajectory.derived.contains_key("dissipated_energy") {
+        let power = trajectory.map_states(|x| model.dissipation_rate(x));
+        let mut dissipated = vec![0.0; trajectory.len()];
+        for k in 1..trajectory.len() {
+            dissipated[k] = dissipated[k - 1] + 0.5 * (trajectory.times[k] - trajectory.times[k - 1]) * (power[k - 1] + power[k]);
+        }
+        trajectory.derived.insert("dissipated_energy".to_string(), dissipated);
+    }
+    trajectory.add_derived("kinetic_energy", |_t, x| model.kinetic_energy(x));
+    trajectory.add_derived("potential_energy", |_t, x| model.potential_energy(x));
+    let total = trajectory.derived["kinetic_energy"].iter()
+        .zip(&trajectory.derived["potential_energy"])
+        .zip(&trajectory.derived["dissipated_energy"])
+        .map(|((kinetic, potential), dissipated)| kinetic + potential + dissipated)
+        .collect();
+    trajectory.derived.insert("total_energy".to_string(), total);
+    let momenta = trajectory.map_states(|x| model.angular_momentum(x));
+    for (axis, name) in ["angular_momentum_x", "angular_momentum_y", "angular_momentum_z"].iter().enumerate() {
+        trajectory.derived.insert(name.to_string(), momenta.iter().map(|l| l[axis]).collect());
+    }
+    trajectory.derived.insert("angular_momentum".to_string(), momenta.iter().map(norm).collect());
+}
+
+pub fn diagnostics_table(trajectory : &Trajectory) -> Result<Trajectory, Box<dyn Error>> {
+    //the diagnostic series alone, without the states, to save as a time series
+    let mut table = Trajectory::new(trajectory.metadata.clone(), &[] as &[&str]);
+    for t in &trajectory.times {
+        table.push(*t, &[]);
+    }
+    for name in SERIES {
+        let values = trajectory.derived.get(name).ok_or(format!("trajectory has no '{}', add the diagnostics first", name))?;
+        table.derived.insert(name.to_string(), values.clone());
+    }
+    Ok(table)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Drift {
+    pub quantity : String,
+    pub max_deviation : f64, //largest distance from the initial value
+    pub relative : f64, //max_deviation over the scale of the quantity during the run
+    pub conserved : bool, //whether the model should keep the quantity constant
+    pub flagged : bool, //conserved but drifting by more than the tolerance
+}
+
+pub fn check_conservation(model : &SphereSprings, trajectory : &Trajectory, tolerance : f64) -> Result<Vec<Drift>, Box<dyn Error>> {
+    /*
+    drift of the total energy and of the angular momentum vector from their initial values
+    energy is conserved without noise, angular momentum also needs C = 0
+    energy is measured against the largest kinetic + |potential| + dissipated, angular momentum against the largest sum of the particle momenta
+     */
+    let series = |name : &str| trajectory.derived.get(name).ok_or(format!("trajectory has no '{}', add the diagnostics first", name));
+    if trajectory.is_empty() {
+        return Err("no states to check".into());
+    }
+    let noiseless = model.config.noise == 0.0;
+    let (kinetic, potential, dissipated, total) = (series("kinetic_energy")?, series("potential_energy")?, series("dissipated_energy")?, series("total_energy")?);
+    let energy_scale = (0..trajectory.len()).map(|k| kinetic[k] + potential[k].abs() + dissipated[k]).fold(0.0, f64::max);
+    let energy_deviation = total.iter().map(|e| (e - total[0]).abs()).fold(0.0, f64::max);
+
+    let momenta : Vec<[f64;3]> = (0..trajectory.len())
+        .map(|k| Ok([series("angular_momentum_x")?[k], series("angular_momentum_y")?[k], series("angular_momentum_z")?[k]]))
+        .collect::<Result<_, String>>()?;
+    let momentum_scale = trajectory.map_states(|x| {
+        let r = model.config.radius;
+        model.unit_vectors(x).iter().zip(model.velocities(x)).map(|(e, v)| model.config.mass * r * norm(&cross(e, &v))).sum::<f64>()
+    }).into_iter().fold(0.0, f64::max);
+    let momentum_deviation = momenta.iter()
+        .map(|l| norm(&[l[0] - momenta[0][0], l[1] - momenta[0][1], l[2] - momenta[0][2]]))
+        .fold(0.0, f64::max);
+
+    let drift = |quantity : &str, max_deviation : f64, scale : f64, conserved : bool| {
+        let relative = if max_deviation == 0.0 {0.0} else {max_deviation / scale};
+        Drift {quantity : quantity.to_string(), max_deviation, relative, conserved, flagged : conserved && relative > tolerance}
+    };
+    Ok(vec![
+        drift("energy", energy_deviation, energy_scale, noiseless),
+        drift("angular_momentum", momentum_deviation, momentum_scale, noiseless && model.config.damping == 0.0),
+    ])
+}
+
+pub fn plot_diagnostics(trajectory : &Trajectory, options : &PlotOptions) -> Result<(), Box<dyn Error>> {
+    //energies above the angular momentum components, sharing the time axis, options.y_range is ignored
+    for name in SERIES {
+        if !trajectory.derived.contains_key(name) {
+            return Err(format!("trajectory has no '{}', add the diagnostics first", name).into());
+        }
+    }
+    if trajectory.len() < 2 {
+        return Err("diagnostics need at least two samples to plot".into());
+    }
+    match options.backend()? {
+        PlotBackend::Svg => {
+            let root = SVGBackend::new(&options.path, options.size).into_drawing_area();
+            draw_diagnostics(&root, trajectory, options)
+        },
+        PlotBackend::Png => {
+            let root = BitMapBackend::new(&options.path, options.size).into_drawing_area();
+            draw_diagnostics(&root, trajectory, options)
+        },
+    }
+}
+
+fn draw_diagnostics<DB : DrawingBackend>(root : &DrawingArea<DB, Shift>, trajectory : &Trajectory, options : &PlotOptions) -> Result<(), Box<dyn Error>>
+where DB::ErrorType : 'static {
+    root.fill(&WHITE)?;
+    let root = root.titled(&options.title, ("sans-serif", 30).into_font())?;
+    let panels = root.split_evenly((2, 1));
+    let x_range = options.x_range.unwrap_or_else(|| auto_range(trajectory.times.iter().copied()));
+    let contents : [(&str, &[&str], &[&str]); 2] = [
+        ("Energy (J)", &SERIES[..4], &["kinetic", "potential", "dissipated", "total"]),
+        ("Angular momentum (kg m^2/s)", &SERIES[4..7], &["x", "y", "z"]),
+    ];
+    for (panel, (y_label, names, titles)) in panels.iter().zip(contents) {
+        let y_range = auto_range(names.iter().flat_map(|name| trajectory.derived[*name].iter().copied()));
+        let mut chart = ChartBuilder::on(panel)
+            .margin(10)
+            .x_label_area_size(30)
+            .y_label_area_size(60)
+            .build_cartesian_2d(x_range.0..x_range.1, y_range.0..y_range.1)?;
+        chart.configure_mesh()
+            .x_desc(&options.x_label)
+            .y_desc(y_label)
+            .draw()?;
+        let colors = gradient_colors(names.len());
+        for (i, name) in names.iter().enumerate() {
+            let line_style = options.line_styles.get(i).copied().unwrap_or_default();
+            let style = line_style.color.unwrap_or(colors[i]).stroke_width(line_style.width);
+            chart.draw_series(LineSeries::new(trajectory.times.iter().copied().zip(trajectory.derived[*name].iter().copied()), style))?
+                .label(titles[i])
+                .legend(move |(x, y)| PathElement::new(vec![(x, y), (x + 20, y)], style));
+        }
+        chart.configure_series_labels()
+            .position(SeriesLabelPosition::UpperRight)
+            .border_style(BLACK)
+            .background_style(WHITE.mix(0.8))
+            .draw()?;
+    }
+    root.present()?;
+    Ok(())
+}
+
+#[test]
+fn test_undamped_run_conserves_energy_and_angular_momentum() {
+    use crate::config::Config;
+    use crate::initial::Initializer;
+    use rand::{rngs::StdRng, SeedableRng};
+    let model = SphereSprings::new(Config {particles : 5, damping : 0.0, temperature : 1.0, initializer : Initializer::Fibonacci, ..Config::default()}).unwrap();
+    let x0 = model.initial_state(&mut StdRng::seed_from_u64(0)).unwrap();
+    let mut trajectory = model.simulate(&x0, 0.001, 2000, 10);
+    add_diagnostics(&model, &mut trajectory);
+    assert!(trajectory.derived["angular_momentum"][0] > 0.1);
+    let drifts = check_conservation(&model, &trajectory, 1e-8).unwrap();
+    assert!(drifts.iter().all(|drift| drift.conserved && !drift.flagged), "{:?}", drifts);
+
+    //with friction the lost energy is accounted for, but angular momentum is not expected to survive
+    let damped = SphereSprings::new(Config {damping : 1.0, ..model.config.clone()}).unwrap();
+    let mut trajectory = damped.simulate(&x0, 0.001, 2000, 10);
+    add_diagnostics(&damped, &mut trajectory);
+    assert!(trajectory.derived["dissipated_energy"].last().unwrap() > &0.1);
+    let drifts = check_conservation(&damped, &trajectory, 1e-6).unwrap();
+    assert!(!drifts[0].flagged && !drifts[1].conserved, "{:?}", drifts);
+    let table = diagnostics_table(&trajectory).unwrap();
+    assert_eq!(table.derived.len(), SERIES.len());
+    assert!(table.state_names.is_empty());
+}
+
+#[test]
+fn test_drift_is_flagged() {
+    //an energy that jumps half way is drift
+    use crate::config::Config;
+    let model = SphereSprings::new(Config {particles : 2, damping : 0.0, ..Config::default()}).unwrap();
+    let x = model.from_spherical(&[1.0, 0.0, 0.5, 0.0, 2.0, 1.0, 0.0, 0.0]);
+    let mut trajectory = Trajectory::new(model.metadata(0.1), &model.state_names());
+    trajectory.push(0.0, &x);
+    trajectory.push(0.1, &x);
+    add_diagnostics(&model, &mut trajectory);
+    assert!(check_conservation(&model, &trajectory, 1e-12).unwrap().iter().all(|drift| !drift.flagged));
+    trajectory.derived.get_mut("total_energy").unwrap()[1] *= 1.01;
+    assert!(check_conservation(&model, &trajectory, 1e-6).unwrap()[0].flagged);
+    assert!(check_conservation(&model, &Trajectory::new(model.metadata(0.1), &model.state_names()), 1e-6).is_err());
+}
@@ -1,5 +1,9 @@
 use three_d::*;
-use crate::math::lerp1d;
+use sim_common::math::lerp1d;
+use crate::config::Config;
+use crate::model::SphereSprings;
+use crate::trajectory::Trajectory;
+use std::error::Error;
 
 #[derive(Default, Clone)]
 struct XyzHistory {
@@ -8,15 +12,14 @@ struct XyzHistory {
     z : Vec<f32>,
 }
 
-pub fn draw_3d(timestamps : &Vec<f32> ,points_by_time : &Vec<Vec<[f32;3]>>, r : f32) {
+pub fn draw_3d(timestamps : &[f32] ,points_by_time : &[Vec<[f32;3]>], r : f32) {
     /*
     points_by_time - outer vector is time, inner vector is points
      */
-    let n = points_by_time.len();
     let m = points_by_time[0].len();
 
     //transpose points_by_time so xyz data is by index
-    let mut points_by_index: Vec<XyzHistory> = vec![XyzHistory::default(); n];
+    let mut points_by_index: Vec<XyzHistory> = vec![XyzHistory::default(); m];
     for p in points_by_time {
         for i in 0..m {
             points_by_index[i].x.push(p[i][0]);
@@ -27,10 +30,10 @@ pub fn draw_3d(timestamps : &Vec<f32> ,points_by_time : &Vec<Vec<[f32;3]>>, r :
 
     // let arc_timestamps = Arc::new(timestamps.clone());
 
-    fn interp_location(time : f32, timestamps : &Vec<f32>, history : &XyzHistory) -> [f32;3] {
-        let x = lerp1d(time, &timestamps, &history.x);
-        let y = lerp1d(time, &timestamps, &history.y);
-        let z = lerp1d(time, &timestamps, &history.z);
+    fn interp_location(time : f32, timestamps : &[f32], history : &XyzHistory) -> [f32;3] {
+        let x = lerp1d(time, timestamps, &history.x);
+        let y = lerp1d(time, timestamps, &history.y);
+        let z = lerp1d(time, timestamps, &history.z);
         [x,y,z]
     }
 
@@ -71,8 +74,8 @@ pub fn draw_3d(timestamps : &Vec<f32> ,points_by_time : &Vec<Vec<[f32;3]>>, r :
         ),
     );
 
-    let mut points = Vec::with_capacity(n);
-    for i in 0..m {
+    let mut points = Vec::with_capacity(m);
+    for history in points_by_index {
         let mut mesh = CpuMesh::sphere(32);
         mesh.transform(&Mat4::from_scale(0.1 * r)).unwrap();
         let mut point = Gm::new(
@@ -85,10 +88,9 @@ pub fn draw_3d(timestamps : &Vec<f32> ,points_by_time : &Vec<Vec<[f32;3]>>, r :
                 },
             ),
         );
-        let tmp_timestamps = timestamps.clone();
-        let tmp_history = points_by_index[i].clone();
+        let tmp_timestamps = timestamps.to_vec();
         point.set_animation(move |time| {
-            let xyz = interp_location(time, &tmp_timestamps, &tmp_history);
+            let xyz = interp_location(time, &tmp_timestamps, &history);
             Mat4::from_translation(vec3(xyz[0], xyz[1], xyz[2]))
         });
         points.push(point)
@@ -125,3 +127,17 @@ pub fn draw_3d(timestamps : &Vec<f32> ,points_by_time : &Vec<Vec<[f32;3]>>, r :
         FrameOutput::default()
     });
 }
+
+pub fn replay_3d(trajectory : &Trajectory) -> Result<(), Box<dyn Error>> {
+    //plays back a recorded trajectory, the model parameters are taken from its metadata
+    let config = Config::from_metadata(&trajectory.metadata)?;
+    if trajectory.is_empty() {
+        return Err("trajectory has no samples to replay".into());
+    }
+    //a spring network is rebuilt where it was built in the recorded run, at the first state
+    let model = SphereSprings::connected(config, &trajectory.states[0])
+        .map_err(|e| format!("cannot replay the trajectory: {}", e))?;
+    let positions = trajectory.map_states(|x| model.x_2_positions(x));
+    draw_3d(&trajectory.times_f32(), &positions, model.config.radius as f32);
+    Ok(())
+}
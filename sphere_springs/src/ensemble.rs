@@ -0,0 +1,250 @@
+use crate::plot_2d::{auto_range, gradient_colors, PlotBackend, PlotOptions};
+use crate::stochastic::stream_seed;
+use plotters::coord::Shift;
+use plotters::prelude::*;
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use rand_distr::{Distribution, LogNormal, Normal};
+use rayon::prelude::*;
+use serde::Deserialize;
+use std::error::Error;
+use std::str::FromStr;
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(try_from = "String")]
+pub enum Sampler {
+    Fixed(f64),
+    Uniform(f64, f64), //low, high
+    Normal(f64, f64), //mean, standard deviation
+    LogNormal(f64, f64), //mean and standard deviation of the logarithm
+}
+
+impl Sampler {
+    pub fn sample<R : Rng>(&self, rng : &mut R) -> f64 {
+        match *self {
+            Sampler::Fixed(value) => value,
+            Sampler::Uniform(low, high) => rng.gen_range(low..high),
+            Sampler::Normal(mean, std) => Normal::new(mean, std).unwrap().sample(rng),
+            Sampler::LogNormal(mu, sigma) => LogNormal::new(mu, sigma).unwrap().sample(rng),
+        }
+    }
+}
+
+impl FromStr for Sampler {
+    type Err = String;
+
+    fn from_str(s : &str) -> Result<Self, Self::Err> {
+        //"2.0", "uniform:1:3", "normal:2:0.1" or "lognormal:0:0.5"
+        let fields : Vec<&str> = s.split(':').collect();
+        let numbers = fields[1..].iter()
+            .map(|field| field.parse::<f64>().map_err(|e| format!("invalid number '{}' in '{}': {}", field, s, e)))
+            .collect::<Result<Vec<f64>, String>>()?;
+        if numbers.iter().any(|number| !number.is_finite()) {
+            return Err(format!("the parameters of '{}' must be finite", s));
+        }
+        let sampler = match (fields[0], numbers.as_slice()) {
+            ("uniform", [low, high]) if low < high => Sampler::Uniform(*low, *high),
+            ("normal", [mean, std]) if *std >= 0.0 => Sampler::Normal(*mean, *std),
+            ("lognormal", [mu, sigma]) if *sigma >= 0.0 => Sampler::LogNormal(*mu, *sigma),
+            (value, []) => match value.parse::<f64>() {
+                Ok(value) if value.is_finite() => Sampler::Fixed(value),
+                _ => return Err(format!("unknown distribution or non-finite value '{}'", s)),
+            },
+            _ => return Err(format!("expected a number, uniform:low:high, normal:mean:std or lognormal:mu:sigma, got '{}'", s)),
+        };
+        Ok(sampler)
+    }
+}
+
+impl TryFrom<String> for Sampler {
+    type Error = String;
+
+    fn try_from(s : String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+pub fn run_ensemble<T, F>(count : usize, seed : u64, realization : F) -> Vec<T>
+where T : Send,
+      F : Fn(usize, &mut StdRng) -> T + Sync {
+    /*
+    runs count realizations in parallel, realization i gets its own generator on stream i of seed
+    so the results do not depend on how rayon schedules the work, and neighboring seeds share no realizations
+     */
+    (0..count).into_par_iter()
+        .map(|i| {
+            let mut rng = StdRng::seed_from_u64(stream_seed(seed, i as u64));
+            realization(i, &mut rng)
+        })
+        .collect()
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct EnsembleStatistics {
+    pub times : Vec<f64>,
+    pub mean : Vec<f64>,
+    pub std : Vec<f64>,
+    pub quantiles : Vec<(f64, Vec<f64>)>, //(probability, value at every time)
+}
+
+impl EnsembleStatistics {
+    pub fn from_runs(times : &[f64], runs : &[Vec<f64>], probabilities : &[f64]) -> Result<Self, Box<dyn Error>> {
+        //per time statistics across runs, every run is sampled at times
+        if runs.is_empty() {
+            return Err("no runs to aggregate".into());
+        }
+        if let Some(i) = runs.iter().position(|run| run.len() != times.len()) {
+            return Err(format!("run {} has {} samples for {} times", i, runs[i].len(), times.len()).into());
+        }
+        if let Some(p) = probabilities.iter().find(|p| !(0.0..=1.0).contains(*p)) {
+            return Err(format!("quantile probabilities must be in [0, 1], got {}", p).into());
+        }
+        let n = runs.len() as f64;
+        let mut mean = Vec::with_capacity(times.len());
+        let mut std = Vec::with_capacity(times.len());
+        let mut quantiles : Vec<(f64, Vec<f64>)> = probabilities.iter().map(|p| (*p, Vec::with_capacity(times.len()))).collect();
+        for k in 0..times.len() {
+            let mut values : Vec<f64> = runs.iter().map(|run| run[k]).collect();
+            let m = values.iter().sum::<f64>() / n;
+            mean.push(m);
+            std.push((values.iter().map(|v| (v - m).powi(2)).sum::<f64>() / n).sqrt());
+            values.sort_by(f64::total_cmp);
+            for (p, series) in quantiles.iter_mut() {
+                series.push(quantile(&values, *p));
+            }
+        }
+        Ok(EnsembleStatistics {times : times.to_vec(), mean, std, quantiles})
+    }
+}
+
+fn quantile(sorted : &[f64], p : f64) -> f64 {
+    //linear interpolation between the closest ranks
+    let position = p * (sorted.len() - 1) as f64;
+    let below = position.floor() as usize;
+    let above = position.ceil() as usize;
+    sorted[below] + (position - below as f64) * (sorted[above] - sorted[below])
+}
+
+pub fn plot_ensemble(statistics : &[EnsembleStatistics], titles : &[&str], options : &PlotOptions) -> Result<(), Box<dyn Error>> {
+    /*
+    the mean of every ensemble as a line over a shaded one standard deviation band
+    and a lighter band between the outermost quantiles
+     */
+    if titles.len() != statistics.len() {
+        return Err(format!("got {} titles for {} ensembles", titles.len(), statistics.len()).into());
+    }
+    if statistics.iter().any(|s| s.times.len() < 2) {
+        return Err("ensembles need at least two samples to plot".into());
+    }
+    match options.backend()? {
+        PlotBackend::Svg => {
+            let root = SVGBackend::new(&options.path, options.size).into_drawing_area();
+            draw_ensemble(&root, statistics, titles, options)
+        },
+        PlotBackend::Png => {
+            let root = BitMapBackend::new(&options.path, options.size).into_drawing_area();
+            draw_ensemble(&root, statistics, titles, options)
+        },
+    }
+}
+
+fn draw_ensemble<DB : DrawingBackend>(root : &DrawingArea<DB, Shift>, statistics : &[EnsembleStatistics], titles : &[&str], options : &PlotOptions) -> Result<(), Box<dyn Error>>
+where DB::ErrorType : 'static {
+    root.fill(&WHITE)?;
+
+    //the outermost quantiles, or the mean when none were computed
+    let outer = |s : &EnsembleStatistics| -> (Vec<f64>, Vec<f64>) {
+        match (s.quantiles.first(), s.quantiles.last()) {
+            (Some(low), Some(high)) if s.quantiles.len() > 1 => (low.1.clone(), high.1.clone()),
+            _ => (s.mean.clone(), s.mean.clone()),
+        }
+    };
+    let x_range = options.x_range.unwrap_or_else(|| auto_range(statistics.iter().flat_map(|s| s.times.iter().copied())));
+    let y_range = options.y_range.unwrap_or_else(|| auto_range(statistics.iter().flat_map(|s| {
+        let (low, high) = outer(s);
+        let std_band : Vec<f64> = s.mean.iter().zip(&s.std).flat_map(|(m, d)| [m - d, m + d]).collect();
+        low.into_iter().chain(high).chain(std_band)
+    })));
+
+    let mut chart = ChartBuilder::on(root)
+        .caption(&options.title, ("sans-serif", 40).into_font())
+        .margin(10)
+        .x_label_area_size(30)
+        .y_label_area_size(60)
+        .build_cartesian_2d(x_range.0..x_range.1, y_range.0..y_range.1)?;
+
+    chart.configure_mesh()
+        .x_desc(&options.x_label)
+        .y_desc(&options.y_label)
+        .draw()?;
+
+    let colors = gradient_colors(statistics.len());
+    let band = |times : &[f64], low : &[f64], high : &[f64]| -> Vec<(f64, f64)> {
+        times.iter().copied().zip(high.iter().copied())
+            .chain(times.iter().copied().zip(low.iter().copied()).rev())
+            .collect()
+    };
+    for (i, s) in statistics.iter().enumerate() {
+        let line_style = options.line_styles.get(i).copied().unwrap_or_default();
+        let color = line_style.color.unwrap_or(colors[i]);
+        let (low, high) = outer(s);
+        chart.draw_series(std::iter::once(Polygon::new(band(&s.times, &low, &high), color.mix(0.15).filled())))?;
+        let std_low : Vec<f64> = s.mean.iter().zip(&s.std).map(|(m, d)| m - d).collect();
+        let std_high : Vec<f64> = s.mean.iter().zip(&s.std).map(|(m, d)| m + d).collect();
+        chart.draw_series(std::iter::once(Polygon::new(band(&s.times, &std_low, &std_high), color.mix(0.3).filled())))?;
+        let style = color.stroke_width(line_style.width);
+        chart.draw_series(LineSeries::new(s.times.iter().copied().zip(s.mean.iter().copied()), style))?
+            .label(titles[i])
+            .legend(move |(x, y)| PathElement::new(vec![(x, y), (x + 20, y)], style));
+    }
+
+    chart.configure_series_labels()
+        .position(SeriesLabelPosition::UpperRight)
+        .border_style(BLACK)
+        .background_style(WHITE.mix(0.8))
+        .draw()?;
+
+    root.present()?;
+
+    Ok(())
+}
+
+#[test]
+fn test_sampler_from_str() {
+    assert_eq!("2.5".parse::<Sampler>().unwrap(), Sampler::Fixed(2.5));
+    assert_eq!("uniform:1:3".parse::<Sampler>().unwrap(), Sampler::Uniform(1.0, 3.0));
+    assert_eq!("normal:2:0.1".parse::<Sampler>().unwrap(), Sampler::Normal(2.0, 0.1));
+    assert!("uniform:3:1".parse::<Sampler>().is_err());
+    assert!("normal:2".parse::<Sampler>().is_err());
+    assert!("gamma:1:1".parse::<Sampler>().is_err());
+    for invalid in ["uniform:1:inf", "uniform:NaN:1", "normal:2:inf", "lognormal:NaN:1", "inf", "NaN"] {
+        assert!(invalid.parse::<Sampler>().is_err(), "{}", invalid);
+    }
+
+    let mut rng = StdRng::seed_from_u64(0);
+    assert!((0..100).all(|_| (1.0..3.0).contains(&Sampler::Uniform(1.0, 3.0).sample(&mut rng))));
+    assert!((0..100).all(|_| Sampler::LogNormal(0.0, 1.0).sample(&mut rng) > 0.0));
+}
+
+#[test]
+fn test_run_ensemble_is_reproducible() {
+    let draw = |_i : usize, rng : &mut StdRng| rng.gen::<f64>();
+    let runs = run_ensemble(64, 5, draw);
+    assert_eq!(runs, run_ensemble(64, 5, draw));
+    assert_ne!(runs, run_ensemble(64, 6, draw));
+    //realization i + 1 of one seed is not realization i of the next
+    assert_ne!(runs[1..], run_ensemble(64, 6, draw)[..63]);
+}
+
+#[test]
+fn test_ensemble_statistics() {
+    let times = [0.0, 1.0];
+    let runs = vec![vec![1.0, 0.0], vec![2.0, 0.0], vec![3.0, 0.0], vec![4.0, 0.0]];
+    let statistics = EnsembleStatistics::from_runs(&times, &runs, &[0.0, 0.5, 1.0]).unwrap();
+    assert_eq!(statistics.mean, vec![2.5, 0.0]);
+    assert!((statistics.std[0] - 1.25f64.sqrt()).abs() < 1e-12);
+    assert_eq!(statistics.quantiles[0].1, vec![1.0, 0.0]);
+    assert_eq!(statistics.quantiles[1].1, vec![2.5, 0.0]);
+    assert_eq!(statistics.quantiles[2].1, vec![4.0, 0.0]);
+    assert!(EnsembleStatistics::from_runs(&times, &[vec![1.0]], &[]).is_err());
+    assert!(EnsembleStatistics::from_runs(&times, &runs, &[1.5]).is_err());
+}
@@ -0,0 +1,319 @@
+use crate::math::{cross, dot, norm, normalize};
+use crate::potential::PairPotential;
+use rayon::prelude::*;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::f64::consts::PI;
+use std::str::FromStr;
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(try_from = "String")]
+pub enum ForceMethod {
+    AllPairs, //every pair evaluated once, exact
+    Cells(Option<f64>), //only pairs closer than the cutoff arclength (m), the range of the potential when not given
+    BarnesHut(f64), //far clusters act as one particle once their size over distance is below this opening angle
+}
+
+impl FromStr for ForceMethod {
+    type Err = String;
+
+    fn from_str(s : &str) -> Result<Self, Self::Err> {
+        //"all_pairs", "cells", "cells:CUTOFF" or "barnes_hut:THETA"
+        let positive = |value : &str| match value.parse::<f64>() {
+            Ok(value) if value.is_finite() && value > 0.0 => Ok(value),
+            _ => Err(format!("the parameter of '{}' must be positive", s)),
+        };
+        match s.split_once(':') {
+            None if s == "all_pairs" => Ok(ForceMethod::AllPairs),
+            None if s == "cells" => Ok(ForceMethod::Cells(None)),
+            None if s == "barnes_hut" => Ok(ForceMethod::BarnesHut(0.5)),
+            Some(("cells", cutoff)) => Ok(ForceMethod::Cells(Some(positive(cutoff)?))),
+            Some(("barnes_hut", theta)) => Ok(ForceMethod::BarnesHut(positive(theta)?)),
+            _ => Err(format!("expected all_pairs, cells[:CUTOFF] or barnes_hut[:THETA], got '{}'", s)),
+        }
+    }
+}
+
+impl TryFrom<String> for ForceMethod {
+    type Error = String;
+
+    fn try_from(s : String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+pub fn pair_forces(method : ForceMethod, potential : &dyn PairPotential, unit_vectors : &[[f64;3]], r : f64) -> Vec<[f64;3]> {
+    //tangential force on every particle from the others, see ForceMethod for what is approximated
+    match method {
+        ForceMethod::AllPairs => symmetric_forces(potential, unit_vectors, r),
+        ForceMethod::Cells(cutoff) => {
+            let grid = CellGrid::new(unit_vectors, cutoff.or(potential.range(r)).unwrap_or(PI * r) / r);
+            (0..unit_vectors.len()).into_par_iter().map(|i| {
+                let mut force = [0.0; 3];
+                grid.visit(unit_vectors, i, |j| add(&mut force, &tangential_force(&unit_vectors[i], &unit_vectors[j], r, potential)));
+                force
+            }).collect()
+        },
+        ForceMethod::BarnesHut(theta) => {
+            let tree = Octree::new(unit_vectors);
+            (0..unit_vectors.len()).into_par_iter().map(|i| {
+                let mut force = [0.0; 3];
+                tree.visit(unit_vectors, i, theta, |e_j, weight| {
+                    let f = tangential_force(&unit_vectors[i], e_j, r, potential);
+                    add(&mut force, &[weight * f[0], weight * f[1], weight * f[2]]);
+                });
+                force
+            }).collect()
+        },
+    }
+}
+
+pub fn pair_energy(method : ForceMethod, potential : &dyn PairPotential, unit_vectors : &[[f64;3]], r : f64) -> f64 {
+    //potential energy of all pairs, with the same approximations as pair_forces
+    let energy = |e_i : &[f64;3], e_j : &[f64;3]| potential.energy(dot(e_i, e_j).clamp(-1.0, 1.0).acos(), r);
+    let n = unit_vectors.len();
+    match method {
+        ForceMethod::AllPairs => (0..n).into_par_iter().map(|i| {
+            (i + 1..n).map(|j| energy(&unit_vectors[i], &unit_vectors[j])).sum::<f64>()
+        }).sum(),
+        ForceMethod::Cells(cutoff) => {
+            let grid = CellGrid::new(unit_vectors, cutoff.or(potential.range(r)).unwrap_or(PI * r) / r);
+            (0..n).into_par_iter().map(|i| {
+                let mut sum = 0.0;
+                grid.visit(unit_vectors, i, |j| if j > i {sum += energy(&unit_vectors[i], &unit_vectors[j])});
+                sum
+            }).sum()
+        },
+        ForceMethod::BarnesHut(theta) => {
+            //every pair is seen from both ends
+            let tree = Octree::new(unit_vectors);
+            0.5 * (0..n).into_par_iter().map(|i| {
+                let mut sum = 0.0;
+                tree.visit(unit_vectors, i, theta, |e_j, weight| sum += weight * energy(&unit_vectors[i], e_j));
+                sum
+            }).sum::<f64>()
+        },
+    }
+}
+
+pub(crate) fn tangential_force<P : PairPotential + ?Sized>(e_i : &[f64;3], e_j : &[f64;3], r : f64, potential : &P) -> [f64;3] {
+    let cos_angle = dot(e_i, e_j).clamp(-1.0, 1.0);
+    //e_j - cos(angle) e_i = (e_i x e_j) x e_i, its length is sin(angle)
+    let tangent = cross(&cross(e_i, e_j), e_i);
+    let sin_angle = norm(&tangent);
+    if sin_angle < 1e-12 {
+        //coincident or antipodal, there is no preferred direction
+        return [0.0; 3];
+    }
+    let f_tangent = potential.derivative(cos_angle.acos(), r) / sin_angle;
+    [f_tangent * tangent[0], f_tangent * tangent[1], f_tangent * tangent[2]]
+}
+
+fn symmetric_forces(potential : &dyn PairPotential, unit_vectors : &[[f64;3]], r : f64) -> Vec<[f64;3]> {
+    /*
+    the potential is evaluated once per pair and the force added to both ends
+    every rayon job accumulates into its own buffer, the buffers are summed at the end
+     */
+    let n = unit_vectors.len();
+    (0..n).into_par_iter()
+        .with_min_len(64)
+        .fold(|| vec![[0.0; 3]; n], |mut forces, i| {
+            let e_i = &unit_vectors[i];
+            for (j, e_j) in unit_vectors.iter().enumerate().skip(i + 1) {
+                let cos_angle = dot(e_i, e_j).clamp(-1.0, 1.0);
+                let sin_angle = norm(&cross(e_i, e_j));
+                if sin_angle < 1e-12 {
+                    continue;
+                }
+                let f_tangent = potential.derivative(cos_angle.acos(), r) / sin_angle;
+                //towards the other particle in both tangent planes
+                add(&mut forces[i], &[0, 1, 2].map(|axis| f_tangent * (e_j[axis] - cos_angle * e_i[axis])));
+                add(&mut forces[j], &[0, 1, 2].map(|axis| f_tangent * (e_i[axis] - cos_angle * e_j[axis])));
+            }
+            forces
+        })
+        .reduce(|| vec![[0.0; 3]; n], |mut a, b| {
+            a.iter_mut().zip(&b).for_each(|(f, g)| add(f, g));
+            a
+        })
+}
+
+fn add(a : &mut [f64;3], b : &[f64;3]) {
+    a[0] += b[0];
+    a[1] += b[1];
+    a[2] += b[2];
+}
+
+struct CellGrid {
+    //particles hashed into cubes as wide as the cutoff chord, so neighbors are in the 27 surrounding cubes
+    size : f64,
+    cos_cutoff : f64,
+    cells : HashMap<[i64;3], Vec<usize>>,
+}
+
+impl CellGrid {
+    fn new(unit_vectors : &[[f64;3]], cutoff_angle : f64) -> Self {
+        let cutoff_angle = cutoff_angle.min(PI);
+        //never more cells per axis than particles, a tiny cutoff would only make empty cells
+        let size = (2.0 * (cutoff_angle / 2.0).sin()).max(2.0 / unit_vectors.len().max(1) as f64);
+        let mut cells : HashMap<[i64;3], Vec<usize>> = HashMap::new();
+        for (i, e) in unit_vectors.iter().enumerate() {
+            cells.entry(Self::key(e, size)).or_default().push(i);
+        }
+        CellGrid {size, cos_cutoff : cutoff_angle.cos(), cells}
+    }
+
+    fn key(e : &[f64;3], size : f64) -> [i64;3] {
+        e.map(|x| ((x + 1.0) / size).floor() as i64)
+    }
+
+    fn visit<F : FnMut(usize)>(&self, unit_vectors : &[[f64;3]], i : usize, mut f : F) {
+        //calls f with every other particle within the cutoff of particle i
+        let key = Self::key(&unit_vectors[i], self.size);
+        for dx in -1..=1 {
+            for dy in -1..=1 {
+                for dz in -1..=1 {
+                    let Some(cell) = self.cells.get(&[key[0] + dx, key[1] + dy, key[2] + dz]) else {
+                        continue;
+                    };
+                    for &j in cell {
+                        if j != i && dot(&unit_vectors[i], &unit_vectors[j]) >= self.cos_cutoff {
+                            f(j);
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+struct Node {
+    center : [f64;3],
+    half : f64, //half the width of the cube
+    count : f64,
+    mean : [f64;3], //mean of the unit vectors inside
+    direction : Option<[f64;3]>, //the mean put back on the sphere, None when the particles cancel out
+    children : Vec<usize>,
+    particles : Vec<usize>, //only filled in leaves
+}
+
+struct Octree {
+    nodes : Vec<Node>,
+}
+
+impl Octree {
+    const LEAF_SIZE : usize = 8;
+
+    fn new(unit_vectors : &[[f64;3]]) -> Self {
+        let mut tree = Octree {nodes : Vec::new()};
+        tree.build(unit_vectors, (0..unit_vectors.len()).collect(), [0.0; 3], 1.0, 0);
+        tree
+    }
+
+    fn build(&mut self, unit_vectors : &[[f64;3]], particles : Vec<usize>, center : [f64;3], half : f64, depth : usize) -> usize {
+        let mut sum = [0.0; 3];
+        for &i in &particles {
+            add(&mut sum, &unit_vectors[i]);
+        }
+        let count = particles.len() as f64;
+        let direction = (norm(&sum) > 1e-12 * count).then(|| normalize(&sum));
+        let index = self.nodes.len();
+        self.nodes.push(Node {center, half, count, mean : sum.map(|x| x / count), direction, children : Vec::new(), particles : Vec::new()});
+        //coincident particles can not be split, the depth limit stops that
+        if particles.len() <= Self::LEAF_SIZE || depth > 40 {
+            self.nodes[index].particles = particles;
+            return index;
+        }
+        let mut octants : [Vec<usize>; 8] = Default::default();
+        for i in particles {
+            let e = &unit_vectors[i];
+            let octant = (0..3).filter(|&axis| e[axis] >= center[axis]).map(|axis| 1 << axis).sum::<usize>();
+            octants[octant].push(i);
+        }
+        for (octant, members) in octants.into_iter().enumerate() {
+            if members.is_empty() {
+                continue;
+            }
+            let child_center = [0, 1, 2].map(|axis| center[axis] + if octant & (1 << axis) != 0 {half / 2.0} else {-half / 2.0});
+            let child = self.build(unit_vectors, members, child_center, half / 2.0, depth + 1);
+            self.nodes[index].children.push(child);
+        }
+        index
+    }
+
+    fn visit<F : FnMut(&[f64;3], f64)>(&self, unit_vectors : &[[f64;3]], i : usize, theta : f64, mut f : F) {
+        /*
+        calls f with the direction and weight of every particle or cluster acting on particle i
+        a cluster not containing i counts as its particles at their mean direction when its width over distance is below theta
+         */
+        let e_i = &unit_vectors[i];
+        let mut stack = vec![0];
+        while let Some(index) = stack.pop() {
+            let node = &self.nodes[index];
+            if node.children.is_empty() {
+                for &j in node.particles.iter().filter(|&&j| j != i) {
+                    f(&unit_vectors[j], 1.0);
+                }
+                continue;
+            }
+            let distance = norm(&[e_i[0] - node.mean[0], e_i[1] - node.mean[1], e_i[2] - node.mean[2]]);
+            let contains = (0..3).all(|axis| (e_i[axis] - node.center[axis]).abs() <= node.half);
+            match node.direction {
+                Some(direction) if !contains && 2.0 * node.half < theta * distance => f(&direction, node.count),
+                _ => stack.extend(&node.children),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+fn test_points(n : usize) -> Vec<[f64;3]> {
+    crate::initial::uniform_unit_vectors(n, &mut <rand::rngs::StdRng as rand::SeedableRng>::seed_from_u64(11))
+}
+
+#[test]
+fn test_methods_match_the_direct_sum() {
+    use crate::potential::{Riesz, SoftSphere};
+    let r = 2.0;
+    let points = test_points(500);
+    let direct = |potential : &dyn PairPotential| -> Vec<[f64;3]> {
+        points.iter().enumerate().map(|(i, e_i)| {
+            let mut force = [0.0; 3];
+            for (j, e_j) in points.iter().enumerate() {
+                if i != j {
+                    add(&mut force, &tangential_force(e_i, e_j, r, potential));
+                }
+            }
+            force
+        }).collect()
+    };
+    let largest_error = |a : &[[f64;3]], b : &[[f64;3]]| a.iter().zip(b)
+        .map(|(u, v)| norm(&[u[0] - v[0], u[1] - v[1], u[2] - v[2]]) / norm(v).max(1e-12))
+        .fold(0.0, f64::max);
+
+    //all pairs is exact, and so are cells for a potential that ends at its range
+    let coulomb = Riesz {k : 1.0, s : 1.0};
+    let exact = direct(&coulomb);
+    assert!(largest_error(&pair_forces(ForceMethod::AllPairs, &coulomb, &points, r), &exact) < 1e-9);
+    let soft = SoftSphere {k : 10.0, sigma : 0.4};
+    assert!(largest_error(&pair_forces(ForceMethod::Cells(None), &soft, &points, r), &direct(&soft)) < 1e-9);
+    let soft_energy = pair_energy(ForceMethod::AllPairs, &soft, &points, r);
+    assert!((pair_energy(ForceMethod::Cells(None), &soft, &points, r) - soft_energy).abs() < 1e-9 * soft_energy);
+
+    //barnes hut gets closer with a smaller opening angle
+    let loose = largest_error(&pair_forces(ForceMethod::BarnesHut(0.8), &coulomb, &points, r), &exact);
+    let tight = largest_error(&pair_forces(ForceMethod::BarnesHut(0.2), &coulomb, &points, r), &exact);
+    assert!(tight < loose && tight < 0.02, "errors {} and {}", tight, loose);
+    let energy = pair_energy(ForceMethod::AllPairs, &coulomb, &points, r);
+    assert!((pair_energy(ForceMethod::BarnesHut(0.2), &coulomb, &points, r) - energy).abs() < 1e-4 * energy);
+}
+
+#[test]
+fn test_force_method_from_str() {
+    assert_eq!("all_pairs".parse::<ForceMethod>().unwrap(), ForceMethod::AllPairs);
+    assert_eq!("cells".parse::<ForceMethod>().unwrap(), ForceMethod::Cells(None));
+    assert_eq!("cells:0.5".parse::<ForceMethod>().unwrap(), ForceMethod::Cells(Some(0.5)));
+    assert_eq!("barnes_hut:0.3".parse::<ForceMethod>().unwrap(), ForceMethod::BarnesHut(0.3));
+    assert!("cells:-1".parse::<ForceMethod>().is_err());
+    assert!("octree".parse::<ForceMethod>().is_err());
+}
@@ -0,0 +1,228 @@
+use crate::math::{cross, dot, normalize};
+use rand::Rng;
+use rand_distr::StandardNormal;
+use serde::Deserialize;
+use std::error::Error;
+use std::f64::consts::PI;
+use std::fs;
+use std::str::FromStr;
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(try_from = "String")]
+pub enum Initializer {
+    Uniform, //independent and uniform over the whole sphere
+    Fibonacci, //the fibonacci lattice, evenly spread and the same every run
+    Cap(f64), //uniform inside a cap of this half angle (rad) around the north pole
+    Polyhedron(f64), //vertices of the platonic solid with N vertices, each moved by about this angle (rad)
+    File(String), //lines of "x y z" or "theta phi"
+}
+
+impl Initializer {
+    pub fn unit_vectors<R : Rng>(&self, n : usize, rng : &mut R) -> Result<Vec<[f64;3]>, Box<dyn Error>> {
+        match self {
+            Initializer::Uniform => Ok(uniform_unit_vectors(n, rng)),
+            Initializer::Fibonacci => Ok(fibonacci_unit_vectors(n)),
+            Initializer::Cap(angle) => {
+                //uniform in z over the cap is uniform in area
+                let z_min = angle.min(PI).cos();
+                Ok((0..n).map(|_| {
+                    let z : f64 = z_min + (1.0 - z_min) * rng.gen::<f64>();
+                    let phi : f64 = rng.gen_range(-PI..PI);
+                    let s = (1.0 - z * z).max(0.0).sqrt();
+                    [s * phi.cos(), s * phi.sin(), z]
+                }).collect())
+            },
+            Initializer::Polyhedron(perturbation) => {
+                let vertices = polyhedron_vertices(n).ok_or(format!("there is no platonic solid with {} vertices, use 4, 6, 8, 12 or 20", n))?;
+                Ok(vertices.iter().map(|e| {
+                    let (t_1, t_2) = tangent_basis(e);
+                    let (a, b) : (f64, f64) = (rng.sample(StandardNormal), rng.sample(StandardNormal));
+                    normalize(&[0, 1, 2].map(|axis| e[axis] + perturbation * (a * t_1[axis] + b * t_2[axis])))
+                }).collect())
+            },
+            Initializer::File(filename) => {
+                let points = read_points(filename)?;
+                if points.len() != n {
+                    return Err(format!("{} lists {} particles, expected {}", filename, points.len(), n).into());
+                }
+                Ok(points)
+            },
+        }
+    }
+}
+
+impl FromStr for Initializer {
+    type Err = String;
+
+    fn from_str(s : &str) -> Result<Self, Self::Err> {
+        //"uniform", "fibonacci", "cap:ANGLE", "polyhedron[:PERTURBATION]" or "file:PATH"
+        let angle = |value : &str| match value.parse::<f64>() {
+            Ok(value) if value.is_finite() && value >= 0.0 => Ok(value),
+            _ => Err(format!("the angle of '{}' must be non-negative", s)),
+        };
+        match s.split_once(':') {
+            None if s == "uniform" => Ok(Initializer::Uniform),
+            None if s == "fibonacci" => Ok(Initializer::Fibonacci),
+            None if s == "polyhedron" => Ok(Initializer::Polyhedron(0.05)),
+            Some(("cap", value)) => Ok(Initializer::Cap(angle(value)?)),
+            Some(("polyhedron", value)) => Ok(Initializer::Polyhedron(angle(value)?)),
+            Some(("file", path)) if !path.is_empty() => Ok(Initializer::File(path.to_string())),
+            _ => Err(format!("expected uniform, fibonacci, cap:ANGLE, polyhedron[:ANGLE] or file:PATH, got '{}'", s)),
+        }
+    }
+}
+
+impl TryFrom<String> for Initializer {
+    type Error = String;
+
+    fn try_from(s : String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+pub fn uniform_unit_vectors<R : Rng>(n : usize, rng : &mut R) -> Vec<[f64;3]> {
+    //uniform on the sphere, z uniform in (-1, 1) and phi uniform in (-pi, pi)
+    (0..n).map(|_| {
+        let z : f64 = rng.gen_range(-1.0..1.0);
+        let phi : f64 = rng.gen_range(-PI..PI);
+        let s = (1.0 - z * z).sqrt();
+        [s * phi.cos(), s * phi.sin(), z]
+    }).collect()
+}
+
+pub fn fibonacci_unit_vectors(n : usize) -> Vec<[f64;3]> {
+    //equal area bands in z, successive points turned by the golden angle
+    let golden_angle = PI * (3.0 - 5f64.sqrt());
+    (0..n).map(|i| {
+        let z = 1.0 - (2 * i + 1) as f64 / n as f64;
+        let phi = golden_angle * i as f64;
+        let s = (1.0 - z * z).sqrt();
+        [s * phi.cos(), s * phi.sin(), z]
+    }).collect()
+}
+
+pub fn polyhedron_vertices(n : usize) -> Option<Vec<[f64;3]>> {
+    //vertices of the tetrahedron, octahedron, cube, icosahedron or dodecahedron on the unit sphere
+    let phi = (1.0 + 5f64.sqrt()) / 2.0;
+    let signs = |a : f64, b : f64| [(a, b), (a, -b), (-a, b), (-a, -b)];
+    let vertices : Vec<[f64;3]> = match n {
+        4 => vec![[1.0, 1.0, 1.0], [1.0, -1.0, -1.0], [-1.0, 1.0, -1.0], [-1.0, -1.0, 1.0]],
+        6 => vec![[1.0, 0.0, 0.0], [-1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, -1.0, 0.0], [0.0, 0.0, 1.0], [0.0, 0.0, -1.0]],
+        8 => signs(1.0, 1.0).into_iter().flat_map(|(a, b)| [[a, b, 1.0], [a, b, -1.0]]).collect(),
+        //cyclic permutations of (0, +-1, +-phi)
+        12 => signs(1.0, phi).into_iter().flat_map(|(a, b)| [[0.0, a, b], [a, b, 0.0], [b, 0.0, a]]).collect(),
+        //the cube and cyclic permutations of (0, +-1/phi, +-phi)
+        20 => signs(1.0, 1.0).into_iter().flat_map(|(a, b)| [[a, b, 1.0], [a, b, -1.0]])
+            .chain(signs(1.0 / phi, phi).into_iter().flat_map(|(a, b)| [[0.0, a, b], [a, b, 0.0], [b, 0.0, a]]))
+            .collect(),
+        _ => return None,
+    };
+    Some(vertices.iter().map(normalize).collect())
+}
+
+pub fn thermal_velocities<R : Rng>(unit_vectors : &[[f64;3]], temperature : f64, mass : f64, rng : &mut R) -> Vec<[f64;3]> {
+    //tangential velocities with both components normal with variance T / M, boltzmann constant 1
+    let sigma = (temperature / mass).sqrt();
+    unit_vectors.iter().map(|e| {
+        let (t_1, t_2) = tangent_basis(e);
+        let (a, b) : (f64, f64) = (rng.sample(StandardNormal), rng.sample(StandardNormal));
+        [0, 1, 2].map(|axis| sigma * (a * t_1[axis] + b * t_2[axis]))
+    }).collect()
+}
+
+fn tangent_basis(e : &[f64;3]) -> ([f64;3], [f64;3]) {
+    //two orthonormal vectors perpendicular to e, starting from the axis furthest from e
+    let axis = if e[0].abs() < 0.5 {[1.0, 0.0, 0.0]} else if e[1].abs() < 0.5 {[0.0, 1.0, 0.0]} else {[0.0, 0.0, 1.0]};
+    let t_1 = normalize(&cross(e, &axis));
+    (t_1, cross(e, &t_1))
+}
+
+fn read_points(filename : &str) -> Result<Vec<[f64;3]>, Box<dyn Error>> {
+    let text = fs::read_to_string(filename).map_err(|e| format!("could not open particle list '{}': {}", filename, e))?;
+    let mut points = Vec::new();
+    for (number, line) in text.lines().enumerate() {
+        let line = line.split('#').next().unwrap_or("").trim();
+        if line.is_empty() {
+            continue;
+        }
+        let error = || format!("{} line {}: expected 'x y z' or 'theta phi', got '{}'", filename, number + 1, line);
+        let values = line.split([' ', ',', '\t']).filter(|field| !field.is_empty())
+            .map(|field| field.parse::<f64>())
+            .collect::<Result<Vec<f64>, _>>()
+            .map_err(|_| error())?;
+        if let Some(value) = values.iter().find(|v| !v.is_finite()) {
+            return Err(format!("{} line {}: coordinates must be finite, got {}", filename, number + 1, value).into());
+        }
+        let point = match values.as_slice() {
+            [x, y, z] if dot(&[*x, *y, *z], &[*x, *y, *z]) > 0.0 => normalize(&[*x, *y, *z]),
+            [theta, phi] => [theta.sin() * phi.cos(), theta.sin() * phi.sin(), theta.cos()],
+            _ => return Err(error().into()),
+        };
+        //x y z beyond about 1e154 overflow the length and normalize to zero
+        if (dot(&point, &point) - 1.0).abs() > 1e-9 {
+            return Err(format!("{} line {}: '{}' is too large to normalize", filename, number + 1, line).into());
+        }
+        points.push(point);
+    }
+    Ok(points)
+}
+
+#[test]
+fn test_initializers() {
+    use rand::{rngs::StdRng, SeedableRng};
+    let mut rng = StdRng::seed_from_u64(0);
+    //uniform sampling has a mean of zero and a third of the unit length squared along every axis
+    let points = Initializer::Uniform.unit_vectors(20000, &mut rng).unwrap();
+    let mean_z = points.iter().map(|e| e[2]).sum::<f64>() / points.len() as f64;
+    let mean_z2 = points.iter().map(|e| e[2] * e[2]).sum::<f64>() / points.len() as f64;
+    assert!(mean_z.abs() < 0.02 && (mean_z2 - 1.0 / 3.0).abs() < 0.01);
+    assert_eq!(Initializer::Uniform.unit_vectors(5, &mut StdRng::seed_from_u64(3)).unwrap(),
+        Initializer::Uniform.unit_vectors(5, &mut StdRng::seed_from_u64(3)).unwrap());
+
+    let fibonacci = Initializer::Fibonacci.unit_vectors(100, &mut rng).unwrap();
+    assert!(fibonacci.iter().all(|e| (dot(e, e) - 1.0).abs() < 1e-12));
+    assert!((fibonacci.iter().map(|e| e[2]).sum::<f64>()).abs() < 1e-9);
+
+    let cap = Initializer::Cap(0.3).unit_vectors(500, &mut rng).unwrap();
+    assert!(cap.iter().all(|e| e[2] >= 0.3f64.cos() - 1e-12));
+
+    for n in [4, 6, 8, 12, 20] {
+        let vertices = polyhedron_vertices(n).unwrap();
+        //every vertex has the same nearest neighbor distance
+        let nearest : Vec<f64> = vertices.iter().enumerate().map(|(i, a)| {
+            vertices.iter().enumerate().filter(|(j, _)| *j != i).map(|(_, b)| dot(a, b).clamp(-1.0, 1.0).acos()).fold(PI, f64::min)
+        }).collect();
+        assert!(nearest.iter().all(|d| (d - nearest[0]).abs() < 1e-12), "N={}", n);
+        let perturbed = Initializer::Polyhedron(0.01).unit_vectors(n, &mut rng).unwrap();
+        assert!(perturbed.iter().zip(&vertices).all(|(a, b)| dot(a, b) > 0.99));
+    }
+    assert!(Initializer::Polyhedron(0.0).unit_vectors(5, &mut rng).is_err());
+
+    let path = std::env::temp_dir().join(format!("sphere_points_{}.txt", std::process::id()));
+    fs::write(&path, "# particles\n0 0 2\n1.5707963267948966, 0\n").unwrap();
+    let file = Initializer::File(path.to_str().unwrap().to_string());
+    let points = file.unit_vectors(2, &mut rng).unwrap();
+    assert_eq!(points[0], [0.0, 0.0, 1.0]);
+    assert!((points[1][0] - 1.0).abs() < 1e-12);
+    assert!(file.unit_vectors(3, &mut rng).is_err());
+    for invalid in ["0 0 NaN\n1 0 0\n", "inf 0 0\n1 0 0\n", "0.5 -inf\n1 0 0\n", "1e300 0 0\n1 0 0\n"] {
+        fs::write(&path, invalid).unwrap();
+        assert!(file.unit_vectors(2, &mut rng).is_err(), "{:?}", invalid);
+    }
+    fs::remove_file(&path).unwrap();
+
+    assert!("cap:-1".parse::<Initializer>().is_err());
+    assert_eq!("polyhedron".parse::<Initializer>().unwrap(), Initializer::Polyhedron(0.05));
+}
+
+#[test]
+fn test_thermal_velocities_are_tangential_with_equipartition() {
+    use rand::{rngs::StdRng, SeedableRng};
+    let mut rng = StdRng::seed_from_u64(1);
+    let points = uniform_unit_vectors(20000, &mut rng);
+    let velocities = thermal_velocities(&points, 3.0, 2.0, &mut rng);
+    assert!(points.iter().zip(&velocities).all(|(e, v)| dot(e, v).abs() < 1e-12));
+    //two degrees of freedom, each with T / 2 of kinetic energy
+    let kinetic = velocities.iter().map(|v| 0.5 * 2.0 * dot(v, v)).sum::<f64>() / points.len() as f64;
+    assert!((kinetic - 3.0).abs() < 0.1);
+}
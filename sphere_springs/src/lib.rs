@@ -1,2 +1,16 @@
 pub mod math;
-pub mod draw_3d;
\ No newline at end of file
+pub mod draw_3d;
+pub mod config;
+pub mod model;
+pub use sim_common::{plot_2d, stochastic, trajectory, trajectory_io};
+pub mod spectrum;
+pub mod ensemble;
+pub mod potential;
+pub mod topology;
+pub mod minimize;
+pub mod forces;
+pub mod initial;
+pub mod diagnostics;
+pub mod stopping;
+pub mod voronoi;
+pub mod pair_statistics;
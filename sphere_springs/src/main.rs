@@ -1,154 +1,271 @@
-use sphere_springs::math::{SphericalPoint,RK4,cross, dot, normalize};
-use sphere_springs::draw_3d::draw_3d;
-use num::complex:: Complex64;
-use rayon::prelude::*;
+use sphere_springs::config::Config;
+use sphere_springs::model::SphereSprings;
+use sphere_springs::trajectory::Trajectory;
+use sphere_springs::spectrum::{particle_spectra, sum_spectra};
+use sim_common::spectrum::{plot_spectrum, Window};
+use sim_common::stochastic::stream_seed;
+use sphere_springs::plot_2d::PlotOptions;
+use sphere_springs::draw_3d::{draw_3d, replay_3d};
+use sphere_springs::ensemble::{run_ensemble, EnsembleStatistics, plot_ensemble};
+use sphere_springs::diagnostics::{add_diagnostics, check_conservation, diagnostics_table, plot_diagnostics};
+use sphere_springs::stopping::{StoppingCriteria, StopReason};
+use sphere_springs::voronoi::Tessellation;
+use sphere_springs::pair_statistics::{plot_pair_statistics, PairStatistics};
+use sphere_springs::minimize::{minimize_with_restarts, thomson_reference, MinimizeOptions};
+use rand::{rngs::StdRng, SeedableRng};
 use std::env;
-use lazy_static::lazy_static;
+use std::error::Error;
 
-fn get_argument<T: std::str::FromStr>(name: &str, default: T) -> T
-where
-    <T as std::str::FromStr>::Err: std::fmt::Debug,
-{
-    let args: Vec<String> = env::args().collect();
-    for i in 0..args.len() - 1 {
-        if args[i] == name {
-            if let Ok(val) = args[i + 1].parse::<T>() {
-                return val;
-            }
-        }
-    }
-    default
-}
-
-#[allow(non_snake_case)]
 fn main() {
-    const PI : f64 = std::f64::consts::PI;
     const TAU : f64 = std::f64::consts::TAU;
-    lazy_static! {
-        static ref R: f64 = get_argument("--R", 2.0);
-        static ref M: f64 = get_argument("--M", 1.0);
-        static ref K: f64 = get_argument("--K", 2.0);
-        static ref C: f64 = get_argument("--C", 1.0);
-        static ref N: usize = get_argument("--N", 4);
+    const MAX_PAIRS : usize = 1_000_000; //pairs the arclength summaries look at, beyond this they are sampled
+    let args: Vec<String> = env::args().skip(1).collect();
+    let config = match Config::from_args(&args) {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            eprintln!("Usage: sphere_springs [--config FILE] [--R radius] [--M mass] [--K stiffness] [--C damping] [--N particles] [--formulation cartesian|spherical] [--potential NAME[:PARAMETER]] [--network ring|nearest:k|delaunay|file:PATH] [--rest_length L] [--forces all_pairs|cells[:CUTOFF]|barnes_hut[:THETA]] [--init uniform|fibonacci|cap:ANGLE|polyhedron[:ANGLE]|file:PATH] [--temperature T] [--output FILE] [--replay FILE] [--draw true|false] [--record_every N] [--spectrum FILE] [--structure FILE] [--pair_statistics FILE] [--pair_plot FILE] [--bins N] [--analysis_window T] [--diagnostics FILE] [--diagnostics_plot FILE] [--drift_tolerance F] [--stop_kinetic_energy E] [--stop_speed V] [--stop_energy_change F] [--stop_window T] [--max_wall_time T] [--integrator rk4|euler_maruyama|heun] [--noise sigma] [--noise_type additive|multiplicative] [--seed N] [--ensemble COUNT] [--ensemble_plot FILE] [--vary_K DIST] [--vary_C DIST] [--minimize gradient_descent|lbfgs|fire] [--restarts N] [--tolerance F] [--max_iterations N]");
+            std::process::exit(1);
+        }
+    };
+    if let Some(replay) = &config.replay {
+        let trajectory = Trajectory::load(replay).expect("loading trajectory failed");
+        replay_3d(&trajectory).expect("replaying trajectory failed");
+        return;
     }
-
     let dt : f64 = 0.001; // seconds
-    let max_time : f64 = 10.0 * TAU / (*K / *M).sqrt();
+    let max_time : f64 = 10.0 * TAU / (config.stiffness / config.mass).sqrt();
     let iterations : usize = (max_time / dt) as usize;
 
-    fn free_length(angle : f64, angle0 : f64) -> f64 {
-        (Complex64::new(0.0, angle).exp() * Complex64::new(0.0, angle0).exp()).ln().im
+    if config.ensemble > 0 {
+        //mean pair arclength of many realizations, recorded every 0.1 s
+        let record_every = 100;
+        let runs = run_ensemble(config.ensemble, config.seed, |i, rng| -> Result<(Vec<f64>, Vec<f64>), String> {
+            let realization = Config {
+                stiffness : config.vary_stiffness.map(|k| k.sample(rng)).unwrap_or(config.stiffness),
+                damping : config.vary_damping.map(|c| c.sample(rng)).unwrap_or(config.damping),
+                //the seed rng was drawn from, like a single run the model takes its noise from a separate stream of it
+                seed : stream_seed(config.seed, i as u64),
+                ..config.clone()
+            };
+            //the sampled K and C can leave the physical range, e.g. a normal distribution reaching below 0
+            realization.validate().map_err(|e| format!("realization {}: {}", i, e))?;
+            let (model, x0) = SphereSprings::with_state(realization, rng).map_err(|e| format!("realization {}: {}", i, e))?;
+            let trajectory = model.simulate(&x0, dt, iterations.saturating_sub(1), record_every);
+            let mean_arclengths : Vec<f64> = trajectory.states.iter().map(|x| {
+                let arclengths = model.sampled_pair_arclengths(x, MAX_PAIRS, rng);
+                arclengths.iter().sum::<f64>() / arclengths.len().max(1) as f64
+            }).collect();
+            Ok((trajectory.times, mean_arclengths))
+        });
+        let runs = match runs.into_iter().collect::<Result<Vec<_>, _>>() {
+            Ok(runs) => runs,
+            Err(e) => {
+                eprintln!("Error: {}", e);
+                std::process::exit(1);
+            }
+        };
+        let times = runs[0].0.clone();
+        let values : Vec<Vec<f64>> = runs.into_iter().map(|run| run.1).collect();
+        let statistics = EnsembleStatistics::from_runs(&times, &values, &[0.05, 0.5, 0.95]).expect("aggregating ensemble failed");
+        let last = times.len() - 1;
+        println!("Ensemble of {}: final mean arclength {:.4} +- {:.4} (5%-95%: {:.4} - {:.4})",
+            config.ensemble, statistics.mean[last], statistics.std[last], statistics.quantiles[0].1[last], statistics.quantiles[2].1[last]);
+        let path = config.ensemble_plot.clone().unwrap_or("ensemble.svg".to_string());
+        let options = PlotOptions {
+            title : "Ensemble Mean Arclength".to_string(),
+            x_label : "Time (s)".to_string(),
+            y_label : "Mean pair arclength (m)".to_string(),
+            ..PlotOptions::with_path(&path).expect("unsupported ensemble plot format")
+        };
+        plot_ensemble(&[statistics], &["mean, 1 std, 5%-95%"], &options).expect("plotting ensemble failed");
+        println!("Saved the ensemble plot to {}.", path);
+        return;
     }
 
-    fn x_2_positions(x : &Vec<f64>) -> Vec<[f32;3]> {
-        //positions - [x,y,z]_1, [x,y,z]_2, ...
-        let mut positions: Vec<[f32;3]> = Vec::with_capacity(*N);
-        for i in 0..*N {
-            let tmp = SphericalPoint::new(*R, x[4*i], x[4*i+1]).xyz();
-            positions.push([tmp[0] as f32, tmp[1] as f32, tmp[2] as f32]);
+    if let Some(method) = config.minimize {
+        //energy minimum found directly on the sphere, networks are still built from a random configuration
+        let (model, _) = SphereSprings::with_state(config.clone(), &mut StdRng::seed_from_u64(config.seed)).expect("initializing the model failed");
+        let options = MinimizeOptions {
+            method,
+            max_iterations : config.max_iterations,
+            gradient_tolerance : config.tolerance,
+            restarts : config.restarts,
+            seed : config.seed,
+            ..MinimizeOptions::default()
+        };
+        let minima = minimize_with_restarts(&model, &options).expect("minimization failed");
+        for minimum in &minima {
+            println!("Energy {:.9} after {} iterations, largest force {:.3e}{}",
+                minimum.energy, minimum.iterations, minimum.max_force, if minimum.converged {""} else {" (not converged)"});
+        }
+        let best = &minima[0];
+        let hits = minima.iter().filter(|m| (m.energy - best.energy).abs() <= 1e-9 * best.energy.abs().max(1.0)).count();
+        println!("Lowest energy: {:.9}, reached by {} of {} restarts", best.energy, hits, minima.len());
+        if let Some(reference) = thomson_reference(&model) {
+            println!("Known Thomson minimum for N={}: {:.9} (difference {:.3e})", config.particles, reference, best.energy - reference);
         }
-        positions       
+        if let Some(output) = &config.output {
+            let mut trajectory = Trajectory::new(model.metadata(0.0), &model.state_names());
+            trajectory.push(0.0, &best.state(&model));
+            trajectory.save(output).expect("saving minimum failed");
+            println!("Saved the minimum to {}.", output);
+        }
+        if let Some(structure) = &config.structure {
+            analyze_structure(&model.unit_vectors(&best.state(&model)), config.radius, structure);
+        }
+        if config.pair_statistics.is_some() || config.pair_plot.is_some() {
+            let statistics = PairStatistics::new(&[model.unit_vectors(&best.state(&model))], config.radius, config.bins);
+            analyze_pairs(&statistics.expect("pair statistics failed"), &config);
+        }
+        return;
     }
 
     // build model
-    #[allow(unused_variables)]
-    fn f(t : f64, x : &Vec<f64>) -> Vec<f64> {
-        //x - [theta, phi, theta_dot, phi_dot]_1, [theta, phi, theta_dot, phi_dot]_2, ...
-    
-        let x_dot = (0..*N).into_par_iter().flat_map(|i| {
-            let (f_theta, f_phi) = (0..*N).into_par_iter()
-                .filter(|&j| i != j)
-                .map(|j| {
-                    let mut f_theta = 0.0;
-                    let mut f_phi = 0.0;
-    
-                    // compute f_k
-                    let sph_j = SphericalPoint::new(*R, x[4 * j], x[4 * j + 1]);
-                    let sph_i = SphericalPoint::new(*R, x[4 * i], x[4 * i + 1]);
-                    if let Some((axis, angle, arc)) = sph_i.axis_angle_arc(&sph_j) {
-                        let tangent = cross(&axis, &normalize(&sph_i.e_r()));
-                        let f_tangent = *K * *R * free_length(angle, PI);
-                        f_theta += f_tangent * dot(&tangent, &sph_i.e_theta());
-                        f_phi += f_tangent * dot(&tangent, &sph_i.e_phi());
-                    }
-    
-                    //compute f_d - abandoned, results are wierd... moved to friction with big sphere
-                    // let sph_j = SphericalPoint::new(R,x[4*j+2],x[4*j+3]);
-                    // let sph_i = SphericalPoint::new(R,x[4*i+2],x[4*i+3]);
-                    // if let Some((axis, angle, arc)) =  sph_i.axis_angle_arc(&sph_j) {                    
-                    //     let tangent = cross(&axis, &normalize(&sph_i.e_r()));
-                    //     let f_tangent = C * arc;
-                    //     f_theta += f_tangent * dot(&tangent, &sph_i.e_theta());
-                    //     f_phi += f_tangent * dot(&tangent, &sph_i.e_phi());
-                    // }
-    
-                    (f_theta, f_phi)
-                })
-                .reduce(|| (0.0, 0.0), |acc, val| (acc.0 + val.0, acc.1 + val.1));
-
-            let theta = x[4*i];
-            let phi = x[4*i+1];
-            let theta_dot = x[4*i+2];
-            let phi_dot = x[4*i+3];
-
-            // compute f_d
-            let v_theta = *R * theta_dot;
-            let v_phi = *R * theta.sin() * phi_dot;
-            let f_theta = f_theta - *C * v_theta;
-            let f_phi = f_phi - *C * v_phi;
-
-            //equations of motions with constant R
-            // https://en.wikipedia.org/wiki/Equations_of_motion
-            let theta_ddot = (f_theta/ *M + *R*phi_dot.powi(2)*theta.sin()*theta.cos())/ *R;
-            let phi_ddot = (f_phi / *M - 2.0* *R *theta_dot*phi_dot*theta.cos())/(*R * theta.sin());
-            
-            [theta_dot, phi_dot, theta_ddot, phi_ddot]
-        }).collect();
-        x_dot
+    let (model, x_0) = SphereSprings::with_state(config.clone(), &mut StdRng::seed_from_u64(config.seed)).expect("initializing the model failed");
+    if let Some(network) = &model.network {
+        println!("Spring network with {} edges.", network.edges.len());
+    }
+    //about 1000 recorded states, fewer when the states are large, so the diagnostics and analyses stay affordable for large N
+    let samples = (50_000_000 / model.state_size()).clamp(10, 1000);
+    let record_every = config.record_every.unwrap_or((iterations / samples).max(1));
+    let (mut trajectory, stop) = model.simulate_until(&x_0, dt, iterations.saturating_sub(1), record_every, &StoppingCriteria::from_config(&config));
+    if stop.reason == StopReason::MaxTime {
+        println!("Ran the full {:.3} s in {:.2} s.", stop.time, stop.wall_time);
+    } else {
+        println!("Stopped at t = {:.3} s after {:.2} s: {}.", stop.time, stop.wall_time, stop.reason);
     }
+    let x_k = trajectory.states[trajectory.len() - 1].clone();
 
-    let rk4 = RK4::new(dt, f);
-    // build initial state
-    let mut x_k : Vec<f64> = vec!(0.0; 4 * *N); //initial state
-    for i in 0..*N {
-        //random pitch theta
-        x_k[4*i] = PI/2.0 * (2.0 * rand::random::<f64>() - 1.0);
-        x_k[4*i+1] = PI * (2.0 * rand::random::<f64>() - 1.0);
-    }
-    let mut timestamps : Vec<f32> = Vec::with_capacity(iterations);
-    let mut positions : Vec<Vec<[f32;3]>> = Vec::with_capacity(iterations);
+    //energies and angular momentum, saved with the trajectory
+    add_diagnostics(&model, &mut trajectory);
+    for drift in check_conservation(&model, &trajectory, config.drift_tolerance).expect("checking conservation failed") {
+        let note = match (drift.conserved, drift.flagged) {
+            (false, _) => " (not conserved in this run)",
+            (true, true) => " - WARNING: drift above tolerance",
+            (true, false) => "",
+        };
+        println!("Relative {} drift: {:.3e}{}", drift.quantity.replace('_', " "), drift.relative, note);
+    }
+    if let Some(diagnostics) = &config.diagnostics {
+        diagnostics_table(&trajectory).and_then(|table| table.save(diagnostics)).expect("saving diagnostics failed");
+        println!("Saved the diagnostics to {}.", diagnostics);
+    }
+    if let Some(diagnostics_plot) = &config.diagnostics_plot {
+        let options = PlotOptions {
+            title : "Energy and Angular Momentum".to_string(),
+            size : (800, 800),
+            ..PlotOptions::with_path(diagnostics_plot).expect("unsupported diagnostics plot format")
+        };
+        plot_diagnostics(&trajectory, &options).expect("plotting diagnostics failed");
+    }
 
-    let mut t = 0.0;
-    for _ in 1..iterations {
-        t += dt;
-        x_k = rk4.propogate(t, &x_k);
-        
-        timestamps.push(t as f32);
-        positions.push(x_2_positions(&x_k));
+    if let Some(output) = &config.output {
+        trajectory.save(output).expect("saving trajectory failed");
+        println!("Saved the trajectory to {}.", output);
     }
 
-    //compute mean and std of arclength on last iteration
-    let mut sph_points : Vec<SphericalPoint> = Vec::with_capacity(*N);
-    for i in 0..*N {
-        sph_points.push(SphericalPoint::new(*R, x_k[4*i], x_k[4*i+1]));
-    }
-    let mut arclengths : Vec<f64> = Vec::with_capacity(*N * (*N-1));
-    for i in 0..*N {
-        for j in 0..*N {
-            if i == j {continue};
-            if let Some((_, _, arc)) = sph_points[i].axis_angle_arc(&sph_points[j]) {
-            arclengths.push(arc);
-            }
+    if let Some(spectrum_path) = &config.spectrum {
+        if let Err(e) = analyze_spectrum(&model, &trajectory, spectrum_path) {
+            eprintln!("Error: the spectrum failed: {}", e);
         }
     }
+
+    //compute mean and std of arclength on last iteration, from a sample of the pairs for large N
+    let arclengths = model.sampled_pair_arclengths(&x_k, MAX_PAIRS, &mut StdRng::seed_from_u64(stream_seed(config.seed, 1)));
+    let pairs = config.particles * (config.particles - 1) / 2;
+    if pairs > MAX_PAIRS {
+        println!("Arclength statistics from {} random pairs of {}.", MAX_PAIRS, pairs);
+    }
     let mean_arclength = arclengths.iter().sum::<f64>() / arclengths.len() as f64;
     let std_arclength = (arclengths.iter().map(|x| (x - mean_arclength).powi(2)).sum::<f64>() / arclengths.len() as f64).sqrt();
     println!("Mean arclength: {}", mean_arclength);
     println!("Std arclength: {}", std_arclength);
+    if let Some(edge_arclengths) = model.edge_arclengths(&x_k) {
+        let mean_edge = edge_arclengths.iter().sum::<f64>() / edge_arclengths.len().max(1) as f64;
+        let std_edge = (edge_arclengths.iter().map(|x| (x - mean_edge).powi(2)).sum::<f64>() / edge_arclengths.len().max(1) as f64).sqrt();
+        println!("Mean edge arclength: {}", mean_edge);
+        println!("Std edge arclength: {}", std_edge);
+    }
+    if let Some(structure) = &config.structure {
+        analyze_structure(&model.unit_vectors(&x_k), config.radius, structure);
+    }
+    if config.pair_statistics.is_some() || config.pair_plot.is_some() {
+        let statistics = PairStatistics::from_trajectory(&model, &trajectory, config.analysis_window, config.bins);
+        analyze_pairs(&statistics.expect("pair statistics failed"), &config);
+    }
 
 
     // //make a 3d drawing
-    draw_3d(&timestamps, &positions, *R as f32);
+    if config.draw {
+        let positions = trajectory.map_states(|x| model.x_2_positions(x));
+        draw_3d(&trajectory.times_f32(), &positions, config.radius as f32);
+    }
     println!("Finished the program.");
 
 }
+
+fn analyze_spectrum(model : &SphereSprings, trajectory : &Trajectory, path : &str) -> Result<(), Box<dyn Error>> {
+    /*
+    oscillation modes of the particle coordinates
+    resampled at the recording step, a run that stopped early ends between recording points
+     */
+    let spectra = particle_spectra(model, trajectory, Some(trajectory.metadata.dt), Window::Hann)?;
+    let mut axis_spectra : Vec<_> = (0..3)
+        .map(|axis| sum_spectra(&spectra[axis..].iter().step_by(3).cloned().collect::<Vec<_>>()).ok_or("the coordinate spectra do not match"))
+        .collect::<Result<_, _>>()?;
+    let total = sum_spectra(&axis_spectra).ok_or("the axis spectra do not match")?;
+    let peaks = total.peaks(5);
+    for (frequency, power) in &peaks {
+        println!("Spectral peak: {:.4} Hz (power {:.3e})", frequency, power);
+    }
+    //show the band around the peaks rather than everything up to the nyquist frequency
+    let max_peak = peaks.iter().map(|p| p.0).fold(0.0, f64::max);
+    axis_spectra.push(total);
+    let options = PlotOptions {
+        title : "Particle Coordinate Spectrum".to_string(),
+        x_label : "Frequency (Hz)".to_string(),
+        y_label : "Power".to_string(),
+        x_range : if max_peak > 0.0 {Some((0.0, 2.0 * max_peak))} else {None},
+        ..PlotOptions::with_path(path)?
+    };
+    plot_spectrum(&axis_spectra, &["x", "y", "z", "total"], &[], &options)?;
+    println!("Saved the spectrum to {}.", path);
+    Ok(())
+}
+
+fn analyze_structure(unit_vectors : &[[f64;3]], r : f64, path : &str) {
+    //coordination numbers, defects and voronoi cell areas of a configuration
+    let tessellation = Tessellation::new(unit_vectors).expect("triangulating the configuration failed");
+    let counts : Vec<String> = tessellation.coordination_counts().iter().map(|(z, count)| format!("{}-fold: {}", z, count)).collect();
+    println!("Coordination: {}", counts.join(", "));
+    let defects = tessellation.defects();
+    let fives = defects.iter().filter(|&&i| tessellation.cells[i].coordination() == 5).count();
+    let sevens = defects.iter().filter(|&&i| tessellation.cells[i].coordination() == 7).count();
+    println!("Defects: {} ({} 5-fold, {} 7-fold), total charge {}", defects.len(), fives, sevens, tessellation.total_charge());
+    let areas : Vec<f64> = tessellation.cells.iter().map(|cell| r * r * cell.area).collect();
+    let mean_area = areas.iter().sum::<f64>() / areas.len() as f64;
+    let std_area = (areas.iter().map(|a| (a - mean_area).powi(2)).sum::<f64>() / areas.len() as f64).sqrt();
+    println!("Voronoi cell area: {:.6} +- {:.6} m^2", mean_area, std_area);
+    tessellation.write_csv(path, unit_vectors, r).expect("saving the structure failed");
+    println!("Saved the structure to {}.", path);
+}
+
+fn analyze_pairs(statistics : &PairStatistics, config : &Config) {
+    //tammes separation and nearest neighbor distance, then the histograms
+    println!("Minimal separation: {:.4} deg over {} state(s), packing density {:.4}",
+        statistics.min_separation.to_degrees(), statistics.frames, statistics.packing_density);
+    println!("Mean nearest neighbor arclength: {:.6} m", statistics.mean_nearest_neighbor);
+    if let Some(path) = &config.pair_statistics {
+        statistics.write_csv(path).expect("saving pair statistics failed");
+        println!("Saved the pair statistics to {}.", path);
+    }
+    if let Some(path) = &config.pair_plot {
+        let options = PlotOptions {
+            title : "Pair Statistics".to_string(),
+            size : (800, 800),
+            ..PlotOptions::with_path(path).expect("unsupported pair plot format")
+        };
+        plot_pair_statistics(statistics, &options).expect("plotting pair statistics failed");
+    }
+}
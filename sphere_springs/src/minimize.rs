@@ -0,0 +1,367 @@
+use crate::ensemble::run_ensemble;
+use crate::initial::uniform_unit_vectors;
+use crate::math::{dot, normalize};
+use crate::model::SphereSprings;
+use crate::potential::Interaction;
+use serde::Deserialize;
+use std::collections::VecDeque;
+use std::error::Error;
+use std::str::FromStr;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Method {
+    GradientDescent, //riemannian steepest descent with a backtracking line search
+    Lbfgs, //limited memory bfgs, the history is moved along by projecting onto the new tangent planes
+    Fire, //fast inertial relaxation engine, damped dynamics that only keeps velocity along the force
+}
+
+impl Method {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Method::GradientDescent => "gradient_descent",
+            Method::Lbfgs => "lbfgs",
+            Method::Fire => "fire",
+        }
+    }
+}
+
+impl FromStr for Method {
+    type Err = String;
+
+    fn from_str(s : &str) -> Result<Self, Self::Err> {
+        [Method::GradientDescent, Method::Lbfgs, Method::Fire].into_iter()
+            .find(|method| method.name() == s)
+            .ok_or(format!("expected gradient_descent, lbfgs or fire, got '{}'", s))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MinimizeOptions {
+    pub method : Method,
+    pub max_iterations : usize,
+    pub gradient_tolerance : f64, //converged once no particle feels a tangential force above this
+    pub energy_tolerance : f64, //converged once a line search step changes the energy by less than this fraction, 0 to only use the gradient
+    pub restarts : usize, //number of random starting configurations
+    pub seed : u64, //seed of the random starting configurations
+}
+
+impl Default for MinimizeOptions {
+    fn default() -> Self {
+        MinimizeOptions {method : Method::Lbfgs, max_iterations : 100000, gradient_tolerance : 1e-8, energy_tolerance : 0.0, restarts : 1, seed : 0}
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Minimum {
+    pub positions : Vec<[f64;3]>, //on the sphere of radius R
+    pub energy : f64,
+    pub max_force : f64,
+    pub iterations : usize,
+    pub converged : bool,
+}
+
+impl Minimum {
+    pub fn state(&self, model : &SphereSprings) -> Vec<f64> {
+        //the particles at rest at the minimum, in the formulation of the model
+        model.from_cartesian(&self.positions, &vec![[0.0; 3]; self.positions.len()])
+    }
+}
+
+pub fn minimize_with_restarts(model : &SphereSprings, options : &MinimizeOptions) -> Result<Vec<Minimum>, Box<dyn Error>> {
+    //minimizes from options.restarts random configurations in parallel, the minima are sorted from the lowest energy
+    if options.restarts == 0 {
+        return Err("minimization needs at least one restart".into());
+    }
+    let n = model.config.particles;
+    let mut minima = run_ensemble(options.restarts, options.seed, |_, rng| minimize(model, &uniform_unit_vectors(n, rng), options));
+    minima.sort_by(|a, b| a.energy.total_cmp(&b.energy));
+    Ok(minima)
+}
+
+pub fn minimize(model : &SphereSprings, initial : &[[f64;3]], options : &MinimizeOptions) -> Minimum {
+    /*
+    minimizes the potential energy of the model over the particle positions, starting from the directions in initial
+    every method moves along the tangent planes and puts the particles back on the sphere after each step
+     */
+    let r = model.config.radius;
+    let positions : Vec<[f64;3]> = initial.iter().map(|e| scale(&normalize(e), r)).collect();
+    match options.method {
+        Method::GradientDescent => descend(model, positions, options, 0),
+        Method::Lbfgs => descend(model, positions, options, 8),
+        Method::Fire => fire(model, positions, options),
+    }
+}
+
+//(s, y, 1 / s.y) of a past l-bfgs step
+type History = (Vec<[f64;3]>, Vec<[f64;3]>, f64);
+
+fn descend(model : &SphereSprings, mut positions : Vec<[f64;3]>, options : &MinimizeOptions, memory : usize) -> Minimum {
+    //line search methods, steepest descent without memory and l-bfgs with it
+    let r = model.config.radius;
+    let (mut energy, mut forces) = evaluate(model, &positions);
+    let mut history : VecDeque<History> = VecDeque::new();
+    let mut step = 1.0;
+    let mut converged = false;
+    let mut iterations = 0;
+    while iterations < options.max_iterations {
+        if max_norm(&forces) <= options.gradient_tolerance {
+            converged = true;
+            break;
+        }
+        iterations += 1;
+        let mut direction = if memory > 0 {
+            two_loop(&forces, &history, r)
+        } else {
+            forces.iter().map(|f| scale(f, step * 0.1 * r / max_norm(&forces))).collect()
+        };
+        if inner(&direction, &forces) <= 0.0 {
+            //lost the descent direction, start over from the gradient
+            history.clear();
+            direction = forces.iter().map(|f| scale(f, 0.1 * r / max_norm(&forces))).collect();
+        }
+        //never move a particle more than a fifth of the radius in one step
+        let largest = max_norm(&direction);
+        if largest > 0.2 * r {
+            direction = direction.iter().map(|d| scale(d, 0.2 * r / largest)).collect();
+        }
+        let Some((accepted, next, next_energy, next_forces)) = line_search(model, &positions, energy, &forces, &direction) else {
+            if history.is_empty() {
+                //even the gradient does not lower the energy within rounding
+                break;
+            }
+            history.clear();
+            continue;
+        };
+        //gradient descent grows its step again after every success
+        step = (2.0 * accepted * step).min(1.0);
+        let relative_change = (energy - next_energy).abs() / energy.abs().max(1.0);
+
+        if memory > 0 {
+            let s = tangent(&next, &subtract(&next, &positions));
+            let y = tangent(&next, &subtract(&forces, &next_forces));
+            //carry the history to the new tangent planes
+            history = history.into_iter()
+                .map(|(s, y, _)| (tangent(&next, &s), tangent(&next, &y)))
+                .filter_map(|(s, y)| {
+                    let sy = inner(&s, &y);
+                    (sy > 0.0).then(|| (s, y, 1.0 / sy))
+                })
+                .collect();
+            let sy = inner(&s, &y);
+            if sy > 1e-16 * inner(&s, &s).max(f64::MIN_POSITIVE) {
+                history.push_back((s, y, 1.0 / sy));
+                if history.len() > memory {
+                    history.pop_front();
+                }
+            }
+        }
+        positions = next;
+        energy = next_energy;
+        forces = next_forces;
+        if relative_change < options.energy_tolerance {
+            converged = true;
+            break;
+        }
+    }
+    let max_force = max_norm(&forces);
+    Minimum {positions, energy, max_force, iterations, converged : converged || max_force <= options.gradient_tolerance}
+}
+
+fn two_loop(forces : &[[f64;3]], history : &VecDeque<History>, r : f64) -> Vec<[f64;3]> {
+    //-H g with g = -forces, the initial inverse hessian is scaled by the newest pair or to a tenth of the radius
+    let mut q : Vec<[f64;3]> = forces.to_vec();
+    let mut alphas = Vec::with_capacity(history.len());
+    for (s, y, rho) in history.iter().rev() {
+        let alpha = rho * inner(s, &q);
+        q = add_scaled(&q, y, -alpha);
+        alphas.push(alpha);
+    }
+    let gamma = match history.back() {
+        Some((s, y, _)) => inner(s, y) / inner(y, y),
+        None => 0.1 * r / max_norm(forces),
+    };
+    let mut direction : Vec<[f64;3]> = q.iter().map(|v| scale(v, gamma)).collect();
+    for ((s, y, rho), alpha) in history.iter().zip(alphas.into_iter().rev()) {
+        let beta = rho * inner(y, &direction);
+        direction = add_scaled(&direction, s, alpha - beta);
+    }
+    direction
+}
+
+type Candidate = (f64, Vec<[f64;3]>, f64, Vec<[f64;3]>);
+
+fn line_search(model : &SphereSprings, positions : &[[f64;3]], energy : f64, forces : &[[f64;3]], direction : &[[f64;3]]) -> Option<Candidate> {
+    /*
+    backtracking from a full step until the armijo condition holds
+    close to a minimum the energy stops resolving the decrease, then a step that keeps the energy within rounding and lowers the forces is taken
+     */
+    let slope = -inner(forces, direction);
+    let rounding = 4.0 * f64::EPSILON * energy.abs().max(1.0);
+    let mut step = 1.0;
+    for _ in 0..60 {
+        let candidate = retract(positions, direction, step, model.config.radius);
+        let (candidate_energy, candidate_forces) = evaluate(model, &candidate);
+        let sufficient = candidate_energy <= energy + 1e-4 * step * slope;
+        let flat = candidate_energy <= energy + rounding && max_norm(&candidate_forces) < max_norm(forces);
+        if sufficient || flat {
+            return Some((step, candidate, candidate_energy, candidate_forces));
+        }
+        step *= 0.5;
+    }
+    None
+}
+
+fn fire(model : &SphereSprings, mut positions : Vec<[f64;3]>, options : &MinimizeOptions) -> Minimum {
+    //fire with unit masses, the velocities stay in the tangent planes
+    let r = model.config.radius;
+    let (mut energy, mut forces) = evaluate(model, &positions);
+    let mut velocities = vec![[0.0; 3]; positions.len()];
+    //a first step moves the particles about a hundredth of the radius
+    let dt_max = 10.0 * (0.01 * r / max_norm(&forces).max(f64::MIN_POSITIVE)).sqrt();
+    let mut dt = dt_max / 10.0;
+    let mut alpha = 0.1;
+    let mut downhill = 0;
+    let mut converged = false;
+    let mut iterations = 0;
+    while iterations < options.max_iterations {
+        if max_norm(&forces) <= options.gradient_tolerance {
+            converged = true;
+            break;
+        }
+        iterations += 1;
+        if inner(&forces, &velocities) > 0.0 {
+            //steer the velocity towards the force
+            let ratio = inner(&velocities, &velocities).sqrt() / inner(&forces, &forces).sqrt();
+            velocities = velocities.iter().zip(&forces)
+                .map(|(v, f)| [0, 1, 2].map(|axis| (1.0 - alpha) * v[axis] + alpha * ratio * f[axis]))
+                .collect();
+            downhill += 1;
+            if downhill > 5 {
+                dt = (1.1 * dt).min(dt_max);
+                alpha *= 0.99;
+            }
+        } else {
+            //went uphill, stop and take smaller steps
+            velocities = vec![[0.0; 3]; positions.len()];
+            dt *= 0.5;
+            alpha = 0.1;
+            downhill = 0;
+        }
+        velocities = add_scaled(&velocities, &forces, dt);
+        positions = retract(&positions, &velocities, dt, r);
+        velocities = tangent(&positions, &velocities);
+        (energy, forces) = evaluate(model, &positions);
+    }
+    let max_force = max_norm(&forces);
+    Minimum {positions, energy, max_force, iterations, converged}
+}
+
+pub fn known_thomson_energy(n : usize) -> Option<f64> {
+    //lowest known coulomb energy sum 1 / |x_i - x_j| of n unit charges on the unit sphere
+    const ENERGIES : [f64; 29] = [
+        0.500000000, 1.732050808, 3.674234614, 6.474691495, 9.985281374, 14.452977414, 19.675287861,
+        25.759986531, 32.716949460, 40.596450510, 49.165253058, 58.853230612, 69.306363297, 80.670244114,
+        92.911655302, 106.050404829, 120.084467447, 135.089467557, 150.881568334, 167.641622399, 185.287536149,
+        203.930190663, 223.347074052, 243.812760299, 265.133326317, 287.302615033, 310.491542358, 334.634439920,
+        359.603945904,
+    ];
+    n.checked_sub(2).and_then(|i| ENERGIES.get(i)).copied()
+}
+
+pub fn thomson_reference(model : &SphereSprings) -> Option<f64> {
+    //the known minimum for the model, when it is a thomson problem: coulomb between all pairs, k / (R d) on the unit sphere
+    let coulomb = matches!(model.config.potential, Interaction::Coulomb) || model.config.potential == Interaction::Riesz(1.0);
+    if !coulomb || model.network.is_some() {
+        return None;
+    }
+    known_thomson_energy(model.config.particles).map(|e| e * model.config.stiffness / model.config.radius)
+}
+
+fn evaluate(model : &SphereSprings, positions : &[[f64;3]]) -> (f64, Vec<[f64;3]>) {
+    let unit_vectors : Vec<[f64;3]> = positions.iter().map(normalize).collect();
+    (model.configuration_energy(&unit_vectors), model.pair_forces(&unit_vectors))
+}
+
+fn retract(positions : &[[f64;3]], direction : &[[f64;3]], step : f64, r : f64) -> Vec<[f64;3]> {
+    positions.iter().zip(direction)
+        .map(|(p, d)| scale(&normalize(&[p[0] + step * d[0], p[1] + step * d[1], p[2] + step * d[2]]), r))
+        .collect()
+}
+
+fn tangent(positions : &[[f64;3]], vectors : &[[f64;3]]) -> Vec<[f64;3]> {
+    //removes the radial part of every vector
+    positions.iter().zip(vectors).map(|(p, v)| {
+        let e = normalize(p);
+        let radial = dot(v, &e);
+        [v[0] - radial * e[0], v[1] - radial * e[1], v[2] - radial * e[2]]
+    }).collect()
+}
+
+fn inner(a : &[[f64;3]], b : &[[f64;3]]) -> f64 {
+    a.iter().zip(b).map(|(u, v)| dot(u, v)).sum()
+}
+
+fn max_norm(vectors : &[[f64;3]]) -> f64 {
+    vectors.iter().map(|v| dot(v, v).sqrt()).fold(0.0, f64::max)
+}
+
+fn scale(v : &[f64;3], factor : f64) -> [f64;3] {
+    [factor * v[0], factor * v[1], factor * v[2]]
+}
+
+fn add_scaled(a : &[[f64;3]], b : &[[f64;3]], factor : f64) -> Vec<[f64;3]> {
+    a.iter().zip(b).map(|(u, v)| [u[0] + factor * v[0], u[1] + factor * v[1], u[2] + factor * v[2]]).collect()
+}
+
+fn subtract(a : &[[f64;3]], b : &[[f64;3]]) -> Vec<[f64;3]> {
+    add_scaled(a, b, -1.0)
+}
+
+#[cfg(test)]
+fn thomson_model(particles : usize) -> SphereSprings {
+    use crate::config::Config;
+    SphereSprings::new(Config {particles, radius : 1.0, stiffness : 1.0, potential : Interaction::Coulomb, ..Config::default()}).unwrap()
+}
+
+#[test]
+fn test_methods_find_the_octahedron() {
+    let model = thomson_model(6);
+    let reference = thomson_reference(&model).unwrap();
+    let initial = uniform_unit_vectors(6, &mut <rand::rngs::StdRng as rand::SeedableRng>::seed_from_u64(3));
+    for method in [Method::GradientDescent, Method::Lbfgs, Method::Fire] {
+        let minimum = minimize(&model, &initial, &MinimizeOptions {method, ..MinimizeOptions::default()});
+        assert!(minimum.converged, "{:?} did not converge", method);
+        assert!((minimum.energy - reference).abs() < 1e-8, "{:?} reached {}", method, minimum.energy);
+        assert!(minimum.positions.iter().all(|p| (dot(p, p).sqrt() - 1.0).abs() < 1e-12));
+    }
+    assert!(minimize(&model, &initial, &MinimizeOptions {max_iterations : 3, ..MinimizeOptions::default()}).iterations <= 3);
+}
+
+#[test]
+fn test_restarts_reach_known_minima() {
+    for n in [2, 5, 12, 20] {
+        let model = thomson_model(n);
+        let options = MinimizeOptions {restarts : 4, ..MinimizeOptions::default()};
+        let minima = minimize_with_restarts(&model, &options).unwrap();
+        assert_eq!(minima.len(), 4);
+        assert!(minima.windows(2).all(|pair| pair[0].energy <= pair[1].energy));
+        assert!((minima[0].energy - known_thomson_energy(n).unwrap()).abs() < 1e-8, "N={} reached {}", n, minima[0].energy);
+    }
+    //the reference scales as K / R and only exists for coulomb without a network
+    use crate::config::Config;
+    let scaled = SphereSprings::new(Config {particles : 4, radius : 2.0, stiffness : 3.0, potential : Interaction::Coulomb, ..Config::default()}).unwrap();
+    assert!((thomson_reference(&scaled).unwrap() - 1.5 * 3.674234614).abs() < 1e-12);
+    assert_eq!(thomson_reference(&SphereSprings::new(Config::default()).unwrap()), None);
+    assert_eq!(known_thomson_energy(1), None);
+    assert!(minimize_with_restarts(&scaled, &MinimizeOptions {restarts : 0, ..MinimizeOptions::default()}).is_err());
+}
+
+#[test]
+fn test_minimum_is_an_equilibrium_of_the_dynamics() {
+    let model = thomson_model(8);
+    let minimum = minimize_with_restarts(&model, &MinimizeOptions::default()).unwrap().remove(0);
+    let x = minimum.state(&model);
+    assert!((model.potential_energy(&x) - minimum.energy).abs() < 1e-9);
+    assert!(model.f(0.0, &x).iter().all(|v| v.abs() < 1e-6));
+}
@@ -0,0 +1,564 @@
+use crate::math::{SphericalPoint, RK4, cross, dot, normalize};
+use crate::config::{Config, Formulation};
+use crate::trajectory::{Metadata, Trajectory};
+use crate::stochastic::{stream_seed, Noise, NoiseType, StochasticIntegrator};
+use crate::potential::PairPotential;
+use crate::topology::{angle_between, Network};
+use crate::forces::{self, tangential_force};
+use crate::initial::thermal_velocities;
+use crate::stopping::{Stop, StopMonitor, StopReason, StoppingCriteria};
+use rand::Rng;
+use rayon::prelude::*;
+use std::error::Error;
+
+pub struct SphereSprings {
+    pub config : Config,
+    pub potential : Box<dyn PairPotential>,
+    pub network : Option<Network>, //springs between chosen pairs only, instead of the potential between all pairs
+}
+
+impl SphereSprings {
+    pub fn new(config : Config) -> Result<Self, Box<dyn Error>> {
+        //a configured network is built from the particle positions, see connected and with_state
+        if config.network.is_some() {
+            return Err("a spring network needs the particle positions, build the model with connected or with_state".into());
+        }
+        Ok(SphereSprings::unconnected(config))
+    }
+
+    pub fn connected(config : Config, x : &[f64]) -> Result<Self, Box<dyn Error>> {
+        //builds the configured network from the particle positions in x, the default stays fully connected
+        let mut model = SphereSprings::unconnected(config);
+        if x.len() != model.state_size() {
+            return Err(format!("state has {} values, expected {} for N = {}", x.len(), model.state_size(), model.config.particles).into());
+        }
+        model.network = match &model.config.network {
+            Some(spec) => Some(spec.build(&model.unit_vectors(x), model.config.radius, model.config.stiffness, model.config.rest_length)?),
+            None => None,
+        };
+        Ok(model)
+    }
+
+    pub fn with_state<R : Rng>(config : Config, rng : &mut R) -> Result<(Self, Vec<f64>), Box<dyn Error>> {
+        //the configured initial state drawn from rng, and the model connected at it
+        let x0 = SphereSprings::unconnected(config.clone()).initial_state(rng)?;
+        Ok((SphereSprings::connected(config, &x0)?, x0))
+    }
+
+    fn unconnected(config : Config) -> Self {
+        let potential = config.potential.build(config.stiffness);
+        SphereSprings {config, potential, network : None}
+    }
+
+    pub fn f(&self, _t : f64, x : &[f64]) -> Vec<f64> {
+        //x - one block of state_names() per particle
+        match self.config.formulation {
+            Formulation::Cartesian => self.f_cartesian(x),
+            Formulation::Spherical => self.f_spherical(x),
+        }
+    }
+
+    pub fn pair_forces(&self, unit_vectors : &[[f64;3]]) -> Vec<[f64;3]> {
+        //tangential force on every particle from the ones it interacts with, along the great circle towards the other particle
+        let r = self.config.radius;
+        let n = unit_vectors.len();
+        match &self.network {
+            None => forces::pair_forces(self.config.forces, self.potential.as_ref(), unit_vectors, r),
+            Some(network) => (0..n).into_par_iter().map(|i| {
+                network.neighbors(i).iter()
+                    .map(|(j, spring)| tangential_force(&unit_vectors[i], &unit_vectors[*j], r, spring))
+                    .fold([0.0; 3], |acc, f| [acc[0] + f[0], acc[1] + f[1], acc[2] + f[2]])
+            }).collect(),
+        }
+    }
+
+    pub fn potential_energy(&self, x : &[f64]) -> f64 {
+        self.configuration_energy(&self.unit_vectors(x))
+    }
+
+    pub fn configuration_energy(&self, unit_vectors : &[[f64;3]]) -> f64 {
+        //sum of the pair potential over every pair of particles, or of the springs over the network edges
+        let r = self.config.radius;
+        if let Some(network) = &self.network {
+            return network.energy(unit_vectors, r);
+        }
+        forces::pair_energy(self.config.forces, self.potential.as_ref(), unit_vectors, r)
+    }
+
+    fn f_spherical(&self, x : &[f64]) -> Vec<f64> {
+        //x - [theta, phi, theta_dot, phi_dot]_1, [theta, phi, theta_dot, phi_dot]_2, ...
+        let Config {radius : r, mass : m, damping : c, ..} = self.config;
+        let points = self.spherical_points(x);
+        let forces = self.pair_forces(&points.iter().map(|point| normalize(&point.e_r())).collect::<Vec<_>>());
+
+        points.par_iter().zip(forces).enumerate().flat_map(|(i, (point, force))| {
+            let theta = x[4*i];
+            let theta_dot = x[4*i+2];
+            let phi_dot = x[4*i+3];
+
+            // compute f_d
+            let v_theta = r * theta_dot;
+            let v_phi = r * theta.sin() * phi_dot;
+            let f_theta = dot(&force, &point.e_theta()) - c * v_theta;
+            let f_phi = dot(&force, &point.e_phi()) - c * v_phi;
+
+            //equations of motions with constant R
+            // https://en.wikipedia.org/wiki/Equations_of_motion
+            let theta_ddot = (f_theta/m + r*phi_dot.powi(2)*theta.sin()*theta.cos())/r;
+            let phi_ddot = (f_phi/m - 2.0*r*theta_dot*phi_dot*theta.cos())/(r*theta.sin());
+
+            [theta_dot, phi_dot, theta_ddot, phi_ddot]
+        }).collect()
+    }
+
+    fn f_cartesian(&self, x : &[f64]) -> Vec<f64> {
+        /*
+        x - [x, y, z, vx, vy, vz]_1, [x, y, z, vx, vy, vz]_2, ...
+        the tangential force moves the particle along the sphere and the centripetal term -|v|^2/R keeps it there,
+        nothing divides by sin(theta) so the poles are ordinary points
+         */
+        let Config {radius : r, mass : m, damping : c, particles : n, ..} = self.config;
+        let unit_vectors : Vec<[f64;3]> = (0..n).map(|i| normalize(&[x[6*i], x[6*i+1], x[6*i+2]])).collect();
+        let forces = self.pair_forces(&unit_vectors);
+
+        unit_vectors.par_iter().zip(forces).enumerate().flat_map(|(i, (e, force))| {
+            let v = [x[6*i+3], x[6*i+4], x[6*i+5]];
+            //friction with the big sphere
+            let f = [force[0] - c * v[0], force[1] - c * v[1], force[2] - c * v[2]];
+            let f_radial = dot(&f, e);
+            let centripetal = dot(&v, &v) / r;
+            let a = [0, 1, 2].map(|axis| (f[axis] - f_radial * e[axis]) / m - centripetal * e[axis]);
+            [v[0], v[1], v[2], a[0], a[1], a[2]]
+        }).collect()
+    }
+
+    pub fn project(&self, x : &[f64]) -> Vec<f64> {
+        //puts cartesian particles back on the sphere with tangential velocities, integration drifts off both
+        match self.config.formulation {
+            Formulation::Spherical => x.to_vec(),
+            Formulation::Cartesian => x.chunks(6).flat_map(|particle| {
+                let e = normalize(&[particle[0], particle[1], particle[2]]);
+                let v = [particle[3], particle[4], particle[5]];
+                let v_radial = dot(&v, &e);
+                let r = self.config.radius;
+                [r * e[0], r * e[1], r * e[2], v[0] - v_radial * e[0], v[1] - v_radial * e[1], v[2] - v_radial * e[2]]
+            }).collect(),
+        }
+    }
+
+    pub fn state_size(&self) -> usize {
+        match self.config.formulation {
+            Formulation::Cartesian => 6 * self.config.particles,
+            Formulation::Spherical => 4 * self.config.particles,
+        }
+    }
+
+    pub fn from_spherical(&self, x : &[f64]) -> Vec<f64> {
+        //converts a [theta, phi, theta_dot, phi_dot] per particle state into this model's formulation
+        match self.config.formulation {
+            Formulation::Spherical => x.to_vec(),
+            Formulation::Cartesian => x.chunks(4).flat_map(|particle| {
+                let point = SphericalPoint::new(self.config.radius, particle[0], particle[1]);
+                let (e_theta, e_phi) = (point.e_theta(), point.e_phi());
+                let v_theta = self.config.radius * particle[2];
+                let v_phi = self.config.radius * particle[0].sin() * particle[3];
+                let p = point.xyz();
+                let v = [0, 1, 2].map(|axis| v_theta * e_theta[axis] + v_phi * e_phi[axis]);
+                [p[0], p[1], p[2], v[0], v[1], v[2]]
+            }).collect(),
+        }
+    }
+
+    pub fn state_names(&self) -> Vec<String> {
+        let names : &[&str] = match self.config.formulation {
+            Formulation::Cartesian => &["x", "y", "z", "vx", "vy", "vz"],
+            Formulation::Spherical => &["theta", "phi", "theta_dot", "phi_dot"],
+        };
+        (0..self.config.particles)
+            .flat_map(|i| names.iter().map(move |name| format!("{}_{}", name, i)))
+            .collect()
+    }
+
+    pub fn metadata(&self, dt : f64) -> Metadata {
+        let mut metadata = Metadata::new("sphere_springs", self.config.integrator.name(), dt)
+            .with_parameter("R", self.config.radius)
+            .with_parameter("M", self.config.mass)
+            .with_parameter("K", self.config.stiffness)
+            .with_parameter("C", self.config.damping)
+            .with_parameter("N", self.config.particles as f64)
+            .with_parameter("noise", self.config.noise)
+            .with_parameter("cartesian", if self.config.formulation == Formulation::Cartesian {1.0} else {0.0})
+            .with_label("potential", &self.config.potential.to_string());
+        //the network is rebuilt from the first recorded state, which is where it was built from
+        if let Some(network) = &self.config.network {
+            metadata = metadata.with_label("network", &network.to_string());
+        }
+        if let Some(rest_length) = self.config.rest_length {
+            metadata = metadata.with_parameter("rest_length", rest_length);
+        }
+        metadata
+    }
+
+    pub fn noise(&self) -> Noise {
+        /*
+        the noise acts on the velocities only, the positions follow by integration
+        those are the angular velocities in the spherical formulation and the cartesian velocities (m/s) otherwise,
+        so additive cartesian noise is scaled by R to keep sigma an angular intensity in both formulations
+         */
+        let sigma = self.config.noise;
+        let sigma : Vec<f64> = match self.config.formulation {
+            Formulation::Cartesian => {
+                let sigma = match self.config.noise_type {
+                    NoiseType::Additive => self.config.radius * sigma,
+                    NoiseType::Multiplicative => sigma, //relative to the velocity, no units to convert
+                };
+                (0..self.config.particles).flat_map(|_| [0.0, 0.0, 0.0, sigma, sigma, sigma]).collect()
+            },
+            Formulation::Spherical => (0..self.config.particles).flat_map(|_| [0.0, 0.0, sigma, sigma]).collect(),
+        };
+        Noise {kind : self.config.noise_type, sigma}
+    }
+
+    pub fn velocities(&self, x : &[f64]) -> Vec<[f64;3]> {
+        //velocity of every particle in cartesian coordinates
+        let r = self.config.radius;
+        (0..self.config.particles).map(|i| match self.config.formulation {
+            Formulation::Cartesian => [x[6*i+3], x[6*i+4], x[6*i+5]],
+            Formulation::Spherical => {
+                let point = SphericalPoint::new(r, x[4*i], x[4*i+1]);
+                let (e_theta, e_phi) = (point.e_theta(), point.e_phi());
+                let v_theta = r * x[4*i+2];
+                let v_phi = r * x[4*i].sin() * x[4*i+3];
+                [0, 1, 2].map(|axis| v_theta * e_theta[axis] + v_phi * e_phi[axis])
+            },
+        }).collect()
+    }
+
+    pub fn kinetic_energy(&self, x : &[f64]) -> f64 {
+        0.5 * self.config.mass * self.velocities(x).iter().map(|v| dot(v, v)).sum::<f64>()
+    }
+
+    pub fn dissipation_rate(&self, x : &[f64]) -> f64 {
+        //power lost to friction with the big sphere, C |v|^2 summed over the particles
+        self.config.damping * self.velocities(x).iter().map(|v| dot(v, v)).sum::<f64>()
+    }
+
+    pub fn angular_momentum(&self, x : &[f64]) -> [f64;3] {
+        //total m p x v about the center of the big sphere
+        let r = self.config.radius;
+        self.unit_vectors(x).iter().zip(self.velocities(x)).fold([0.0; 3], |acc, (e, v)| {
+            let l = cross(e, &v);
+            [0, 1, 2].map(|axis| acc[axis] + self.config.mass * r * l[axis])
+        })
+    }
+
+    pub fn x_2_positions(&self, x : &[f64]) -> Vec<[f32;3]> {
+        //positions - [x,y,z]_1, [x,y,z]_2, ...
+        (0..self.config.particles).map(|i| {
+            let tmp = match self.config.formulation {
+                Formulation::Cartesian => [x[6*i], x[6*i+1], x[6*i+2]],
+                Formulation::Spherical => SphericalPoint::new(self.config.radius, x[4*i], x[4*i+1]).xyz(),
+            };
+            [tmp[0] as f32, tmp[1] as f32, tmp[2] as f32]
+        }).collect()
+    }
+
+    pub fn spherical_points(&self, x : &[f64]) -> Vec<SphericalPoint> {
+        (0..self.config.particles).map(|i| match self.config.formulation {
+            Formulation::Cartesian => {
+                let e = normalize(&[x[6*i], x[6*i+1], x[6*i+2]]);
+                SphericalPoint::new(self.config.radius, e[2].clamp(-1.0, 1.0).acos(), e[1].atan2(e[0]))
+            },
+            Formulation::Spherical => SphericalPoint::new(self.config.radius, x[4*i], x[4*i+1]),
+        }).collect()
+    }
+
+    pub fn unit_vectors(&self, x : &[f64]) -> Vec<[f64;3]> {
+        self.spherical_points(x).iter().map(|point| normalize(&point.e_r())).collect()
+    }
+
+    pub fn edge_arclengths(&self, x : &[f64]) -> Option<Vec<f64>> {
+        //great circle length of every network edge
+        let network = self.network.as_ref()?;
+        let unit_vectors = self.unit_vectors(x);
+        Some(network.edges.iter().map(|edge| self.config.radius * angle_between(&unit_vectors[edge.i], &unit_vectors[edge.j])).collect())
+    }
+
+    pub fn pair_arclengths(&self, x : &[f64]) -> Vec<f64> {
+        //great circle distance of every pair of particles, pairs sitting on the same point are skipped
+        let points = self.spherical_points(x);
+        let mut arclengths = Vec::with_capacity(points.len() * (points.len() - 1) / 2);
+        for i in 0..points.len() {
+            for j in i + 1..points.len() {
+                if let Some((_, _, arc)) = points[i].axis_angle_arc(&points[j]) {
+                    arclengths.push(arc);
+                }
+            }
+        }
+        arclengths
+    }
+
+    pub fn sampled_pair_arclengths<R : Rng>(&self, x : &[f64], max_pairs : usize, rng : &mut R) -> Vec<f64> {
+        //every pair when there are at most max_pairs, otherwise max_pairs random pairs so large N stays linear
+        let n = self.config.particles;
+        if n * (n - 1) / 2 <= max_pairs {
+            return self.pair_arclengths(x);
+        }
+        let points = self.spherical_points(x);
+        (0..max_pairs).filter_map(|_| {
+            let i = rng.gen_range(0..n);
+            let j = (i + rng.gen_range(1..n)) % n;
+            points[i].axis_angle_arc(&points[j]).map(|(_, _, arc)| arc)
+        }).collect()
+    }
+
+    pub fn from_cartesian(&self, positions : &[[f64;3]], velocities : &[[f64;3]]) -> Vec<f64> {
+        //converts positions on the sphere and tangential velocities into this model's formulation
+        let r = self.config.radius;
+        positions.iter().zip(velocities).flat_map(|(p, v)| {
+            let e = normalize(p);
+            match self.config.formulation {
+                Formulation::Cartesian => vec![r * e[0], r * e[1], r * e[2], v[0], v[1], v[2]],
+                Formulation::Spherical => {
+                    let (theta, phi) = (e[2].clamp(-1.0, 1.0).acos(), e[1].atan2(e[0]));
+                    let point = SphericalPoint::new(r, theta, phi);
+                    let theta_dot = dot(v, &point.e_theta()) / r;
+                    //phi is undefined on the poles, a particle there has no azimuthal velocity
+                    let phi_dot = if theta.sin() > 1e-12 {dot(v, &point.e_phi()) / (r * theta.sin())} else {0.0};
+                    vec![theta, phi, theta_dot, phi_dot]
+                },
+            }
+        }).collect()
+    }
+
+    pub fn initial_state<R : Rng>(&self, rng : &mut R) -> Result<Vec<f64>, Box<dyn Error>> {
+        //positions from the configured initializer, velocities at the configured temperature
+        let unit_vectors = self.config.initializer.unit_vectors(self.config.particles, rng)?;
+        //the spherical equations divide by sin(theta), so they cannot start a particle on or right next to a pole
+        if self.config.formulation == Formulation::Spherical {
+            if let Some(i) = unit_vectors.iter().position(|e| (1.0 - e[2].powi(2)).max(0.0).sqrt() < 1e-6) {
+                return Err(format!("particle {} starts on a pole, which the spherical formulation cannot integrate, use the cartesian one", i).into());
+            }
+        }
+        let velocities = if self.config.temperature > 0.0 {
+            thermal_velocities(&unit_vectors, self.config.temperature, self.config.mass, rng)
+        } else {
+            vec![[0.0; 3]; unit_vectors.len()]
+        };
+        Ok(self.from_cartesian(&unit_vectors, &velocities))
+    }
+
+    pub fn simulate(&self, x0 : &[f64], dt : f64, steps : usize, record_every : usize) -> Trajectory {
+        self.simulate_until(x0, dt, steps, record_every, &StoppingCriteria::default()).0
+    }
+
+    pub fn simulate_until(&self, x0 : &[f64], dt : f64, steps : usize, record_every : usize, criteria : &StoppingCriteria) -> (Trajectory, Stop) {
+        /*
+        integrates up to steps steps of dt from x0 with the configured integrator, or until a stopping criterion fires
+        every record_every-th state is kept, the initial and the last state always are
+        the energy lost to friction is integrated every step, with the trapezoidal rule, into the derived "dissipated_energy"
+         */
+        let record_every = record_every.max(1);
+        let rk4 = RK4::new(dt, |t, x| self.f(t, x));
+        //stochastic integrators only when an sde scheme is configured, on a stream of the seed the initial state does not draw from
+        let mut sde = self.config.integrator.scheme()
+            .map(|scheme| StochasticIntegrator::new(dt, |t, x| self.f(t, x), self.noise(), scheme, stream_seed(self.config.seed, 0)));
+        let mut monitor = StopMonitor::new(*criteria, self, x0);
+        let mut trajectory = Trajectory::new(self.metadata(dt * record_every as f64), &self.state_names());
+        let mut x = x0.to_vec();
+        let mut power = self.dissipation_rate(&x);
+        let mut dissipated = 0.0;
+        let mut dissipated_series = vec![dissipated];
+        let mut reason = StopReason::MaxTime;
+        trajectory.push(0.0, &x);
+        for k in 1..=steps {
+            let t = (k - 1) as f64 * dt;
+            x = match &mut sde {
+                Some(sde) => sde.propogate(t, &x),
+                None => rk4.propogate(t, &x),
+            };
+            x = self.project(&x);
+            let next_power = self.dissipation_rate(&x);
+            dissipated += 0.5 * dt * (power + next_power);
+            power = next_power;
+            let stop = monitor.check(self, k as f64 * dt, &x);
+            if k % record_every == 0 || k == steps || stop.is_some() {
+                trajectory.push(k as f64 * dt, &x);
+                dissipated_series.push(dissipated);
+            }
+            if let Some(stop) = stop {
+                reason = stop;
+                break;
+            }
+        }
+        trajectory.derived.insert("dissipated_energy".to_string(), dissipated_series);
+        let stop = Stop {reason, time : trajectory.times[trajectory.len() - 1], wall_time : monitor.elapsed()};
+        (trajectory, stop)
+    }
+}
+
+#[test]
+fn test_models_with_different_configs() {
+    //two differently parameterized models can live side by side
+    let stiff = SphereSprings::new(Config {stiffness : 4.0, formulation : Formulation::Spherical, ..Config::default()}).unwrap();
+    let soft = SphereSprings::new(Config {stiffness : 1.0, particles : 3, formulation : Formulation::Spherical, ..Config::default()}).unwrap();
+
+    let x_stiff = vec![1.0, 0.0, 0.0, 0.0, 2.0, 0.5, 0.0, 0.0,
+                       1.5, 1.0, 0.0, 0.0, 0.5, 2.0, 0.0, 0.0];
+    let x_soft = x_stiff[..12].to_vec();
+    assert_eq!(stiff.f(0.0, &x_stiff).len(), 16);
+    assert_eq!(soft.f(0.0, &x_soft).len(), 12);
+    assert_eq!(soft.x_2_positions(&x_soft).len(), 3);
+    assert_eq!(soft.pair_arclengths(&x_soft).len(), 3);
+}
+
+#[test]
+fn test_simulate_records_every_nth_state() {
+    use rand::{rngs::StdRng, SeedableRng};
+    let model = SphereSprings::new(Config::default()).unwrap();
+    let x0 = model.initial_state(&mut StdRng::seed_from_u64(0)).unwrap();
+    let trajectory = model.simulate(&x0, 0.001, 100, 10);
+    assert_eq!(trajectory.len(), 11);
+    assert!((trajectory.times[10] - 0.1).abs() < 1e-12);
+    assert!((trajectory.metadata.dt - 0.01).abs() < 1e-12);
+    assert_eq!(trajectory.states[0], x0);
+}
+
+#[test]
+fn test_simulate_records_the_last_step() {
+    //the last step is kept even between recording points, so the stop reports the end of the run
+    use rand::{rngs::StdRng, SeedableRng};
+    let model = SphereSprings::new(Config::default()).unwrap();
+    let x0 = model.initial_state(&mut StdRng::seed_from_u64(0)).unwrap();
+    let (trajectory, stop) = model.simulate_until(&x0, 0.001, 105, 10, &StoppingCriteria::default());
+    assert_eq!(trajectory.len(), 12);
+    assert!((trajectory.times[11] - 0.105).abs() < 1e-12);
+    assert_eq!((stop.reason, stop.time), (StopReason::MaxTime, trajectory.times[11]));
+    assert_eq!(trajectory.states[11], model.simulate(&x0, 0.001, 105, 105).states[1]);
+}
+
+#[test]
+fn test_sampled_pair_arclengths() {
+    //small systems use every pair, large ones a sample with about the same mean
+    use crate::initial::Initializer;
+    use rand::{rngs::StdRng, SeedableRng};
+    let model = SphereSprings::new(Config {particles : 400, initializer : Initializer::Fibonacci, ..Config::default()}).unwrap();
+    let x = model.initial_state(&mut StdRng::seed_from_u64(0)).unwrap();
+    let all = model.pair_arclengths(&x);
+    let mut rng = StdRng::seed_from_u64(1);
+    assert_eq!(model.sampled_pair_arclengths(&x, all.len(), &mut rng), all);
+    let sampled = model.sampled_pair_arclengths(&x, 20000, &mut rng);
+    assert_eq!(sampled.len(), 20000);
+    let mean = |values : &[f64]| values.iter().sum::<f64>() / values.len() as f64;
+    assert!((mean(&sampled) - mean(&all)).abs() < 0.02 * mean(&all));
+}
+
+#[test]
+fn test_cartesian_matches_spherical_away_from_poles() {
+    let spherical = SphereSprings::new(Config {particles : 3, stiffness : 5.0, formulation : Formulation::Spherical, ..Config::default()}).unwrap();
+    let cartesian = SphereSprings::new(Config {formulation : Formulation::Cartesian, ..spherical.config.clone()}).unwrap();
+    let x0 = vec![1.0, 0.0, 0.3, -0.2,
+                  1.6, 2.0, -0.1, 0.4,
+                  2.1, -2.0, 0.2, 0.1];
+    let a = spherical.simulate(&x0, 0.001, 1000, 1000);
+    let b = cartesian.simulate(&cartesian.from_spherical(&x0), 0.001, 1000, 1000);
+    for (p, q) in spherical.x_2_positions(&a.states[1]).iter().zip(cartesian.x_2_positions(&b.states[1])) {
+        for axis in 0..3 {
+            assert!((p[axis] - q[axis]).abs() < 1e-4);
+        }
+    }
+}
+
+#[test]
+fn test_noise_is_angular_in_both_formulations() {
+    //additive sigma is an angular intensity, the cartesian velocities get R sigma
+    let spherical = SphereSprings::new(Config {particles : 1, radius : 2.0, noise : 0.3, formulation : Formulation::Spherical, ..Config::default()}).unwrap();
+    let cartesian = SphereSprings::new(Config {formulation : Formulation::Cartesian, ..spherical.config.clone()}).unwrap();
+    assert_eq!(spherical.noise().sigma, vec![0.0, 0.0, 0.3, 0.3]);
+    assert_eq!(cartesian.noise().sigma, vec![0.0, 0.0, 0.0, 0.6, 0.6, 0.6]);
+    let multiplicative = SphereSprings::new(Config {noise_type : NoiseType::Multiplicative, ..cartesian.config.clone()}).unwrap();
+    assert_eq!(multiplicative.noise().sigma, vec![0.0, 0.0, 0.0, 0.3, 0.3, 0.3]);
+}
+
+#[test]
+fn test_spherical_formulation_rejects_poles() {
+    //a particle on the pole has no azimuth, the conversion gives it no phi velocity and the spherical run refuses it
+    use crate::initial::Initializer;
+    use rand::{rngs::StdRng, SeedableRng};
+    let config = Config {particles : 6, formulation : Formulation::Spherical, initializer : Initializer::Polyhedron(0.0), ..Config::default()};
+    let model = SphereSprings::new(config.clone()).unwrap();
+    let x = model.from_cartesian(&[[0.0, 0.0, 1.0], [1.0, 0.0, 0.0]], &[[0.5, 0.0, 0.0], [0.0, 0.5, 0.0]]);
+    assert!(x.iter().all(|v| v.is_finite()));
+    assert_eq!((x[0], x[3]), (0.0, 0.0));
+    assert!(model.initial_state(&mut StdRng::seed_from_u64(0)).is_err());
+    let cartesian = SphereSprings::new(Config {formulation : Formulation::Cartesian, ..config}).unwrap();
+    assert!(cartesian.initial_state(&mut StdRng::seed_from_u64(0)).is_ok());
+}
+
+#[test]
+fn test_cartesian_passes_through_the_pole() {
+    //one particle is launched straight over the north pole, the spherical equations divide by zero there
+    let model = SphereSprings::new(Config {particles : 2, damping : 0.0, ..Config::default()}).unwrap();
+    let x0 = model.from_spherical(&[0.05, 0.0, -2.0, 0.0, 2.5, 1.0, 0.0, 0.0]);
+    let trajectory = model.simulate(&x0, 0.001, 2000, 10);
+    let r = model.config.radius;
+    for x in &trajectory.states {
+        assert!(x.iter().all(|v| v.is_finite()));
+        for particle in x.chunks(6) {
+            let radius = dot(&[particle[0], particle[1], particle[2]], &[particle[0], particle[1], particle[2]]).sqrt();
+            assert!((radius - r).abs() < 1e-9);
+            assert!(dot(&[particle[0], particle[1], particle[2]], &[particle[3], particle[4], particle[5]]).abs() < 1e-9);
+        }
+    }
+    //the particle really crossed, it ends up on the other side of the z axis
+    let start = trajectory.states[0][0];
+    let end = trajectory.states[trajectory.len() - 1][0];
+    assert!(start > 0.0 && end < 0.0);
+}
+
+#[test]
+fn test_coulomb_pair_relaxes_to_antipodes() {
+    use crate::potential::Interaction;
+    use std::f64::consts::PI;
+    let model = SphereSprings::new(Config {particles : 2, potential : Interaction::Coulomb, stiffness : 50.0, damping : 2.0, ..Config::default()}).unwrap();
+    let x0 = model.from_spherical(&[1.0, 0.0, 0.0, 0.0, 1.5, 0.5, 0.0, 0.0]);
+    let trajectory = model.simulate(&x0, 0.001, 20000, 20000);
+    let x = &trajectory.states[1];
+    let arclengths = model.pair_arclengths(x);
+    assert!((arclengths[0] - PI * model.config.radius).abs() < 1e-3);
+    //the coulomb energy of an antipodal pair is k / (2 R)
+    assert!((model.potential_energy(x) - model.config.stiffness / (2.0 * model.config.radius)).abs() < 1e-6);
+}
+
+
+#[test]
+fn test_ring_network_relaxes_to_rest_length() {
+    //two particles joined by one spring settle a quarter circle apart, with no spring left stretched
+    use crate::topology::NetworkSpec;
+    use std::f64::consts::PI;
+    let r = 2.0;
+    let config = Config {particles : 2, damping : 2.0, network : Some(NetworkSpec::Ring), rest_length : Some(PI / 2.0 * r), ..Config::default()};
+    assert!(SphereSprings::new(config.clone()).is_err());
+    let x0 = SphereSprings::new(Config {network : None, ..config.clone()}).unwrap().from_spherical(&[1.0, 0.0, 0.0, 0.0, 1.2, 0.3, 0.0, 0.0]);
+    let model = SphereSprings::connected(config, &x0).unwrap();
+    assert_eq!(model.network.as_ref().unwrap().edges.len(), 1);
+    let trajectory = model.simulate(&x0, 0.001, 20000, 20000);
+    let x = &trajectory.states[1];
+    assert!((model.edge_arclengths(x).unwrap()[0] - PI / 2.0 * r).abs() < 1e-4);
+    assert!(model.potential_energy(x) < 1e-8);
+}
+
+#[test]
+fn test_seeded_initial_state_is_the_same_in_both_formulations() {
+    use crate::initial::Initializer;
+    use rand::{rngs::StdRng, SeedableRng};
+    let cartesian = SphereSprings::new(Config {particles : 5, initializer : Initializer::Fibonacci, temperature : 0.5, ..Config::default()}).unwrap();
+    let spherical = SphereSprings::new(Config {formulation : Formulation::Spherical, ..cartesian.config.clone()}).unwrap();
+    let x_c = cartesian.initial_state(&mut StdRng::seed_from_u64(7)).unwrap();
+    let x_s = spherical.initial_state(&mut StdRng::seed_from_u64(7)).unwrap();
+    assert_eq!(x_c, cartesian.initial_state(&mut StdRng::seed_from_u64(7)).unwrap());
+    assert_ne!(x_c, cartesian.initial_state(&mut StdRng::seed_from_u64(8)).unwrap());
+    //the spherical state converted back gives the same positions and velocities
+    let x_back = cartesian.from_spherical(&x_s);
+    assert!(x_c.iter().zip(&x_back).all(|(a, b)| (a - b).abs() < 1e-9));
+}
@@ -0,0 +1,193 @@
+use crate::model::SphereSprings;
+use crate::plot_2d::{auto_range, gradient_colors, PlotBackend, PlotOptions};
+use crate::topology::angle_between;
+use crate::trajectory::Trajectory;
+use plotters::coord::Shift;
+use plotters::prelude::*;
+use std::error::Error;
+use std::f64::consts::PI;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Histogram {
+    pub edges : Vec<f64>, //bins + 1 increasing bin edges
+    pub values : Vec<f64>, //one per bin
+}
+
+impl Histogram {
+    pub fn centers(&self) -> Vec<f64> {
+        self.edges.windows(2).map(|w| 0.5 * (w[0] + w[1])).collect()
+    }
+}
+
+fn bin(value : f64, min : f64, max : f64, bins : usize) -> usize {
+    //the top edge belongs to the last bin
+    (((value - min) / (max - min) * bins as f64) as usize).min(bins - 1)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PairStatistics {
+    pub pair_distribution : Histogram, //g(theta) over the pair angle (rad), 1 for uniformly random particles
+    pub nearest_neighbors : Histogram, //probability density of the arclength (m) to the nearest particle
+    pub mean_nearest_neighbor : f64, //mean arclength (m) to the nearest particle
+    pub min_separation : f64, //smallest pair angle (rad), the tammes problem maximizes it, averaged over the frames
+    pub packing_density : f64, //fraction of the sphere covered by caps of angular radius min_separation / 2
+    pub frames : usize,
+}
+
+impl PairStatistics {
+    pub fn new(frames : &[Vec<[f64;3]>], r : f64, bins : usize) -> Result<Self, Box<dyn Error>> {
+        /*
+        statistics of the particle directions in frames, averaged over the frames
+        g(theta) is the pair count of a bin over the count expected for uniformly random particles,
+        N (N - 1) / 2 (cos theta_a - cos theta_b) / 2 for the bin from theta_a to theta_b
+         */
+        let n = frames.first().map(|frame| frame.len()).unwrap_or(0);
+        if n < 2 || frames.iter().any(|frame| frame.len() != n) {
+            return Err("pair statistics need frames of at least 2 particles, all of the same size".into());
+        }
+        if bins == 0 {
+            return Err("pair statistics need at least one bin".into());
+        }
+        let mut pair_counts = vec![0.0; bins];
+        let mut nearest = Vec::with_capacity(n * frames.len());
+        let mut min_separation = 0.0;
+        for frame in frames {
+            let mut closest = vec![f64::INFINITY; n];
+            for i in 0..n {
+                for j in i + 1..n {
+                    let angle = angle_between(&frame[i], &frame[j]);
+                    pair_counts[bin(angle, 0.0, PI, bins)] += 1.0;
+                    closest[i] = closest[i].min(angle);
+                    closest[j] = closest[j].min(angle);
+                }
+            }
+            min_separation += closest.iter().fold(f64::INFINITY, |a, b| a.min(*b)) / frames.len() as f64;
+            nearest.extend(closest.iter().map(|angle| r * angle));
+        }
+
+        let edges : Vec<f64> = (0..=bins).map(|k| PI * k as f64 / bins as f64).collect();
+        let expected = (n * (n - 1) / 2 * frames.len()) as f64;
+        let values = pair_counts.iter().enumerate()
+            .map(|(k, count)| count / (expected * 0.5 * (edges[k].cos() - edges[k + 1].cos())))
+            .collect();
+        let pair_distribution = Histogram {edges, values};
+
+        let max = nearest.iter().fold(0.0, |a : f64, b| a.max(*b));
+        let max = if max > 0.0 {max} else {1.0};
+        let width = max / bins as f64;
+        let mut values = vec![0.0; bins];
+        for arclength in &nearest {
+            values[bin(*arclength, 0.0, max, bins)] += 1.0 / (nearest.len() as f64 * width);
+        }
+        let nearest_neighbors = Histogram {edges : (0..=bins).map(|k| width * k as f64).collect(), values};
+
+        let mean_nearest_neighbor = nearest.iter().sum::<f64>() / nearest.len() as f64;
+        let packing_density = n as f64 * (1.0 - (0.5 * min_separation).cos()) / 2.0;
+        Ok(PairStatistics {pair_distribution, nearest_neighbors, mean_nearest_neighbor, min_separation, packing_density, frames : frames.len()})
+    }
+
+    pub fn from_trajectory(model : &SphereSprings, trajectory : &Trajectory, window : Option<f64>, bins : usize) -> Result<Self, Box<dyn Error>> {
+        //averaged over the states of the last window seconds, the last state alone without a window
+        let last = *trajectory.times.last().ok_or("no states to analyze")?;
+        let start = window.map(|window| last - window).unwrap_or(last);
+        let frames : Vec<Vec<[f64;3]>> = trajectory.iter()
+            .filter(|(t, _)| *t >= start)
+            .map(|(_, x)| model.unit_vectors(x))
+            .collect();
+        PairStatistics::new(&frames, model.config.radius, bins)
+    }
+
+    pub fn write_csv(&self, filename : &str) -> Result<(), Box<dyn Error>> {
+        //both histograms by bin, the scalars as '#' comment lines before the header
+        let mut writer = BufWriter::new(File::create(filename)?);
+        writeln!(writer, "# frames={}", self.frames)?;
+        writeln!(writer, "# mean_nearest_neighbor={}", self.mean_nearest_neighbor)?;
+        writeln!(writer, "# min_separation={}", self.min_separation)?;
+        writeln!(writer, "# packing_density={}", self.packing_density)?;
+        writeln!(writer, "theta,g,arclength,nearest_neighbor_density")?;
+        let (theta, arclength) = (self.pair_distribution.centers(), self.nearest_neighbors.centers());
+        for k in 0..theta.len() {
+            writeln!(writer, "{},{},{},{}", theta[k], self.pair_distribution.values[k], arclength[k], self.nearest_neighbors.values[k])?;
+        }
+        writer.flush()?;
+        Ok(())
+    }
+}
+
+pub fn plot_pair_statistics(statistics : &PairStatistics, options : &PlotOptions) -> Result<(), Box<dyn Error>> {
+    //g(theta) above the nearest neighbor distribution, the labels and ranges of options are ignored
+    match options.backend()? {
+        PlotBackend::Svg => {
+            let root = SVGBackend::new(&options.path, options.size).into_drawing_area();
+            draw_pair_statistics(&root, statistics, options)
+        },
+        PlotBackend::Png => {
+            let root = BitMapBackend::new(&options.path, options.size).into_drawing_area();
+            draw_pair_statistics(&root, statistics, options)
+        },
+    }
+}
+
+fn draw_pair_statistics<DB : DrawingBackend>(root : &DrawingArea<DB, Shift>, statistics : &PairStatistics, options : &PlotOptions) -> Result<(), Box<dyn Error>>
+where DB::ErrorType : 'static {
+    root.fill(&WHITE)?;
+    let root = root.titled(&options.title, ("sans-serif", 30).into_font())?;
+    let panels = root.split_evenly((2, 1));
+    let contents = [
+        (&statistics.pair_distribution, "Pair angle (rad)", "g"),
+        (&statistics.nearest_neighbors, "Nearest neighbor arclength (m)", "Probability density (1/m)"),
+    ];
+    let colors = gradient_colors(contents.len());
+    for (k, (panel, (histogram, x_label, y_label))) in panels.iter().zip(contents).enumerate() {
+        let x_range = (histogram.edges[0], histogram.edges[histogram.edges.len() - 1]);
+        let y_max = auto_range(histogram.values.iter().copied()).1.max(1e-12);
+        let mut chart = ChartBuilder::on(panel)
+            .margin(10)
+            .x_label_area_size(30)
+            .y_label_area_size(60)
+            .build_cartesian_2d(x_range.0..x_range.1, 0.0..y_max)?;
+        chart.configure_mesh()
+            .x_desc(x_label)
+            .y_desc(y_label)
+            .draw()?;
+        let color = options.line_styles.get(k).and_then(|style| style.color).unwrap_or(colors[k]);
+        chart.draw_series(histogram.edges.windows(2).zip(&histogram.values)
+            .map(|(w, value)| Rectangle::new([(w[0], 0.0), (w[1], *value)], color.mix(0.6).filled())))?;
+    }
+    root.present()?;
+    Ok(())
+}
+
+#[test]
+fn test_pair_distribution_of_uniform_particles() {
+    //g is close to 1 everywhere for uniformly random particles, and the histograms are normalized
+    use crate::initial::uniform_unit_vectors;
+    use rand::{rngs::StdRng, SeedableRng};
+    let mut rng = StdRng::seed_from_u64(3);
+    let frames : Vec<Vec<[f64;3]>> = (0..20).map(|_| uniform_unit_vectors(100, &mut rng)).collect();
+    let statistics = PairStatistics::new(&frames, 2.0, 10).unwrap();
+    assert!(statistics.pair_distribution.values.iter().all(|g| (g - 1.0).abs() < 0.1), "{:?}", statistics.pair_distribution.values);
+    let h = &statistics.nearest_neighbors;
+    let total : f64 = h.values.iter().zip(h.edges.windows(2)).map(|(density, w)| density * (w[1] - w[0])).sum();
+    assert!((total - 1.0).abs() < 1e-12);
+    assert!(statistics.mean_nearest_neighbor > 0.0 && statistics.mean_nearest_neighbor < h.edges[10]);
+    assert!(PairStatistics::new(&[vec![[0.0, 0.0, 1.0]]], 2.0, 10).is_err());
+    assert!(PairStatistics::new(&frames, 2.0, 0).is_err());
+}
+
+#[test]
+fn test_tammes_separation_of_the_octahedron() {
+    //six particles are best spread over the octahedron, 90 degrees apart
+    use crate::initial::polyhedron_vertices;
+    let statistics = PairStatistics::new(&[polyhedron_vertices(6).unwrap()], 1.0, 3).unwrap();
+    assert!((statistics.min_separation - PI / 2.0).abs() < 1e-12);
+    assert!((statistics.mean_nearest_neighbor - PI / 2.0).abs() < 1e-12);
+    assert!((statistics.packing_density - 3.0 * (1.0 - (PI / 4.0).cos())).abs() < 1e-12);
+    //12 neighbors at 90 degrees and 3 opposite pairs
+    let expected = 15.0 * 0.5 * ((PI / 3.0).cos() - (2.0 * PI / 3.0).cos());
+    assert!((statistics.pair_distribution.values[1] - 12.0 / expected).abs() < 1e-9);
+    assert!((statistics.pair_distribution.values[2] - 3.0 / (15.0 * 0.5 * 0.5)).abs() < 1e-9);
+    assert_eq!(statistics.pair_distribution.values[0], 0.0);
+}
@@ -0,0 +1,231 @@
+use serde::Deserialize;
+use std::f64::consts::PI;
+use std::fmt::{self, Debug};
+use std::str::FromStr;
+
+pub trait PairPotential : Debug + Send + Sync {
+    //energy of a pair at great circle angle between them, on a sphere of radius r
+    fn energy(&self, angle : f64, r : f64) -> f64;
+    //dE/ds with s = r * angle the arclength, positive values pull the pair together
+    fn derivative(&self, angle : f64, r : f64) -> f64;
+    //arclength beyond which the pair no longer interacts, None for potentials reaching around the whole sphere
+    fn range(&self, _r : f64) -> Option<f64> {
+        None
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TangentSpring {
+    //the original interaction, a tangential force k r (angle - pi) sin(angle), at rest only when antipodal
+    pub k : f64,
+}
+
+impl PairPotential for TangentSpring {
+    fn energy(&self, angle : f64, r : f64) -> f64 {
+        self.k * r * r * (angle.sin() + (PI - angle) * angle.cos())
+    }
+
+    fn derivative(&self, angle : f64, r : f64) -> f64 {
+        self.k * r * (angle - PI) * angle.sin()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Harmonic {
+    //0.5 k (s - rest_length)^2 along the great circle
+    pub k : f64,
+    pub rest_length : f64,
+}
+
+impl PairPotential for Harmonic {
+    fn energy(&self, angle : f64, r : f64) -> f64 {
+        0.5 * self.k * (r * angle - self.rest_length).powi(2)
+    }
+
+    fn derivative(&self, angle : f64, r : f64) -> f64 {
+        self.k * (r * angle - self.rest_length)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Riesz {
+    //k / d^s on the chord distance d, s = 1 is the coulomb energy of the thomson problem
+    pub k : f64,
+    pub s : f64,
+}
+
+impl PairPotential for Riesz {
+    fn energy(&self, angle : f64, r : f64) -> f64 {
+        self.k / chord(angle, r).powf(self.s)
+    }
+
+    fn derivative(&self, angle : f64, r : f64) -> f64 {
+        let d = chord(angle, r);
+        -self.s * self.k / d.powf(self.s + 1.0) * chord_derivative(angle)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LennardJones {
+    //4 epsilon ((sigma/d)^12 - (sigma/d)^6) on the chord distance d
+    pub epsilon : f64,
+    pub sigma : f64,
+}
+
+impl PairPotential for LennardJones {
+    fn energy(&self, angle : f64, r : f64) -> f64 {
+        let x = (self.sigma / chord(angle, r)).powi(6);
+        4.0 * self.epsilon * (x * x - x)
+    }
+
+    fn derivative(&self, angle : f64, r : f64) -> f64 {
+        let d = chord(angle, r);
+        let x = (self.sigma / d).powi(6);
+        4.0 * self.epsilon * (-12.0 * x * x + 6.0 * x) / d * chord_derivative(angle)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SoftSphere {
+    //0.5 k (sigma - d)^2 while the chord distance d is below the diameter sigma, nothing beyond
+    pub k : f64,
+    pub sigma : f64,
+}
+
+impl PairPotential for SoftSphere {
+    fn energy(&self, angle : f64, r : f64) -> f64 {
+        let overlap = (self.sigma - chord(angle, r)).max(0.0);
+        0.5 * self.k * overlap * overlap
+    }
+
+    fn derivative(&self, angle : f64, r : f64) -> f64 {
+        let overlap = (self.sigma - chord(angle, r)).max(0.0);
+        -self.k * overlap * chord_derivative(angle)
+    }
+
+    fn range(&self, r : f64) -> Option<f64> {
+        //the arclength at which the chord reaches sigma
+        Some(2.0 * r * (self.sigma / (2.0 * r)).min(1.0).asin())
+    }
+}
+
+fn chord(angle : f64, r : f64) -> f64 {
+    2.0 * r * (angle / 2.0).sin()
+}
+
+fn chord_derivative(angle : f64) -> f64 {
+    //d(chord)/d(arclength)
+    (angle / 2.0).cos()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(try_from = "String")]
+pub enum Interaction {
+    TangentSpring,
+    Harmonic(f64), //rest length (m)
+    Coulomb,
+    Riesz(f64), //exponent s
+    LennardJones(f64), //sigma (m)
+    SoftSphere(f64), //diameter (m)
+}
+
+impl Interaction {
+    pub fn build(&self, strength : f64) -> Box<dyn PairPotential> {
+        //strength is the stiffness K for the springs and the energy scale for the others
+        match *self {
+            Interaction::TangentSpring => Box::new(TangentSpring {k : strength}),
+            Interaction::Harmonic(rest_length) => Box::new(Harmonic {k : strength, rest_length}),
+            Interaction::Coulomb => Box::new(Riesz {k : strength, s : 1.0}),
+            Interaction::Riesz(s) => Box::new(Riesz {k : strength, s}),
+            Interaction::LennardJones(sigma) => Box::new(LennardJones {epsilon : strength, sigma}),
+            Interaction::SoftSphere(sigma) => Box::new(SoftSphere {k : strength, sigma}),
+        }
+    }
+}
+
+impl FromStr for Interaction {
+    type Err = String;
+
+    fn from_str(s : &str) -> Result<Self, Self::Err> {
+        //"tangent_spring", "harmonic:L", "coulomb", "riesz:s", "lennard_jones:sigma" or "soft_sphere:sigma"
+        let (name, argument) = match s.split_once(':') {
+            Some((name, argument)) => {
+                let value = argument.parse::<f64>().map_err(|e| format!("invalid number '{}' in '{}': {}", argument, s, e))?;
+                if !value.is_finite() || value <= 0.0 {
+                    return Err(format!("the parameter of '{}' must be positive", s));
+                }
+                (name, Some(value))
+            },
+            None => (s, None),
+        };
+        match (name, argument) {
+            ("tangent_spring", None) => Ok(Interaction::TangentSpring),
+            ("harmonic", Some(rest_length)) => Ok(Interaction::Harmonic(rest_length)),
+            ("coulomb", None) => Ok(Interaction::Coulomb),
+            ("riesz", Some(exponent)) => Ok(Interaction::Riesz(exponent)),
+            ("lennard_jones", Some(sigma)) => Ok(Interaction::LennardJones(sigma)),
+            ("soft_sphere", Some(sigma)) => Ok(Interaction::SoftSphere(sigma)),
+            _ => Err(format!("expected tangent_spring, harmonic:L, coulomb, riesz:s, lennard_jones:sigma or soft_sphere:sigma, got '{}'", s)),
+        }
+    }
+}
+
+impl fmt::Display for Interaction {
+    fn fmt(&self, f : &mut fmt::Formatter) -> fmt::Result {
+        //the same form from_str reads, so the interaction can be stored as text and rebuilt
+        match self {
+            Interaction::TangentSpring => write!(f, "tangent_spring"),
+            Interaction::Harmonic(rest_length) => write!(f, "harmonic:{}", rest_length),
+            Interaction::Coulomb => write!(f, "coulomb"),
+            Interaction::Riesz(s) => write!(f, "riesz:{}", s),
+            Interaction::LennardJones(sigma) => write!(f, "lennard_jones:{}", sigma),
+            Interaction::SoftSphere(sigma) => write!(f, "soft_sphere:{}", sigma),
+        }
+    }
+}
+
+impl TryFrom<String> for Interaction {
+    type Error = String;
+
+    fn try_from(s : String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+#[test]
+fn test_derivatives_match_energies() {
+    let r = 2.0;
+    let potentials : Vec<Box<dyn PairPotential>> = vec![
+        Box::new(TangentSpring {k : 2.0}),
+        Box::new(Harmonic {k : 2.0, rest_length : 1.5}),
+        Box::new(Riesz {k : 1.0, s : 1.0}),
+        Box::new(Riesz {k : 1.0, s : 3.0}),
+        Box::new(LennardJones {epsilon : 1.0, sigma : 1.0}),
+        Box::new(SoftSphere {k : 5.0, sigma : 3.0}),
+    ];
+    let h = 1e-6;
+    for potential in &potentials {
+        for angle in [0.4, 1.0, 2.0, 3.0] {
+            let numeric = (potential.energy(angle + h / r, r) - potential.energy(angle - h / r, r)) / (2.0 * h);
+            let analytic = potential.derivative(angle, r);
+            assert!((numeric - analytic).abs() < 1e-5 * analytic.abs().max(1.0), "{:?} at {}", potential, angle);
+        }
+    }
+    //the tangent spring is the original k r free_length(angle, pi) force times |e_i x e_j| = sin(angle)
+    let spring = TangentSpring {k : 2.0};
+    assert!((spring.derivative(1.0, r) - 2.0 * r * (1.0 - PI) * 1.0f64.sin()).abs() < 1e-12);
+}
+
+#[test]
+fn test_interaction_from_str() {
+    assert_eq!("tangent_spring".parse::<Interaction>().unwrap(), Interaction::TangentSpring);
+    assert_eq!("harmonic:1.5".parse::<Interaction>().unwrap(), Interaction::Harmonic(1.5));
+    assert_eq!("riesz:2".parse::<Interaction>().unwrap(), Interaction::Riesz(2.0));
+    assert!("coulomb:2".parse::<Interaction>().is_err());
+    assert!("harmonic".parse::<Interaction>().is_err());
+    assert!("soft_sphere:-1".parse::<Interaction>().is_err());
+    assert!("gravity".parse::<Interaction>().is_err());
+    for interaction in [Interaction::TangentSpring, Interaction::Harmonic(1.5), Interaction::Coulomb, Interaction::Riesz(0.1), Interaction::LennardJones(2.0), Interaction::SoftSphere(0.3)] {
+        assert_eq!(interaction.to_string().parse::<Interaction>().unwrap(), interaction);
+    }
+}
@@ -0,0 +1,67 @@
+use crate::model::SphereSprings;
+use sim_common::spectrum::{is_uniform, power_spectrum, resample, Spectrum, Window};
+use sim_common::trajectory::Trajectory;
+use std::error::Error;
+
+pub fn particle_spectra(model : &SphereSprings, trajectory : &Trajectory, resample_dt : Option<f64>, window : Window) -> Result<Vec<Spectrum>, Box<dyn Error>> {
+    //spectra of the cartesian coordinates of every particle, ordered x_0, y_0, z_0, x_1, ...
+    if trajectory.len() < 4 {
+        return Err("at least 4 samples are needed for a spectrum".into());
+    }
+    let dt = match resample_dt {
+        Some(dt) => dt,
+        None => {
+            if !is_uniform(&trajectory.times, 1e-6) {
+                return Err("trajectory is not uniformly sampled, pass a resample step".into());
+            }
+            (trajectory.times[trajectory.len() - 1] - trajectory.times[0]) / (trajectory.len() - 1) as f64
+        },
+    };
+    let positions = trajectory.map_states(|x| model.x_2_positions(x));
+    let mut spectra = Vec::with_capacity(3 * model.config.particles);
+    for i in 0..model.config.particles {
+        for axis in 0..3 {
+            let values : Vec<f64> = positions.iter().map(|p| p[i][axis] as f64).collect();
+            let values = match resample_dt {
+                Some(dt) => resample(&trajectory.times, &values, dt)?.1,
+                None => values,
+            };
+            spectra.push(power_spectrum(&values, dt, window));
+        }
+    }
+    Ok(spectra)
+}
+
+pub fn sum_spectra(spectra : &[Spectrum]) -> Option<Spectrum> {
+    //adds the power of spectra sharing the same frequencies, e.g. all particle coordinates
+    let first = spectra.first()?;
+    let mut power = vec![0.0; first.power.len()];
+    for spectrum in spectra {
+        if spectrum.frequencies.len() != power.len() {
+            return None;
+        }
+        for (total, p) in power.iter_mut().zip(&spectrum.power) {
+            *total += p;
+        }
+    }
+    Some(Spectrum {frequencies : first.frequencies.clone(), power})
+}
+
+#[test]
+fn test_particle_spectra() {
+    //two particles rotating rigidly about the z axis at 0.5 Hz
+    use crate::config::{Config, Formulation};
+    use std::f64::consts::PI;
+    let model = SphereSprings::new(Config {particles : 2, formulation : Formulation::Spherical, ..Config::default()}).unwrap();
+    let dt = 0.05;
+    let mut trajectory = Trajectory::new(model.metadata(dt), &model.state_names());
+    for i in 0..400 {
+        let t = i as f64 * dt;
+        let phi = 2.0 * PI * 0.5 * t;
+        trajectory.push(t, &[1.0, phi, 0.0, PI, 2.0, phi + 1.0, 0.0, PI]);
+    }
+    let spectra = particle_spectra(&model, &trajectory, None, Window::Hann).unwrap();
+    assert_eq!(spectra.len(), 6);
+    let total = sum_spectra(&spectra).unwrap();
+    assert!((total.dominant_frequency().unwrap() - 0.5).abs() < 0.01);
+}
@@ -0,0 +1,183 @@
+use crate::config::Config;
+use crate::math::dot;
+use crate::model::SphereSprings;
+use std::fmt;
+use std::time::Instant;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct StoppingCriteria {
+    pub kinetic_energy : Option<f64>, //stop once the kinetic energy (J) stayed below this for a window
+    pub max_speed : Option<f64>, //stop once no particle moved faster than this (m/s) for a window
+    pub energy_change : Option<f64>, //stop once the potential energy changed by less than this fraction over a window
+    pub window : f64, //seconds of simulated time the criteria above have to hold
+    pub wall_time : Option<f64>, //stop after this many seconds of real time
+}
+
+impl Default for StoppingCriteria {
+    fn default() -> Self {
+        StoppingCriteria {kinetic_energy : None, max_speed : None, energy_change : None, window : 1.0, wall_time : None}
+    }
+}
+
+impl StoppingCriteria {
+    pub fn from_config(config : &Config) -> Self {
+        StoppingCriteria {
+            kinetic_energy : config.stop_kinetic_energy,
+            max_speed : config.stop_speed,
+            energy_change : config.stop_energy_change,
+            window : config.stop_window,
+            wall_time : config.max_wall_time,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StopReason {
+    KineticEnergy,
+    MaxSpeed,
+    EnergyChange,
+    WallTime,
+    MaxTime, //ran all the steps
+}
+
+impl fmt::Display for StopReason {
+    fn fmt(&self, f : &mut fmt::Formatter) -> fmt::Result {
+        let description = match self {
+            StopReason::KineticEnergy => "kinetic energy stayed below the threshold",
+            StopReason::MaxSpeed => "every particle stayed slower than the threshold",
+            StopReason::EnergyChange => "potential energy stopped changing",
+            StopReason::WallTime => "wall clock limit reached",
+            StopReason::MaxTime => "reached the end time",
+        };
+        write!(f, "{}", description)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Stop {
+    pub reason : StopReason,
+    pub time : f64, //simulated time of the last state (s)
+    pub wall_time : f64, //real time spent integrating (s)
+}
+
+pub(crate) struct StopMonitor {
+    criteria : StoppingCriteria,
+    start : Instant,
+    slow_since : Option<f64>, //when the kinetic energy went below its threshold
+    still_since : Option<f64>, //when the fastest particle went below its threshold
+    window_start : (f64, f64), //(time, potential energy) at the start of the current window
+    energy_scale : f64, //initial kinetic plus absolute potential energy, the energy change is relative to at least this
+}
+
+impl StopMonitor {
+    pub(crate) fn new(criteria : StoppingCriteria, model : &SphereSprings, x0 : &[f64]) -> Self {
+        //the potential energy costs a pair sum, it is only computed when its criterion is used
+        let energy = if criteria.energy_change.is_some() {model.potential_energy(x0)} else {0.0};
+        let energy_scale = if criteria.energy_change.is_some() {model.kinetic_energy(x0) + energy.abs()} else {0.0};
+        StopMonitor {criteria, start : Instant::now(), slow_since : None, still_since : None, window_start : (0.0, energy), energy_scale}
+    }
+
+    pub(crate) fn elapsed(&self) -> f64 {
+        self.start.elapsed().as_secs_f64()
+    }
+
+    pub(crate) fn check(&mut self, model : &SphereSprings, t : f64, x : &[f64]) -> Option<StopReason> {
+        let criteria = self.criteria;
+        if criteria.wall_time.is_some_and(|limit| self.elapsed() >= limit) {
+            return Some(StopReason::WallTime);
+        }
+        if criteria.kinetic_energy.is_some() || criteria.max_speed.is_some() {
+            let speeds_squared : Vec<f64> = model.velocities(x).iter().map(|v| dot(v, v)).collect();
+            let kinetic = 0.5 * model.config.mass * speeds_squared.iter().sum::<f64>();
+            let max_speed = speeds_squared.iter().fold(0.0, |a : f64, b| a.max(*b)).sqrt();
+            if held(&mut self.slow_since, criteria.kinetic_energy.map(|limit| kinetic < limit), t, criteria.window) {
+                return Some(StopReason::KineticEnergy);
+            }
+            if held(&mut self.still_since, criteria.max_speed.map(|limit| max_speed < limit), t, criteria.window) {
+                return Some(StopReason::MaxSpeed);
+            }
+        }
+        if let Some(tolerance) = criteria.energy_change {
+            let (start, energy) = self.window_start;
+            if t - start >= criteria.window {
+                let next = model.potential_energy(x);
+                self.window_start = (t, next);
+                //relative to the initial energies as well, a potential that relaxes to 0 would never settle relative to itself
+                if (next - energy).abs() <= tolerance * energy.abs().max(self.energy_scale) {
+                    return Some(StopReason::EnergyChange);
+                }
+            }
+        }
+        None
+    }
+}
+
+fn held(since : &mut Option<f64>, holds : Option<bool>, t : f64, window : f64) -> bool {
+    //whether a condition held without a break for the last window seconds
+    match holds {
+        Some(true) => t - *since.get_or_insert(t) >= window,
+        _ => {
+            *since = None;
+            false
+        },
+    }
+}
+
+#[test]
+fn test_criteria_stop_a_damped_run() {
+    use crate::initial::Initializer;
+    use rand::{rngs::StdRng, SeedableRng};
+    let model = SphereSprings::new(Config {particles : 4, damping : 2.0, temperature : 1.0, initializer : Initializer::Fibonacci, ..Config::default()}).unwrap();
+    let x0 = model.initial_state(&mut StdRng::seed_from_u64(0)).unwrap();
+    let steps = 40000;
+
+    let (trajectory, stop) = model.simulate_until(&x0, 0.001, steps, 100, &StoppingCriteria::default());
+    assert_eq!(stop.reason, StopReason::MaxTime);
+    assert!((stop.time - 40.0).abs() < 1e-9);
+    assert_eq!(trajectory.len(), 401);
+
+    let criteria = StoppingCriteria {kinetic_energy : Some(1e-6), window : 0.5, ..StoppingCriteria::default()};
+    let (trajectory, stop) = model.simulate_until(&x0, 0.001, steps, 100, &criteria);
+    assert_eq!(stop.reason, StopReason::KineticEnergy);
+    assert!(stop.time < 40.0);
+    //the state the run stopped at is always recorded
+    assert_eq!(*trajectory.times.last().unwrap(), stop.time);
+    assert!(model.kinetic_energy(trajectory.states.last().unwrap()) < 1e-6);
+
+    let criteria = StoppingCriteria {max_speed : Some(1e-3), ..StoppingCriteria::default()};
+    assert_eq!(model.simulate_until(&x0, 0.001, steps, 100, &criteria).1.reason, StopReason::MaxSpeed);
+    let criteria = StoppingCriteria {energy_change : Some(1e-9), ..StoppingCriteria::default()};
+    let (_, stop) = model.simulate_until(&x0, 0.001, steps, 100, &criteria);
+    assert_eq!(stop.reason, StopReason::EnergyChange);
+    assert!(stop.time < 40.0);
+    let criteria = StoppingCriteria {wall_time : Some(0.0), ..StoppingCriteria::default()};
+    let (trajectory, stop) = model.simulate_until(&x0, 0.001, steps, 100, &criteria);
+    assert_eq!((stop.reason, trajectory.len()), (StopReason::WallTime, 2));
+}
+
+#[test]
+fn test_energy_change_stops_at_zero_potential() {
+    //a spring at its rest length has no energy left, the change is measured against the initial energy instead
+    use crate::topology::NetworkSpec;
+    let config = Config {particles : 2, damping : 2.0, network : Some(NetworkSpec::Ring), rest_length : Some(2.0), ..Config::default()};
+    let x0 = SphereSprings::new(Config {network : None, ..config.clone()}).unwrap().from_spherical(&[1.0, 0.0, 0.5, 0.0, 2.0, 0.0, 0.0, 0.0]);
+    let model = SphereSprings::connected(config, &x0).unwrap();
+    assert_eq!(model.potential_energy(&x0), 0.0);
+    let criteria = StoppingCriteria {energy_change : Some(1e-6), ..StoppingCriteria::default()};
+    let (trajectory, stop) = model.simulate_until(&x0, 0.001, 40000, 100, &criteria);
+    assert_eq!(stop.reason, StopReason::EnergyChange);
+    assert!(stop.time < 15.0, "stopped at {} s", stop.time);
+    assert!(model.potential_energy(trajectory.states.last().unwrap()) < 1e-6);
+}
+
+#[test]
+fn test_criteria_need_the_whole_window() {
+    //a particle at rest at the start does not count as settled
+    let mut since = None;
+    assert!(!held(&mut since, Some(true), 0.0, 1.0));
+    assert!(!held(&mut since, Some(true), 0.5, 1.0));
+    assert!(!held(&mut since, Some(false), 0.8, 1.0));
+    assert!(!held(&mut since, Some(true), 1.0, 1.0));
+    assert!(held(&mut since, Some(true), 2.0, 1.0));
+    assert!(!held(&mut None, None, 5.0, 1.0));
+}
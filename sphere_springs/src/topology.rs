@@ -0,0 +1,299 @@
+use crate::math::{cross, dot, norm};
+use crate::potential::{Harmonic, PairPotential};
+use serde::Deserialize;
+use std::collections::BTreeSet;
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::str::FromStr;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Edge {
+    pub i : usize,
+    pub j : usize,
+    pub spring : Harmonic, //stiffness and rest length (arclength) of this edge
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Network {
+    pub particles : usize,
+    pub edges : Vec<Edge>,
+    neighbors : Vec<Vec<(usize, Harmonic)>>, //for every particle, the other end and spring of each of its edges
+}
+
+impl Network {
+    pub fn new(particles : usize, edges : Vec<Edge>) -> Result<Self, Box<dyn Error>> {
+        let mut neighbors = vec![Vec::new(); particles];
+        let mut pairs = BTreeSet::new();
+        for edge in &edges {
+            if edge.i >= particles || edge.j >= particles {
+                return Err(format!("edge {}-{} is out of range for {} particles", edge.i, edge.j, particles).into());
+            }
+            if edge.i == edge.j {
+                return Err(format!("edge {}-{} connects a particle to itself", edge.i, edge.j).into());
+            }
+            //a repeated pair would count its spring twice
+            if !pairs.insert((edge.i.min(edge.j), edge.i.max(edge.j))) {
+                return Err(format!("edge {}-{} appears more than once", edge.i, edge.j).into());
+            }
+            neighbors[edge.i].push((edge.j, edge.spring));
+            neighbors[edge.j].push((edge.i, edge.spring));
+        }
+        Ok(Network {particles, edges, neighbors})
+    }
+
+    pub fn neighbors(&self, i : usize) -> &[(usize, Harmonic)] {
+        &self.neighbors[i]
+    }
+
+    pub fn energy(&self, unit_vectors : &[[f64;3]], r : f64) -> f64 {
+        self.edges.iter()
+            .map(|edge| edge.spring.energy(angle_between(&unit_vectors[edge.i], &unit_vectors[edge.j]), r))
+            .sum()
+    }
+}
+
+//i, j, and the stiffness and rest length when they differ from the defaults
+type EdgeEntry = (usize, usize, Option<f64>, Option<f64>);
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(try_from = "String")]
+pub enum NetworkSpec {
+    Ring, //particle i to particle i + 1, closing the loop
+    Nearest(usize), //every particle to its k nearest neighbors
+    Delaunay, //edges of the spherical delaunay triangulation
+    File(String), //lines of "i j [stiffness [rest_length]]"
+}
+
+impl NetworkSpec {
+    pub fn build(&self, unit_vectors : &[[f64;3]], r : f64, stiffness : f64, rest_length : Option<f64>) -> Result<Network, Box<dyn Error>> {
+        /*
+        connects particles at the given positions (unit vectors), every edge gets the given stiffness
+        without a rest length every edge rests at the mean initial edge length, so the network relaxes towards an even mesh
+        edge-list files can set both per edge
+         */
+        let n = unit_vectors.len();
+        let pairs : Vec<EdgeEntry> = match self {
+            NetworkSpec::Ring => (0..n).filter(|&i| n > 2 || i == 0).map(|i| (i, (i + 1) % n, None, None)).collect(),
+            NetworkSpec::Nearest(k) => nearest_pairs(unit_vectors, *k).into_iter().map(|(i, j)| (i, j, None, None)).collect(),
+            NetworkSpec::Delaunay => triangulation_edges(&spherical_delaunay(unit_vectors)?).into_iter().map(|(i, j)| (i, j, None, None)).collect(),
+            NetworkSpec::File(filename) => read_edge_list(filename)?,
+        };
+        if pairs.iter().any(|(i, j, _, _)| *i >= n || *j >= n) {
+            return Err(format!("the network refers to particles beyond the {} simulated", n).into());
+        }
+        let mean_length = pairs.iter().map(|(i, j, _, _)| r * angle_between(&unit_vectors[*i], &unit_vectors[*j])).sum::<f64>()
+            / pairs.len().max(1) as f64;
+        let edges = pairs.into_iter().map(|(i, j, k, length)| Edge {
+            i,
+            j,
+            spring : Harmonic {k : k.unwrap_or(stiffness), rest_length : length.or(rest_length).unwrap_or(mean_length)},
+        }).collect();
+        Network::new(n, edges)
+    }
+}
+
+impl FromStr for NetworkSpec {
+    type Err = String;
+
+    fn from_str(s : &str) -> Result<Self, Self::Err> {
+        //"ring", "nearest:k", "delaunay" or "file:PATH"
+        match s.split_once(':') {
+            None if s == "ring" => Ok(NetworkSpec::Ring),
+            None if s == "delaunay" => Ok(NetworkSpec::Delaunay),
+            Some(("nearest", k)) => match k.parse::<usize>() {
+                Ok(k) if k > 0 => Ok(NetworkSpec::Nearest(k)),
+                _ => Err(format!("expected a positive number of neighbors, got '{}'", k)),
+            },
+            Some(("file", path)) if !path.is_empty() => Ok(NetworkSpec::File(path.to_string())),
+            _ => Err(format!("expected ring, nearest:k, delaunay or file:PATH, got '{}'", s)),
+        }
+    }
+}
+
+impl fmt::Display for NetworkSpec {
+    fn fmt(&self, f : &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            NetworkSpec::Ring => write!(f, "ring"),
+            NetworkSpec::Nearest(k) => write!(f, "nearest:{}", k),
+            NetworkSpec::Delaunay => write!(f, "delaunay"),
+            NetworkSpec::File(path) => write!(f, "file:{}", path),
+        }
+    }
+}
+
+impl TryFrom<String> for NetworkSpec {
+    type Error = String;
+
+    fn try_from(s : String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+pub fn angle_between(a : &[f64;3], b : &[f64;3]) -> f64 {
+    dot(a, b).clamp(-1.0, 1.0).acos()
+}
+
+fn nearest_pairs(unit_vectors : &[[f64;3]], k : usize) -> BTreeSet<(usize, usize)> {
+    //undirected, so a particle can end up with more than k edges
+    let mut pairs = BTreeSet::new();
+    for i in 0..unit_vectors.len() {
+        let mut others : Vec<(f64, usize)> = (0..unit_vectors.len())
+            .filter(|&j| j != i)
+            .map(|j| (angle_between(&unit_vectors[i], &unit_vectors[j]), j))
+            .collect();
+        others.sort_by(|a, b| a.0.total_cmp(&b.0));
+        for (_, j) in others.into_iter().take(k) {
+            pairs.insert((i.min(j), i.max(j)));
+        }
+    }
+    pairs
+}
+
+pub fn triangulation_edges(triangles : &[[usize;3]]) -> BTreeSet<(usize, usize)> {
+    triangles.iter()
+        .flat_map(|t| [(t[0], t[1]), (t[1], t[2]), (t[2], t[0])])
+        .map(|(a, b)| (a.min(b), a.max(b)))
+        .collect()
+}
+
+pub fn spherical_delaunay(unit_vectors : &[[f64;3]]) -> Result<Vec<[usize;3]>, Box<dyn Error>> {
+    /*
+    triangles of the delaunay triangulation of points on a sphere, the faces of their convex hull
+    incremental hull, every triangle is counter clockwise seen from outside the sphere
+     */
+    let p = unit_vectors;
+    let n = p.len();
+    if n < 4 {
+        return Err(format!("a triangulation needs at least 4 points, got {}", n).into());
+    }
+    let eps = 1e-12;
+    let sub = |a : &[f64;3], b : &[f64;3]| [a[0] - b[0], a[1] - b[1], a[2] - b[2]];
+    let normal = |t : &[usize;3]| cross(&sub(&p[t[1]], &p[t[0]]), &sub(&p[t[2]], &p[t[0]]));
+
+    //a starting tetrahedron from four points that are not coplanar
+    let second = (1..n).max_by(|&a, &b| norm(&sub(&p[a], &p[0])).total_cmp(&norm(&sub(&p[b], &p[0])))).unwrap();
+    let third = (1..n).filter(|&c| c != second)
+        .max_by(|&a, &b| norm(&normal(&[0, second, a])).total_cmp(&norm(&normal(&[0, second, b])))).unwrap();
+    let base = [0, second, third];
+    let base_normal = normal(&base);
+    let fourth = (1..n).filter(|&d| d != second && d != third)
+        .max_by(|&a, &b| dot(&base_normal, &sub(&p[a], &p[0])).abs().total_cmp(&dot(&base_normal, &sub(&p[b], &p[0])).abs())).unwrap();
+    if norm(&base_normal) < eps || dot(&base_normal, &sub(&p[fourth], &p[0])).abs() < eps {
+        return Err("the points are coplanar or coincide, they have no triangulation".into());
+    }
+    let mut faces : Vec<[usize;3]> = vec![[0, second, third], [0, third, fourth], [0, fourth, second], [second, fourth, third]];
+    //orient every face away from the centroid of the tetrahedron
+    let centroid = [0, 1, 2].map(|axis| (p[0][axis] + p[second][axis] + p[third][axis] + p[fourth][axis]) / 4.0);
+    for face in faces.iter_mut() {
+        if dot(&normal(face), &sub(&p[face[0]], &centroid)) < 0.0 {
+            face.swap(1, 2);
+        }
+    }
+
+    for point in 0..n {
+        if [0, second, third, fourth].contains(&point) {
+            continue;
+        }
+        let (visible, hidden) : (Vec<[usize;3]>, Vec<[usize;3]>) = faces.into_iter()
+            .partition(|face| dot(&normal(face), &sub(&p[point], &p[face[0]])) > eps);
+        faces = hidden;
+        if visible.is_empty() {
+            //inside the hull, a duplicate point
+            continue;
+        }
+        //the horizon is made of the visible edges whose reverse is not visible
+        let visible_edges : BTreeSet<(usize, usize)> = visible.iter()
+            .flat_map(|t| [(t[0], t[1]), (t[1], t[2]), (t[2], t[0])])
+            .collect();
+        for &(a, b) in &visible_edges {
+            if !visible_edges.contains(&(b, a)) {
+                faces.push([a, b, point]);
+            }
+        }
+    }
+    Ok(faces)
+}
+
+fn read_edge_list(filename : &str) -> Result<Vec<EdgeEntry>, Box<dyn Error>> {
+    let text = fs::read_to_string(filename).map_err(|e| format!("could not open edge list '{}': {}", filename, e))?;
+    let mut edges = Vec::new();
+    for (number, line) in text.lines().enumerate() {
+        let line = line.split('#').next().unwrap_or("").trim();
+        if line.is_empty() {
+            continue;
+        }
+        let fields : Vec<&str> = line.split([' ', ',', '\t']).filter(|field| !field.is_empty()).collect();
+        let error = || format!("{} line {}: expected 'i j [stiffness [rest_length]]', got '{}'", filename, number + 1, line);
+        if fields.len() < 2 || fields.len() > 4 {
+            return Err(error().into());
+        }
+        let i = fields[0].parse::<usize>().map_err(|_| error())?;
+        let j = fields[1].parse::<usize>().map_err(|_| error())?;
+        let stiffness = fields.get(2).map(|f| f.parse::<f64>()).transpose().map_err(|_| error())?;
+        let rest_length = fields.get(3).map(|f| f.parse::<f64>()).transpose().map_err(|_| error())?;
+        if stiffness.is_some_and(|k| !k.is_finite() || k <= 0.0) {
+            return Err(format!("{} line {}: the stiffness must be positive, got '{}'", filename, number + 1, fields[2]).into());
+        }
+        if rest_length.is_some_and(|length| !length.is_finite() || length < 0.0) {
+            return Err(format!("{} line {}: the rest length must be non-negative, got '{}'", filename, number + 1, fields[3]).into());
+        }
+        edges.push((i, j, stiffness, rest_length));
+    }
+    Ok(edges)
+}
+
+#[cfg(test)]
+fn random_unit_vectors(n : usize, seed : u64) -> Vec<[f64;3]> {
+    crate::initial::uniform_unit_vectors(n, &mut <rand::rngs::StdRng as rand::SeedableRng>::seed_from_u64(seed))
+}
+
+#[test]
+fn test_spherical_delaunay() {
+    //a triangulation of a sphere has 2n - 4 triangles and 3n - 6 edges, and its triangles have empty circumcircles
+    let points = random_unit_vectors(60, 1);
+    let triangles = spherical_delaunay(&points).unwrap();
+    assert_eq!(triangles.len(), 2 * 60 - 4);
+    assert_eq!(triangulation_edges(&triangles).len(), 3 * 60 - 6);
+    for t in &triangles {
+        let a = &points[t[0]];
+        let sub = |u : &[f64;3], v : &[f64;3]| [u[0] - v[0], u[1] - v[1], u[2] - v[2]];
+        let n = cross(&sub(&points[t[1]], a), &sub(&points[t[2]], a));
+        assert!(dot(&n, a) > 0.0);
+        assert!(points.iter().all(|q| dot(&n, &sub(q, a)) <= 1e-12));
+    }
+    assert!(spherical_delaunay(&points[..3]).is_err());
+}
+
+#[test]
+fn test_network_generators() {
+    let points = random_unit_vectors(20, 2);
+    let ring = NetworkSpec::Ring.build(&points, 2.0, 3.0, Some(1.0)).unwrap();
+    assert_eq!(ring.edges.len(), 20);
+    assert!(ring.edges.iter().all(|edge| edge.spring == Harmonic {k : 3.0, rest_length : 1.0}));
+    assert_eq!(ring.neighbors(0).len(), 2);
+
+    let nearest = NetworkSpec::Nearest(3).build(&points, 2.0, 3.0, None).unwrap();
+    assert!(nearest.edges.len() >= 30 && nearest.edges.len() <= 60);
+    let rest = nearest.edges[0].spring.rest_length;
+    assert!(nearest.edges.iter().all(|edge| edge.spring.rest_length == rest));
+
+    let path = std::env::temp_dir().join(format!("sphere_edges_{}.txt", std::process::id()));
+    fs::write(&path, "# i j stiffness rest_length\n0 1\n1 2 5.0\n2 3 5.0 0.5 # short\n").unwrap();
+    let spec : NetworkSpec = format!("file:{}", path.to_str().unwrap()).parse().unwrap();
+    let network = spec.build(&points, 2.0, 3.0, Some(1.0)).unwrap();
+    assert_eq!(network.edges[0].spring, Harmonic {k : 3.0, rest_length : 1.0});
+    assert_eq!(network.edges[2].spring, Harmonic {k : 5.0, rest_length : 0.5});
+    assert!(spec.build(&points[..3], 2.0, 3.0, None).is_err());
+    for invalid in ["0 1 stiff\n", "0 1 -5.0\n", "0 1 NaN\n", "0 1 5.0 -0.5\n", "0 1 5.0 inf\n", "0 1\n1 2\n1 0 5.0\n", "0 0\n"] {
+        fs::write(&path, invalid).unwrap();
+        assert!(spec.build(&points, 2.0, 3.0, None).is_err(), "{:?}", invalid);
+    }
+    fs::remove_file(&path).unwrap();
+
+    assert!("nearest:0".parse::<NetworkSpec>().is_err());
+    assert!("mesh".parse::<NetworkSpec>().is_err());
+    for spec in [NetworkSpec::Ring, NetworkSpec::Nearest(4), NetworkSpec::Delaunay, NetworkSpec::File("edges.txt".to_string())] {
+        assert_eq!(spec.to_string().parse::<NetworkSpec>().unwrap(), spec);
+    }
+}
@@ -0,0 +1,145 @@
+use crate::math::{cross, dot, normalize};
+use crate::topology::spherical_delaunay;
+use std::collections::{BTreeMap, HashMap};
+use std::error::Error;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct VoronoiCell {
+    pub neighbors : Vec<usize>, //delaunay neighbors, counter clockwise seen from outside
+    pub vertices : Vec<[f64;3]>, //unit vectors of the cell corners, vertices[k] lies between neighbors k and k + 1
+    pub area : f64, //solid angle of the cell (sr), times R^2 for the area on the sphere
+}
+
+impl VoronoiCell {
+    pub fn coordination(&self) -> usize {
+        self.neighbors.len()
+    }
+
+    pub fn charge(&self) -> i64 {
+        //topological charge 6 - z, the charges of a triangulated sphere add up to 12
+        6 - self.coordination() as i64
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Tessellation {
+    pub triangles : Vec<[usize;3]>,
+    pub cells : Vec<VoronoiCell>, //one per particle
+}
+
+impl Tessellation {
+    pub fn new(unit_vectors : &[[f64;3]]) -> Result<Self, Box<dyn Error>> {
+        /*
+        delaunay triangulation of the particles and its dual, the spherical voronoi diagram
+        the corners of a cell are the circumcenters of the triangles around the particle,
+        its area is the sum of the spherical triangles (particle, corner k, corner k + 1)
+         */
+        let triangles = spherical_delaunay(unit_vectors)?;
+        let circumcenters : Vec<[f64;3]> = triangles.iter().map(|t| {
+            let (a, b, c) = (&unit_vectors[t[0]], &unit_vectors[t[1]], &unit_vectors[t[2]]);
+            normalize(&cross(&[b[0] - a[0], b[1] - a[1], b[2] - a[2]], &[c[0] - a[0], c[1] - a[1], c[2] - a[2]]))
+        }).collect();
+        //for every particle: neighbor -> (next neighbor counter clockwise, triangle between them)
+        let mut fans : Vec<HashMap<usize, (usize, usize)>> = vec![HashMap::new(); unit_vectors.len()];
+        for (index, t) in triangles.iter().enumerate() {
+            for k in 0..3 {
+                fans[t[k]].insert(t[(k + 1) % 3], (t[(k + 2) % 3], index));
+            }
+        }
+        let mut cells = Vec::with_capacity(unit_vectors.len());
+        for (i, fan) in fans.iter().enumerate() {
+            let start = *fan.keys().min().ok_or(format!("particle {} coincides with another one and has no voronoi cell", i))?;
+            let mut neighbors = Vec::with_capacity(fan.len());
+            let mut vertices = Vec::with_capacity(fan.len());
+            let mut current = start;
+            loop {
+                let (next, triangle) = fan[&current];
+                neighbors.push(current);
+                vertices.push(circumcenters[triangle]);
+                current = next;
+                if current == start || neighbors.len() > fan.len() {
+                    break;
+                }
+            }
+            let area = (0..vertices.len())
+                .map(|k| spherical_triangle_area(&unit_vectors[i], &vertices[k], &vertices[(k + 1) % vertices.len()]))
+                .sum();
+            cells.push(VoronoiCell {neighbors, vertices, area});
+        }
+        Ok(Tessellation {triangles, cells})
+    }
+
+    pub fn coordination_counts(&self) -> BTreeMap<usize, usize> {
+        //number of particles with each coordination number
+        let mut counts = BTreeMap::new();
+        for cell in &self.cells {
+            *counts.entry(cell.coordination()).or_insert(0) += 1;
+        }
+        counts
+    }
+
+    pub fn defects(&self) -> Vec<usize> {
+        //particles that are not 6-fold coordinated
+        (0..self.cells.len()).filter(|&i| self.cells[i].coordination() != 6).collect()
+    }
+
+    pub fn total_charge(&self) -> i64 {
+        self.cells.iter().map(|cell| cell.charge()).sum()
+    }
+
+    pub fn write_csv(&self, filename : &str, unit_vectors : &[[f64;3]], r : f64) -> Result<(), Box<dyn Error>> {
+        //one row per particle, cell areas on a sphere of radius r
+        let mut writer = BufWriter::new(File::create(filename)?);
+        writeln!(writer, "particle,x,y,z,coordination,charge,cell_area,neighbors")?;
+        for (i, cell) in self.cells.iter().enumerate() {
+            let e = unit_vectors[i];
+            let neighbors : Vec<String> = cell.neighbors.iter().map(|j| j.to_string()).collect();
+            writeln!(writer, "{},{},{},{},{},{},{},{}", i, r * e[0], r * e[1], r * e[2], cell.coordination(), cell.charge(), r * r * cell.area, neighbors.join(" "))?;
+        }
+        writer.flush()?;
+        Ok(())
+    }
+}
+
+pub fn spherical_triangle_area(a : &[f64;3], b : &[f64;3], c : &[f64;3]) -> f64 {
+    //signed solid angle of the triangle of unit vectors a, b, c, positive when counter clockwise seen from outside
+    2.0 * dot(a, &cross(b, c)).atan2(1.0 + dot(a, b) + dot(b, c) + dot(c, a))
+}
+
+#[test]
+fn test_icosahedron_has_twelve_five_fold_defects() {
+    use crate::initial::polyhedron_vertices;
+    use std::f64::consts::PI;
+    let points = polyhedron_vertices(12).unwrap();
+    let tessellation = Tessellation::new(&points).unwrap();
+    assert_eq!(tessellation.triangles.len(), 20);
+    assert_eq!(tessellation.coordination_counts(), BTreeMap::from([(5, 12)]));
+    assert_eq!((tessellation.defects().len(), tessellation.total_charge()), (12, 12));
+    for cell in &tessellation.cells {
+        assert!((cell.area - 4.0 * PI / 12.0).abs() < 1e-12);
+        assert_eq!(cell.vertices.len(), 5);
+    }
+    let mut duplicated = points[..6].to_vec();
+    duplicated.push(points[0]);
+    assert!(Tessellation::new(&duplicated).is_err());
+}
+
+#[test]
+fn test_cells_cover_the_sphere() {
+    //a large fibonacci lattice is mostly 6-fold, with a net charge of 12 carried by the defects
+    use crate::initial::fibonacci_unit_vectors;
+    use std::f64::consts::PI;
+    let points = fibonacci_unit_vectors(400);
+    let tessellation = Tessellation::new(&points).unwrap();
+    let total_area : f64 = tessellation.cells.iter().map(|cell| cell.area).sum();
+    assert!((total_area - 4.0 * PI).abs() < 1e-9);
+    assert!(tessellation.cells.iter().all(|cell| cell.area > 0.0));
+    assert_eq!(tessellation.total_charge(), 12);
+    assert!(tessellation.coordination_counts()[&6] > 300);
+    //every particle is in the cells of its neighbors
+    for (i, cell) in tessellation.cells.iter().enumerate() {
+        assert!(cell.neighbors.iter().all(|&j| tessellation.cells[j].neighbors.contains(&i)));
+    }
+}